@@ -0,0 +1,76 @@
+//! Fan-out cost of one room broadcast, comparing per-session encoding with
+//! the shared buffer `ChatServer` uses.
+//!
+//! Run with `cargo bench --bench fanout`.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use rusty_chat::models::message::DisplayOverride;
+use rusty_chat::utils::types::ServerMessage;
+use rusty_chat::ws_outbox::{OutboundFrame, Outbox, SlowConsumerPolicy};
+use rusty_chat::ws_protocol::{Payload, WireCodec};
+
+const ROOM_SIZES: [usize; 3] = [100, 1_000, 5_000];
+
+fn room_message() -> ServerMessage {
+    ServerMessage::Text {
+        room_id: "5f0c6b5e-8c1e-4a53-9a57-3f4f0d1e2c3b".to_string(),
+        content: "The deploy finished, dashboards are green again. ".repeat(4),
+        user_id: "0b7e3c7a-1d2f-4e5a-8b6c-9d0e1f2a3b4c".to_string(),
+        seq: Some(42),
+        message_id: Some("9c1d2e3f-4a5b-4c6d-8e7f-0a1b2c3d4e5f".to_string()),
+        display: DisplayOverride::default(),
+        mentions: Vec::new(),
+    }
+}
+
+fn frame(payload: Payload) -> OutboundFrame {
+    OutboundFrame {
+        room_id: Some("5f0c6b5e-8c1e-4a53-9a57-3f4f0d1e2c3b".to_string()),
+        seq: Some(42),
+        message_id: None,
+        payload,
+        typing_user: None,
+    }
+}
+
+fn drain_all(outboxes: &[Outbox]) {
+    for outbox in outboxes {
+        black_box(outbox.drain());
+    }
+}
+
+fn fanout(c: &mut Criterion) {
+    let message = room_message();
+    let mut group = c.benchmark_group("room_fanout");
+
+    for size in ROOM_SIZES {
+        let outboxes: Vec<Outbox> = (0..size)
+            .map(|_| Outbox::new(256, SlowConsumerPolicy::DropOldest, WireCodec::Json))
+            .collect();
+        group.throughput(Throughput::Elements(size as u64));
+
+        group.bench_with_input(BenchmarkId::new("encode_per_session", size), &outboxes, |b, outboxes| {
+            b.iter(|| {
+                for outbox in outboxes {
+                    outbox.push(frame(WireCodec::Json.encode(&message).unwrap()));
+                }
+                drain_all(outboxes);
+            })
+        });
+
+        group.bench_with_input(BenchmarkId::new("encode_once_shared", size), &outboxes, |b, outboxes| {
+            b.iter(|| {
+                let payload = WireCodec::Json.encode(&message).unwrap();
+                for outbox in outboxes {
+                    outbox.push(frame(payload.clone()));
+                }
+                drain_all(outboxes);
+            })
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, fanout);
+criterion_main!(benches);
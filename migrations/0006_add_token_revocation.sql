@@ -0,0 +1,5 @@
+-- Tokens issued before this instant are rejected, set when a user revokes all sessions
+ALTER TABLE users ADD COLUMN IF NOT EXISTS tokens_revoked_at TIMESTAMP WITH TIME ZONE;
+
+-- Expiry of the token a WebSocket ticket was issued with, carried over to the session
+ALTER TABLE ws_tickets ADD COLUMN IF NOT EXISTS auth_expires_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW();
@@ -0,0 +1,31 @@
+ALTER TABLE rooms ADD COLUMN IF NOT EXISTS topic TEXT;
+
+-- Users who belong to a room, whether or not they are connected to it
+CREATE TABLE IF NOT EXISTS room_members (
+    room_id UUID NOT NULL REFERENCES rooms(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    muted BOOLEAN NOT NULL DEFAULT FALSE,
+    invited_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    joined_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    PRIMARY KEY (room_id, user_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_room_members_user_id ON room_members(user_id);
+
+-- Room creators are members of their rooms
+INSERT INTO room_members (room_id, user_id, joined_at)
+SELECT id, created_by, created_at FROM rooms
+ON CONFLICT DO NOTHING;
+
+-- Slash commands registered by bots, answered through a callback URL
+CREATE TABLE IF NOT EXISTS bot_commands (
+    id UUID PRIMARY KEY,
+    bot_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    name VARCHAR(64) UNIQUE NOT NULL,
+    description TEXT NOT NULL,
+    callback_url TEXT NOT NULL,
+    secret TEXT NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_bot_commands_bot_id ON bot_commands(bot_id);
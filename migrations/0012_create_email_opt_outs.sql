@@ -0,0 +1,8 @@
+-- Notification categories a user does not want emails for
+CREATE TABLE IF NOT EXISTS email_opt_outs (
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    -- A notification kind, e.g. 'mention'
+    category TEXT NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    PRIMARY KEY (user_id, category)
+);
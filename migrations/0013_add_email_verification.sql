@@ -0,0 +1,2 @@
+-- Set once the user followed the link emailed to their current address
+ALTER TABLE users ADD COLUMN IF NOT EXISTS email_verified_at TIMESTAMP WITH TIME ZONE;
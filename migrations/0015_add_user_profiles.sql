@@ -0,0 +1,11 @@
+-- Optional profile fields users edit themselves
+ALTER TABLE users ADD COLUMN IF NOT EXISTS display_name TEXT;
+ALTER TABLE users ADD COLUMN IF NOT EXISTS avatar_url TEXT;
+ALTER TABLE users ADD COLUMN IF NOT EXISTS bio TEXT;
+ALTER TABLE users ADD COLUMN IF NOT EXISTS pronouns TEXT;
+-- IANA time zone, e.g. 'Europe/Berlin'
+ALTER TABLE users ADD COLUMN IF NOT EXISTS timezone TEXT;
+-- BCP 47 language tag, e.g. 'en-GB'
+ALTER TABLE users ADD COLUMN IF NOT EXISTS locale TEXT;
+-- New address waiting for verification, replaces email once verified
+ALTER TABLE users ADD COLUMN IF NOT EXISTS pending_email TEXT;
@@ -0,0 +1,15 @@
+-- Moderator actions against room members: mute (can read, cannot post), kick and ban
+CREATE TABLE IF NOT EXISTS room_sanctions (
+    id UUID PRIMARY KEY,
+    room_id UUID NOT NULL REFERENCES rooms(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    kind TEXT NOT NULL CHECK (kind IN ('mute', 'kick', 'ban')),
+    reason TEXT,
+    issued_by UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    -- NULL means until lifted; a kick without a duration expires right away
+    expires_at TIMESTAMP WITH TIME ZONE,
+    lifted_at TIMESTAMP WITH TIME ZONE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_room_sanctions_room_user ON room_sanctions(room_id, user_id);
@@ -0,0 +1,33 @@
+-- Messages users reported, reviewed by the room's owner or an admin
+CREATE TABLE IF NOT EXISTS message_reports (
+    id UUID PRIMARY KEY,
+    -- NULL once the message is deleted, `content` keeps what was reported
+    message_id UUID REFERENCES messages(id) ON DELETE SET NULL,
+    room_id UUID NOT NULL REFERENCES rooms(id) ON DELETE CASCADE,
+    sender_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    content TEXT NOT NULL,
+    reporter_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    category TEXT NOT NULL CHECK (category IN ('spam', 'harassment', 'hate', 'violence', 'sexual', 'self_harm', 'other')),
+    details TEXT,
+    status TEXT NOT NULL DEFAULT 'open' CHECK (status IN ('open', 'claimed', 'resolved')),
+    claimed_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    claimed_at TIMESTAMP WITH TIME ZONE,
+    resolution TEXT CHECK (resolution IN ('dismissed', 'message_deleted', 'user_sanctioned')),
+    resolved_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    resolved_at TIMESTAMP WITH TIME ZONE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    UNIQUE (message_id, reporter_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_message_reports_room_status ON message_reports(room_id, status, created_at);
+
+-- Moderators' notes on a report
+CREATE TABLE IF NOT EXISTS report_notes (
+    id UUID PRIMARY KEY,
+    report_id UUID NOT NULL REFERENCES message_reports(id) ON DELETE CASCADE,
+    author_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    body TEXT NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_report_notes_report_id ON report_notes(report_id, created_at);
@@ -0,0 +1,17 @@
+-- Content policy applied to messages before they are stored and sent, global when room_id is NULL
+CREATE TABLE IF NOT EXISTS content_rules (
+    id UUID PRIMARY KEY,
+    room_id UUID REFERENCES rooms(id) ON DELETE CASCADE,
+    -- 'word', 'regex', 'link' (pattern is a domain) or 'max_length' (pattern is a character count)
+    kind TEXT NOT NULL CHECK (kind IN ('word', 'regex', 'link', 'max_length')),
+    pattern TEXT NOT NULL,
+    -- 'allow' is only used by link rules, turning the room's links into an allow list
+    action TEXT NOT NULL CHECK (action IN ('block', 'mask', 'flag', 'allow')),
+    created_by UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_content_rules_room_id ON content_rules(room_id);
+
+-- Messages flagged by a content rule are queued for review without a reporter
+ALTER TABLE message_reports ALTER COLUMN reporter_id DROP NOT NULL;
@@ -0,0 +1,6 @@
+-- Warnings are recorded as sanctions that are never in force
+ALTER TABLE room_sanctions DROP CONSTRAINT IF EXISTS room_sanctions_kind_check;
+ALTER TABLE room_sanctions ADD CONSTRAINT room_sanctions_kind_check CHECK (kind IN ('warn', 'mute', 'kick', 'ban'));
+
+-- Sanctions issued by spam detection have no moderator
+ALTER TABLE room_sanctions ALTER COLUMN issued_by DROP NOT NULL;
@@ -0,0 +1,6 @@
+-- Room messages from non-members of private rooms are refused, bots posting
+-- through incoming webhooks become members of the webhook's room
+INSERT INTO room_members (room_id, user_id, invited_by, joined_at)
+SELECT DISTINCT ON (w.room_id, w.bot_id) w.room_id, w.bot_id, w.created_by, NOW()
+FROM incoming_webhooks w
+ON CONFLICT (room_id, user_id) DO NOTHING;
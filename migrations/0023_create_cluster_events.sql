@@ -0,0 +1,8 @@
+-- Cluster events too large for a NOTIFY payload, notifications carry the row id instead
+CREATE TABLE IF NOT EXISTS cluster_events (
+    id UUID PRIMARY KEY,
+    payload TEXT NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_cluster_events_created_at ON cluster_events(created_at);
@@ -1,3 +1,6 @@
+use crate::services::mailer::MailTransport;
+use crate::services::spam::SpamResponse;
+use crate::ws_outbox::SlowConsumerPolicy;
 use config::{Config, ConfigError, Environment};
 use serde::Deserialize;
 use std::env;
@@ -17,10 +20,107 @@ pub struct ServerConfig {
     pub port: u16,
 }
 
+#[derive(Debug, Clone, Deserialize)]
+pub struct ClusterConfig {
+    pub enabled: bool,
+    pub channel: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct WebSocketConfig {
+    /// Frames buffered per session before the slow-consumer policy applies
+    pub outbound_capacity: usize,
+    pub slow_consumer_policy: SlowConsumerPolicy,
+    /// Number of `ChatServer` actors rooms are partitioned across
+    pub shards: usize,
+    /// Whether `?token=<jwt>` is still accepted; deprecated in favour of tickets
+    pub allow_query_token: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct WebhookConfig {
+    /// Attempts per delivery before it is marked failed
+    pub max_attempts: i32,
+    /// Delay before the first retry, doubled after every further attempt
+    pub backoff_base_secs: u64,
+    pub request_timeout_secs: u64,
+    /// How often the queue is checked for retries that became due
+    pub poll_interval_secs: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct AuthConfig {
+    /// Keep users who have not verified their email from posting and joining public rooms
+    pub require_verified_email: bool,
+    pub email_verification_ttl_secs: u64,
+    pub password_reset_ttl_secs: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ModerationConfig {
+    /// Longest message accepted, rooms may set a lower limit with a `max_length` rule
+    pub max_message_chars: usize,
+    /// How often content rules are reloaded, picking up changes made on other nodes
+    pub rules_refresh_secs: u64,
+    /// Spam detection looks at each user's messages of this many seconds
+    pub spam_window_secs: u64,
+    /// Identical messages allowed within the window, one more is spam
+    pub spam_max_repeats: usize,
+    /// Mentions allowed within the window
+    pub spam_max_mentions: usize,
+    /// Links allowed within the window from accounts younger than `spam_new_account_secs`
+    pub spam_max_new_account_links: usize,
+    pub spam_new_account_secs: u64,
+    pub spam_response: SpamResponse,
+    /// How long `spam_response = "mute"` mutes the sender in the room
+    pub spam_mute_secs: u64,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct MailConfig {
+    pub transport: MailTransport,
+    /// Sender address, e.g. `Rusty Chat <noreply@example.com>`
+    pub from: String,
+    /// Base URL of the web client, links in emails point there
+    pub app_url: String,
+    pub smtp_host: String,
+    pub smtp_port: u16,
+    /// Use STARTTLS; turn off only for local relays
+    pub smtp_tls: bool,
+    pub smtp_username: Option<String>,
+    pub smtp_password: Option<String>,
+    /// Where the `file` transport writes `.eml` files
+    pub spool_dir: String,
+    /// Notifications arriving within this long of the first one go out as one digest
+    pub digest_delay_secs: u64,
+}
+
+impl std::fmt::Debug for MailConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MailConfig")
+            .field("transport", &self.transport)
+            .field("from", &self.from)
+            .field("app_url", &self.app_url)
+            .field("smtp_host", &self.smtp_host)
+            .field("smtp_port", &self.smtp_port)
+            .field("smtp_tls", &self.smtp_tls)
+            .field("smtp_username", &self.smtp_username)
+            .field("spool_dir", &self.spool_dir)
+            .field("digest_delay_secs", &self.digest_delay_secs)
+            .finish_non_exhaustive()
+    }
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct AppConfig {
     pub database: DatabaseConfig,
     pub server: ServerConfig,
+    pub cluster: ClusterConfig,
+    pub websocket: WebSocketConfig,
+    pub webhooks: WebhookConfig,
+    pub auth: AuthConfig,
+    pub mail: MailConfig,
+    pub moderation: ModerationConfig,
     pub environment: String,
 }
 
@@ -43,6 +143,10 @@ impl AppConfig {
         let database_url = env::var(database_url_key)
             .map_err(|e| ConfigError::NotFound(format!("{}: {}", database_url_key, e)))?;
 
+        let default_shards = std::thread::available_parallelism()
+            .map(|n| n.get() as u64)
+            .unwrap_or(1);
+
         let cfg = Config::builder()
             .set_default("server.host", "127.0.0.1")?
             .set_default("server.port", 8080)?
@@ -50,6 +154,36 @@ impl AppConfig {
             .set_default("database.min_connections", 5)?
             .set_default("database.connection_timeout", 30)? // seconds
             .set_default("database.idle_timeout", 600)? // seconds
+            .set_default("cluster.enabled", false)?
+            .set_default("cluster.channel", "rusty_chat_events")?
+            .set_default("websocket.outbound_capacity", 256)?
+            .set_default("websocket.slow_consumer_policy", "drop_oldest")?
+            .set_default("websocket.shards", default_shards)?
+            .set_default("websocket.allow_query_token", true)?
+            .set_default("webhooks.max_attempts", 8)?
+            .set_default("webhooks.backoff_base_secs", 10)?
+            .set_default("webhooks.request_timeout_secs", 10)?
+            .set_default("webhooks.poll_interval_secs", 5)?
+            .set_default("auth.require_verified_email", false)?
+            .set_default("auth.email_verification_ttl_secs", 24 * 60 * 60)?
+            .set_default("auth.password_reset_ttl_secs", 60 * 60)?
+            .set_default("mail.transport", "disabled")?
+            .set_default("mail.from", "Rusty Chat <noreply@localhost>")?
+            .set_default("mail.app_url", "http://localhost:3000")?
+            .set_default("mail.smtp_host", "localhost")?
+            .set_default("mail.smtp_port", 587)?
+            .set_default("mail.smtp_tls", true)?
+            .set_default("mail.spool_dir", "mail")?
+            .set_default("mail.digest_delay_secs", 300)?
+            .set_default("moderation.max_message_chars", 4000)?
+            .set_default("moderation.rules_refresh_secs", 60)?
+            .set_default("moderation.spam_window_secs", 60)?
+            .set_default("moderation.spam_max_repeats", 2)?
+            .set_default("moderation.spam_max_mentions", 10)?
+            .set_default("moderation.spam_max_new_account_links", 2)?
+            .set_default("moderation.spam_new_account_secs", 24 * 60 * 60)?
+            .set_default("moderation.spam_response", "mute")?
+            .set_default("moderation.spam_mute_secs", 10 * 60)?
             .add_source(Environment::with_prefix("APP").separator("__"))
             .set_override("database.url", database_url)?
             .set_override("environment", app_environment)?;
@@ -64,6 +198,18 @@ impl AppConfig {
             ));
         }
 
+        if config.moderation.max_message_chars == 0 {
+            return Err(ConfigError::Message(
+                "moderation.max_message_chars must be at least 1".to_string(),
+            ));
+        }
+
+        if config.websocket.shards == 0 {
+            return Err(ConfigError::Message(
+                "websocket.shards must be at least 1".to_string(),
+            ));
+        }
+
         Ok(config)
     }
 }
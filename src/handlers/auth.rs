@@ -1,17 +1,37 @@
 use crate::{
+    config::settings::AppConfig,
     database::connection::DbPool,
+    middleware::auth::AuthenticatedUser,
     models::{
-        auth::{AuthResponse, LoginRequest, RegisterRequest, UserInfo},
+        auth::{
+            AuthResponse, ChangePasswordRequest, ForgotPasswordRequest, LoginRequest,
+            RegisterRequest, ResetPasswordRequest, UserInfo, VerifyEmailRequest,
+        },
+        password_reset::PasswordReset,
         user::{CreateUser, OnlineStatus, User},
     },
-    services::auth::AuthService,
-    utils::helpers::ApiResponse,
+    services::{auth::AuthService, email_templates, email_verification, mailer::Mailer},
+    utils::{
+        helpers::ApiResponse,
+        secrets::{generate_token, hash_token},
+    },
+    ws_router::ChatRouter,
+    ws_server::RevokeSessions,
 };
 use actix_web::{web, HttpResponse, Result};
+use std::sync::Arc;
+use std::time::Duration;
 use tracing::error;
+use uuid::Uuid;
+
+/// Prefix of password reset tokens
+const RESET_TOKEN_PREFIX: &str = "rcr_";
 
+/// Creates the account and emails a link verifying its address when mail is configured
 pub async fn register(
     pool: web::Data<DbPool>,
+    config: web::Data<AppConfig>,
+    mailer: web::Data<Option<Arc<dyn Mailer>>>,
     request: web::Json<RegisterRequest>,
 ) -> Result<HttpResponse> {
     let auth_service = AuthService::new().map_err(|e| {
@@ -37,16 +57,19 @@ pub async fn register(
         actix_web::error::ErrorInternalServerError("Failed to generate token")
     })?;
 
-    let user_info = UserInfo {
-        id: user.id,
-        full_name: user.full_name,
-        username: user.username,
-        email: user.email,
-    };
+    // Registration does not wait for the mail server
+    if let Some(mailer) = mailer.get_ref().clone() {
+        let email = email_verification::verification_email(&auth_service, &config, &user, &user.email);
+        tokio::spawn(async move {
+            if let Err(e) = mailer.send(&email).await {
+                error!("Failed to send verification email to {}: {}", email.to, e);
+            }
+        });
+    }
 
     let response = AuthResponse {
         token,
-        user: user_info,
+        user: UserInfo::from(user),
     };
     Ok(HttpResponse::Created().json(ApiResponse::success(response)))
 }
@@ -77,16 +100,243 @@ pub async fn login(
         actix_web::error::ErrorInternalServerError("Failed to generate token")
     })?;
 
-    let user_info = UserInfo {
-        id: user.id,
-        full_name: user.full_name,
-        username: user.username,
-        email: user.email,
+    let response = AuthResponse {
+        token,
+        user: UserInfo::from(user),
+    };
+    Ok(HttpResponse::Ok().json(ApiResponse::success(response)))
+}
+
+/// Invalidates every token of the current user and closes their live sessions
+pub async fn revoke(
+    pool: web::Data<DbPool>,
+    router: web::Data<ChatRouter>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse> {
+    sign_out_everywhere(&pool, &router, user.user_id).await.map_err(|e| {
+        error!("Failed to revoke tokens of user {}: {}", user.user_id, e);
+        actix_web::error::ErrorInternalServerError("Failed to revoke tokens")
+    })?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+async fn sign_out_everywhere(pool: &DbPool, router: &ChatRouter, user_id: Uuid) -> Result<(), sqlx::Error> {
+    User::revoke_tokens(pool, user_id).await?;
+
+    let user_id = user_id.to_string();
+    router.user(&user_id).do_send(RevokeSessions { user_id });
+    Ok(())
+}
+
+/// Verifies the address a verification token was emailed to, completing an email
+/// change when it was the user's pending address
+pub async fn verify_email(
+    pool: web::Data<DbPool>,
+    auth_service: web::Data<AuthService>,
+    request: web::Json<VerifyEmailRequest>,
+) -> Result<HttpResponse> {
+    let (user, address) = auth_service
+        .redeem_email_verification_token(&pool, &request.token)
+        .await
+        .map_err(|e| {
+            error!("Failed to check email verification token: {}", e);
+            actix_web::error::ErrorInternalServerError("Failed to verify email")
+        })?
+        .ok_or_else(|| actix_web::error::ErrorBadRequest("Invalid or expired token"))?;
+
+    let verified = if address == user.email {
+        User::mark_email_verified(&pool, user.id, &address).await
+    } else {
+        User::confirm_email_change(&pool, user.id, &address).await
+    }
+    .map_err(|e| match e {
+        sqlx::Error::Database(db) if db.is_unique_violation() => {
+            actix_web::error::ErrorConflict("Email is already taken")
+        }
+        e => {
+            error!("Failed to mark email of user {} verified: {}", user.id, e);
+            actix_web::error::ErrorInternalServerError("Failed to verify email")
+        }
+    })?;
+    if !verified {
+        return Err(actix_web::error::ErrorBadRequest("Invalid or expired token"));
+    }
+
+    let user = User::find_by_id(&pool, user.id)
+        .await
+        .map_err(|e| {
+            error!("Failed to fetch user {}: {}", user.id, e);
+            actix_web::error::ErrorInternalServerError("Failed to verify email")
+        })?
+        .ok_or_else(|| actix_web::error::ErrorBadRequest("Invalid or expired token"))?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(UserInfo::from(user))))
+}
+
+/// Emails the current user a new verification link, for their pending address if
+/// they are changing it
+pub async fn resend_verification(
+    pool: web::Data<DbPool>,
+    config: web::Data<AppConfig>,
+    auth_service: web::Data<AuthService>,
+    mailer: web::Data<Option<Arc<dyn Mailer>>>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse> {
+    let Some(mailer) = mailer.get_ref() else {
+        return Err(actix_web::error::ErrorServiceUnavailable("Email is not configured"));
+    };
+
+    let user = User::find_by_id(&pool, user.user_id)
+        .await
+        .map_err(|e| {
+            error!("Failed to fetch user {}: {}", user.user_id, e);
+            actix_web::error::ErrorInternalServerError("Failed to fetch user")
+        })?
+        .ok_or_else(|| actix_web::error::ErrorNotFound("User not found"))?;
+    let address = match &user.pending_email {
+        Some(pending) => pending,
+        None if user.email_verified_at.is_some() => {
+            return Err(actix_web::error::ErrorConflict("Email is already verified"));
+        }
+        None => &user.email,
     };
 
+    let email = email_verification::verification_email(&auth_service, &config, &user, address);
+    mailer.send(&email).await.map_err(|e| {
+        error!("Failed to send verification email to {}: {}", email.to, e);
+        actix_web::error::ErrorInternalServerError("Failed to send verification email")
+    })?;
+
+    Ok(HttpResponse::Accepted().finish())
+}
+
+/// Emails a single-use reset link if the address belongs to an account. Answers the
+/// same either way so the endpoint does not reveal which addresses are registered.
+pub async fn forgot_password(
+    pool: web::Data<DbPool>,
+    config: web::Data<AppConfig>,
+    mailer: web::Data<Option<Arc<dyn Mailer>>>,
+    request: web::Json<ForgotPasswordRequest>,
+) -> Result<HttpResponse> {
+    let Some(mailer) = mailer.get_ref().clone() else {
+        return Err(actix_web::error::ErrorServiceUnavailable("Email is not configured"));
+    };
+
+    let user = User::find_by_email(&pool, &request.email).await.map_err(|e| {
+        error!("Failed to fetch user by email: {}", e);
+        actix_web::error::ErrorInternalServerError("Failed to request password reset")
+    })?;
+    let Some(user) = user.filter(|user| !user.is_bot) else {
+        return Ok(HttpResponse::Accepted().finish());
+    };
+
+    let token = generate_token(RESET_TOKEN_PREFIX);
+    let ttl_secs = config.auth.password_reset_ttl_secs;
+    PasswordReset::create(
+        &pool,
+        user.id,
+        &hash_token(&token),
+        chrono::Duration::seconds(ttl_secs as i64),
+    )
+    .await
+    .map_err(|e| {
+        error!("Failed to create password reset for user {}: {}", user.id, e);
+        actix_web::error::ErrorInternalServerError("Failed to request password reset")
+    })?;
+
+    let link = format!(
+        "{}/reset-password?token={}",
+        config.mail.app_url.trim_end_matches('/'),
+        token
+    );
+    let email = email_templates::password_reset(
+        &user.email,
+        &user.username,
+        &link,
+        Duration::from_secs(ttl_secs),
+    );
+    // Not waiting for the mail server keeps response times the same for unknown addresses
+    tokio::spawn(async move {
+        if let Err(e) = mailer.send(&email).await {
+            error!("Failed to send password reset email to {}: {}", email.to, e);
+        }
+    });
+
+    Ok(HttpResponse::Accepted().finish())
+}
+
+/// Sets a new password with a reset token, signing the user out everywhere
+pub async fn reset_password(
+    pool: web::Data<DbPool>,
+    router: web::Data<ChatRouter>,
+    request: web::Json<ResetPasswordRequest>,
+) -> Result<HttpResponse> {
+    let reset = PasswordReset::redeem(&pool, &hash_token(&request.token))
+        .await
+        .map_err(|e| {
+            error!("Failed to redeem password reset: {}", e);
+            actix_web::error::ErrorInternalServerError("Failed to reset password")
+        })?
+        .ok_or_else(|| actix_web::error::ErrorBadRequest("Invalid or expired token"))?;
+
+    set_password(&pool, &router, reset.user_id, &request.password)
+        .await
+        .map_err(|e| {
+            error!("Failed to reset password of user {}: {}", reset.user_id, e);
+            actix_web::error::ErrorInternalServerError("Failed to reset password")
+        })?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Changes the current user's password. Every token is revoked, including the one
+/// used here, so a fresh token is returned.
+pub async fn change_password(
+    pool: web::Data<DbPool>,
+    router: web::Data<ChatRouter>,
+    auth_service: web::Data<AuthService>,
+    user: AuthenticatedUser,
+    request: web::Json<ChangePasswordRequest>,
+) -> Result<HttpResponse> {
+    let account = User::find_by_id(&pool, user.user_id)
+        .await
+        .map_err(|e| {
+            error!("Failed to fetch user {}: {}", user.user_id, e);
+            actix_web::error::ErrorInternalServerError("Failed to fetch user")
+        })?
+        .ok_or_else(|| actix_web::error::ErrorNotFound("User not found"))?;
+    if !account.verify_password(&request.current_password).unwrap_or(false) {
+        return Err(actix_web::error::ErrorForbidden("Current password is incorrect"));
+    }
+
+    set_password(&pool, &router, account.id, &request.new_password)
+        .await
+        .map_err(|e| {
+            error!("Failed to change password of user {}: {}", account.id, e);
+            actix_web::error::ErrorInternalServerError("Failed to change password")
+        })?;
+
+    let token = auth_service.generate_token(&account).map_err(|e| {
+        error!("Failed to generate token: {}", e);
+        actix_web::error::ErrorInternalServerError("Failed to generate token")
+    })?;
+
     let response = AuthResponse {
         token,
-        user: user_info,
+        user: UserInfo::from(account),
     };
     Ok(HttpResponse::Ok().json(ApiResponse::success(response)))
 }
+
+/// Stores a new password, drops pending resets and signs the user out everywhere
+async fn set_password(
+    pool: &DbPool,
+    router: &ChatRouter,
+    user_id: Uuid,
+    password: &str,
+) -> Result<(), sqlx::Error> {
+    User::update_password(pool, user_id, password).await?;
+    PasswordReset::delete_by_user_id(pool, user_id).await?;
+    sign_out_everywhere(pool, router, user_id).await
+}
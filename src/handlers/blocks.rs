@@ -0,0 +1,74 @@
+use crate::{
+    database::connection::DbPool,
+    middleware::auth::AuthenticatedUser,
+    models::{user::User, user_block::UserBlock},
+    requests::block_requests::BlockUserRequest,
+    services::blocks,
+    utils::helpers::ApiResponse,
+    ws_router::ChatRouter,
+};
+use actix_web::{web, HttpResponse, Result};
+use tracing::error;
+use uuid::Uuid;
+
+pub async fn get_blocks(pool: web::Data<DbPool>, user: AuthenticatedUser) -> Result<HttpResponse> {
+    let blocks = UserBlock::find_by_blocker_id(&pool, user.user_id)
+        .await
+        .map_err(|e| {
+            error!("Failed to fetch blocks of user {}: {}", user.user_id, e);
+            actix_web::error::ErrorInternalServerError("Failed to fetch blocks")
+        })?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(blocks)))
+}
+
+/// Blocks a user, or changes whether their room messages are hidden
+pub async fn block_user(
+    pool: web::Data<DbPool>,
+    router: web::Data<ChatRouter>,
+    user: AuthenticatedUser,
+    blocked_id: web::Path<Uuid>,
+    request: Option<web::Json<BlockUserRequest>>,
+) -> Result<HttpResponse> {
+    let blocked_id = blocked_id.into_inner();
+    if blocked_id == user.user_id {
+        return Err(actix_web::error::ErrorBadRequest("You cannot block yourself"));
+    }
+    User::find_by_id(&pool, blocked_id)
+        .await
+        .map_err(|e| {
+            error!("Failed to fetch user {}: {}", blocked_id, e);
+            actix_web::error::ErrorInternalServerError("Failed to block user")
+        })?
+        .ok_or_else(|| actix_web::error::ErrorNotFound("User not found"))?;
+
+    let hide_messages = request.and_then(|r| r.hide_messages).unwrap_or(true);
+    let block = blocks::block(&pool, &router, user.user_id, blocked_id, hide_messages)
+        .await
+        .map_err(|e| {
+            error!("Failed to block user {} for {}: {}", blocked_id, user.user_id, e);
+            actix_web::error::ErrorInternalServerError("Failed to block user")
+        })?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(block)))
+}
+
+pub async fn unblock_user(
+    pool: web::Data<DbPool>,
+    router: web::Data<ChatRouter>,
+    user: AuthenticatedUser,
+    blocked_id: web::Path<Uuid>,
+) -> Result<HttpResponse> {
+    let blocked_id = blocked_id.into_inner();
+    let removed = blocks::unblock(&pool, &router, user.user_id, blocked_id)
+        .await
+        .map_err(|e| {
+            error!("Failed to unblock user {} for {}: {}", blocked_id, user.user_id, e);
+            actix_web::error::ErrorInternalServerError("Failed to unblock user")
+        })?;
+    if !removed {
+        return Err(actix_web::error::ErrorNotFound("User is not blocked"));
+    }
+
+    Ok(HttpResponse::NoContent().finish())
+}
@@ -0,0 +1,203 @@
+use crate::{
+    database::connection::DbPool,
+    middleware::auth::AuthenticatedUser,
+    models::{
+        api_token::ApiToken,
+        bot_command::{BotCommand, CreateBotCommand},
+        user::{CreateBot, User},
+    },
+    requests::bot_requests::{CreateApiTokenRequest, CreateBotCommandRequest, CreateBotRequest},
+    services::{commands, outbound},
+    utils::helpers::ApiResponse,
+};
+use actix_web::{web, HttpResponse, Result};
+use serde::Serialize;
+use tracing::error;
+use uuid::Uuid;
+
+/// Returned once on creation, the only time the token is shown
+#[derive(Debug, Serialize)]
+pub struct CreatedApiToken {
+    #[serde(flatten)]
+    pub api_token: ApiToken,
+    pub token: String,
+}
+
+/// Returned once on creation, the only time the signing secret is shown
+#[derive(Debug, Serialize)]
+pub struct CreatedBotCommand {
+    #[serde(flatten)]
+    pub command: BotCommand,
+    pub secret: String,
+}
+
+/// The bot with the given id, if it is owned by `user`
+pub async fn find_owned_bot(pool: &DbPool, bot_id: Uuid, user: &AuthenticatedUser) -> Result<User> {
+    let bot = User::find_by_id(pool, bot_id).await.map_err(|e| {
+        error!("Failed to fetch bot {}: {}", bot_id, e);
+        actix_web::error::ErrorInternalServerError("Failed to fetch bot")
+    })?;
+
+    bot.filter(|bot| bot.is_bot && bot.owner_id == Some(user.user_id))
+        .ok_or_else(|| actix_web::error::ErrorNotFound("Bot not found"))
+}
+
+pub async fn create_bot(
+    pool: web::Data<DbPool>,
+    bot_data: web::Json<CreateBotRequest>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse> {
+    let create_data = CreateBot {
+        full_name: bot_data.full_name.clone(),
+        username: bot_data.username.clone(),
+        owner_id: user.user_id,
+    };
+
+    let bot = User::create_bot(&pool, create_data).await.map_err(|e| {
+        error!("Failed to create bot: {}", e);
+        actix_web::error::ErrorInternalServerError("Failed to create bot")
+    })?;
+
+    Ok(HttpResponse::Created().json(ApiResponse::success(bot)))
+}
+
+pub async fn get_bots(pool: web::Data<DbPool>, user: AuthenticatedUser) -> Result<HttpResponse> {
+    let bots = User::find_bots_by_owner(&pool, user.user_id).await.map_err(|e| {
+        error!("Failed to fetch bots of user {}: {}", user.user_id, e);
+        actix_web::error::ErrorInternalServerError("Failed to fetch bots")
+    })?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(bots)))
+}
+
+pub async fn create_token(
+    pool: web::Data<DbPool>,
+    bot_id: web::Path<Uuid>,
+    token_data: web::Json<CreateApiTokenRequest>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse> {
+    let bot = find_owned_bot(&pool, bot_id.into_inner(), &user).await?;
+
+    let (api_token, token) = ApiToken::create(&pool, bot.id, token_data.name.clone())
+        .await
+        .map_err(|e| {
+            error!("Failed to create API token for bot {}: {}", bot.id, e);
+            actix_web::error::ErrorInternalServerError("Failed to create token")
+        })?;
+
+    Ok(HttpResponse::Created().json(ApiResponse::success(CreatedApiToken { api_token, token })))
+}
+
+pub async fn get_tokens(
+    pool: web::Data<DbPool>,
+    bot_id: web::Path<Uuid>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse> {
+    let bot = find_owned_bot(&pool, bot_id.into_inner(), &user).await?;
+
+    let tokens = ApiToken::find_by_user_id(&pool, bot.id).await.map_err(|e| {
+        error!("Failed to fetch API tokens of bot {}: {}", bot.id, e);
+        actix_web::error::ErrorInternalServerError("Failed to fetch tokens")
+    })?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(tokens)))
+}
+
+pub async fn delete_token(
+    pool: web::Data<DbPool>,
+    path: web::Path<(Uuid, Uuid)>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse> {
+    let (bot_id, token_id) = path.into_inner();
+    let bot = find_owned_bot(&pool, bot_id, &user).await?;
+
+    let deleted = ApiToken::delete(&pool, token_id, bot.id).await.map_err(|e| {
+        error!("Failed to delete API token {}: {}", token_id, e);
+        actix_web::error::ErrorInternalServerError("Failed to delete token")
+    })?;
+
+    if !deleted {
+        return Err(actix_web::error::ErrorNotFound("Token not found"));
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
+
+pub async fn create_command(
+    pool: web::Data<DbPool>,
+    bot_id: web::Path<Uuid>,
+    command_data: web::Json<CreateBotCommandRequest>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse> {
+    let bot = find_owned_bot(&pool, bot_id.into_inner(), &user).await?;
+    let command_data = command_data.into_inner();
+
+    let name = command_data.name.trim_start_matches('/').to_lowercase();
+    let valid_name = !name.is_empty()
+        && name.len() <= 64
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid_name {
+        return Err(actix_web::error::ErrorBadRequest(
+            "Command names are up to 64 letters, digits, dashes or underscores",
+        ));
+    }
+    if commands::registry().is_registered(&name) {
+        return Err(actix_web::error::ErrorConflict("A built-in command has this name"));
+    }
+    if let Err(reason) = outbound::check_url(&command_data.callback_url).await {
+        return Err(actix_web::error::ErrorBadRequest(format!("Callback url {}", reason)));
+    }
+
+    let create_data = CreateBotCommand {
+        bot_id: bot.id,
+        name,
+        description: command_data.description,
+        callback_url: command_data.callback_url,
+    };
+
+    let command = BotCommand::create(&pool, create_data).await.map_err(|e| match e {
+        sqlx::Error::Database(db) if db.is_unique_violation() => {
+            actix_web::error::ErrorConflict("A command with this name already exists")
+        }
+        e => {
+            error!("Failed to create command for bot {}: {}", bot.id, e);
+            actix_web::error::ErrorInternalServerError("Failed to create command")
+        }
+    })?;
+
+    let secret = command.secret.clone();
+    Ok(HttpResponse::Created().json(ApiResponse::success(CreatedBotCommand { command, secret })))
+}
+
+pub async fn get_commands(
+    pool: web::Data<DbPool>,
+    bot_id: web::Path<Uuid>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse> {
+    let bot = find_owned_bot(&pool, bot_id.into_inner(), &user).await?;
+
+    let commands = BotCommand::find_by_bot_id(&pool, bot.id).await.map_err(|e| {
+        error!("Failed to fetch commands of bot {}: {}", bot.id, e);
+        actix_web::error::ErrorInternalServerError("Failed to fetch commands")
+    })?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(commands)))
+}
+
+pub async fn delete_command(
+    pool: web::Data<DbPool>,
+    path: web::Path<(Uuid, Uuid)>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse> {
+    let (bot_id, command_id) = path.into_inner();
+    let bot = find_owned_bot(&pool, bot_id, &user).await?;
+
+    let deleted = BotCommand::delete(&pool, command_id, bot.id).await.map_err(|e| {
+        error!("Failed to delete command {}: {}", command_id, e);
+        actix_web::error::ErrorInternalServerError("Failed to delete command")
+    })?;
+
+    if !deleted {
+        return Err(actix_web::error::ErrorNotFound("Command not found"));
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
@@ -0,0 +1,152 @@
+use crate::{
+    config::settings::AppConfig,
+    database::connection::DbPool,
+    handlers::rooms::find_owned_room,
+    middleware::auth::AuthenticatedUser,
+    models::content_rule::{ContentRule, CreateContentRule},
+    requests::content_rule_requests::CreateContentRuleRequest,
+    services::content_filter,
+    utils::helpers::ApiResponse,
+    ws_router::ChatRouter,
+};
+use actix_web::{web, HttpResponse, Result};
+use tracing::error;
+use uuid::Uuid;
+
+/// Room rules are managed by the room owner, global ones by admins
+async fn ensure_can_manage(pool: &DbPool, room_id: Option<Uuid>, user: &AuthenticatedUser) -> Result<()> {
+    if let Some(room_id) = room_id {
+        find_owned_room(pool, room_id, user, "Only the room owner can manage its content rules").await?;
+        return Ok(());
+    }
+
+    if !user.is_admin(pool).await? {
+        return Err(actix_web::error::ErrorForbidden("Only admins can manage global content rules"));
+    }
+    Ok(())
+}
+
+/// Applies a rule change to the in-memory policy right away
+async fn reload_rules(pool: &DbPool, config: &AppConfig, router: &ChatRouter) -> Result<()> {
+    router
+        .content_filter()
+        .reload(pool, config.moderation.max_message_chars)
+        .await
+        .map_err(|e| {
+            error!("Failed to reload content rules: {}", e);
+            actix_web::error::ErrorInternalServerError("Failed to apply content rules")
+        })
+}
+
+async fn create_rule(
+    pool: &DbPool,
+    config: &AppConfig,
+    router: &ChatRouter,
+    room_id: Option<Uuid>,
+    request: CreateContentRuleRequest,
+    user: &AuthenticatedUser,
+) -> Result<HttpResponse> {
+    ensure_can_manage(pool, room_id, user).await?;
+    let pattern = request.pattern.trim().to_string();
+    content_filter::validate(request.kind, &pattern, request.action).map_err(actix_web::error::ErrorBadRequest)?;
+
+    let rule = CreateContentRule {
+        room_id,
+        kind: request.kind,
+        pattern,
+        action: request.action,
+        created_by: user.user_id,
+    };
+    let rule = ContentRule::create(pool, rule).await.map_err(|e| {
+        error!("Failed to create content rule: {}", e);
+        actix_web::error::ErrorInternalServerError("Failed to create content rule")
+    })?;
+    reload_rules(pool, config, router).await?;
+
+    Ok(HttpResponse::Created().json(ApiResponse::success(rule)))
+}
+
+async fn get_rules(pool: &DbPool, room_id: Option<Uuid>, user: &AuthenticatedUser) -> Result<HttpResponse> {
+    ensure_can_manage(pool, room_id, user).await?;
+    let rules = ContentRule::find_by_room_id(pool, room_id).await.map_err(|e| {
+        error!("Failed to fetch content rules: {}", e);
+        actix_web::error::ErrorInternalServerError("Failed to fetch content rules")
+    })?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(rules)))
+}
+
+async fn delete_rule(
+    pool: &DbPool,
+    config: &AppConfig,
+    router: &ChatRouter,
+    room_id: Option<Uuid>,
+    rule_id: Uuid,
+    user: &AuthenticatedUser,
+) -> Result<HttpResponse> {
+    ensure_can_manage(pool, room_id, user).await?;
+    let deleted = ContentRule::delete(pool, rule_id, room_id).await.map_err(|e| {
+        error!("Failed to delete content rule {}: {}", rule_id, e);
+        actix_web::error::ErrorInternalServerError("Failed to delete content rule")
+    })?;
+    if !deleted {
+        return Err(actix_web::error::ErrorNotFound("Content rule not found"));
+    }
+    reload_rules(pool, config, router).await?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+pub async fn create_global_rule(
+    pool: web::Data<DbPool>,
+    config: web::Data<AppConfig>,
+    router: web::Data<ChatRouter>,
+    request: web::Json<CreateContentRuleRequest>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse> {
+    create_rule(&pool, &config, &router, None, request.into_inner(), &user).await
+}
+
+pub async fn get_global_rules(pool: web::Data<DbPool>, user: AuthenticatedUser) -> Result<HttpResponse> {
+    get_rules(&pool, None, &user).await
+}
+
+pub async fn delete_global_rule(
+    pool: web::Data<DbPool>,
+    config: web::Data<AppConfig>,
+    router: web::Data<ChatRouter>,
+    rule_id: web::Path<Uuid>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse> {
+    delete_rule(&pool, &config, &router, None, rule_id.into_inner(), &user).await
+}
+
+pub async fn create_room_rule(
+    pool: web::Data<DbPool>,
+    config: web::Data<AppConfig>,
+    router: web::Data<ChatRouter>,
+    room_id: web::Path<Uuid>,
+    request: web::Json<CreateContentRuleRequest>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse> {
+    create_rule(&pool, &config, &router, Some(room_id.into_inner()), request.into_inner(), &user).await
+}
+
+pub async fn get_room_rules(
+    pool: web::Data<DbPool>,
+    room_id: web::Path<Uuid>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse> {
+    get_rules(&pool, Some(room_id.into_inner()), &user).await
+}
+
+pub async fn delete_room_rule(
+    pool: web::Data<DbPool>,
+    config: web::Data<AppConfig>,
+    router: web::Data<ChatRouter>,
+    path: web::Path<(Uuid, Uuid)>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse> {
+    let (room_id, rule_id) = path.into_inner();
+    delete_rule(&pool, &config, &router, Some(room_id), rule_id, &user).await
+}
@@ -0,0 +1,381 @@
+use crate::{
+    config::settings::AppConfig,
+    database::connection::DbPool,
+    middleware::auth::{self, AuthenticatedUser},
+    models::{message::DisplayOverride, user::User, ws_ticket::WsTicket},
+    services::{
+        email_verification::{self, VERIFY_FIRST},
+        messaging::{self, PostError},
+        moderation,
+    },
+    stream_session::{PollRegistry, StreamEvent, StreamSubscription},
+    utils::{helpers::ApiResponse, types::ClientMessage},
+    ws_router::ChatRouter,
+    ws_server::TypingIndicator,
+};
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tracing::error;
+use uuid::Uuid;
+
+/// Comment sent on idle SSE streams so proxies keep the connection open
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+/// How long a poll waits for events when the client does not say
+const DEFAULT_POLL_TIMEOUT_SECS: u64 = 25;
+const MAX_POLL_TIMEOUT_SECS: u64 = 60;
+/// Long-poll sessions not polled for this long are closed
+const POLL_SESSION_IDLE: Duration = Duration::from_secs(90);
+
+#[derive(Debug, Deserialize)]
+pub struct StreamQuery {
+    /// Comma separated room ids
+    pub rooms: String,
+    /// Message id to resume after, for clients that cannot set `Last-Event-ID`
+    pub last_event_id: Option<Uuid>,
+    /// Issued by `POST /ws/ticket`, for `EventSource` which cannot send headers
+    pub ticket: Option<Uuid>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PollQuery {
+    pub rooms: Option<String>,
+    /// Returned by the first poll, identifies the subscription on later polls
+    pub session: Option<Uuid>,
+    /// Message id to resume after when starting a new session
+    pub cursor: Option<Uuid>,
+    /// Seconds to wait for events
+    pub timeout: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SendQuery {
+    pub room_id: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PollEvent {
+    pub id: Option<Uuid>,
+    pub data: serde_json::Value,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PollResponse {
+    pub session: Uuid,
+    pub events: Vec<PollEvent>,
+}
+
+fn room_list(rooms: &str) -> Result<Vec<String>> {
+    let rooms: Vec<String> = rooms
+        .split(',')
+        .map(str::trim)
+        .filter(|room| !room.is_empty())
+        .map(str::to_string)
+        .collect();
+    if rooms.is_empty() {
+        return Err(actix_web::error::ErrorBadRequest("No rooms given"));
+    }
+    Ok(rooms)
+}
+
+/// Refuses rooms the user may not join: private rooms they are not a member of, and rooms
+/// they are kept out of for lack of a verified email or because of a kick or ban
+async fn check_rooms(pool: &DbPool, config: &AppConfig, user_id: &str, rooms: &[String]) -> Result<()> {
+    for room_id in rooms {
+        let refusal = moderation::privacy_refusal(pool, user_id, room_id).await.map_err(|e| {
+            error!("Failed to check membership of user {} in room {}: {}", user_id, room_id, e);
+            actix_web::error::ErrorInternalServerError("Failed to join room")
+        })?;
+        if let Some(refusal) = refusal {
+            return Err(actix_web::error::ErrorForbidden(refusal));
+        }
+        let allowed = email_verification::may_join(pool, config.auth.require_verified_email, user_id, room_id)
+            .await
+            .map_err(|e| {
+                error!("Failed to check whether user {} may join room {}: {}", user_id, room_id, e);
+                actix_web::error::ErrorInternalServerError("Failed to join room")
+            })?;
+        if !allowed {
+            return Err(actix_web::error::ErrorForbidden(VERIFY_FIRST));
+        }
+        let refusal = moderation::join_refusal(pool, user_id, room_id).await.map_err(|e| {
+            error!("Failed to check sanctions of user {} in room {}: {}", user_id, room_id, e);
+            actix_web::error::ErrorInternalServerError("Failed to join room")
+        })?;
+        if let Some(refusal) = refusal {
+            return Err(actix_web::error::ErrorForbidden(refusal));
+        }
+    }
+    Ok(())
+}
+
+fn format_sse(events: Vec<StreamEvent>) -> web::Bytes {
+    if events.is_empty() {
+        return web::Bytes::from_static(b": keep-alive\n\n");
+    }
+    let mut body = String::new();
+    for event in events {
+        if let Some(id) = event.id {
+            body.push_str(&format!("id: {}\n", id));
+        }
+        body.push_str(&format!("data: {}\n\n", event.data));
+    }
+    web::Bytes::from(body)
+}
+
+/// Who opens a stream and when their token expires, from a ticket or the bearer token.
+/// A ticket bound to a room only opens a stream of that room.
+async fn stream_user(req: &HttpRequest, pool: &DbPool, ticket: Option<Uuid>, rooms: &[String]) -> Result<(Uuid, i64)> {
+    let Some(ticket_id) = ticket else {
+        let token = req
+            .headers()
+            .get("Authorization")
+            .and_then(|h| h.to_str().ok())
+            .and_then(|h| h.strip_prefix("Bearer "))
+            .ok_or_else(|| actix_web::error::ErrorUnauthorized("Missing or invalid authorization header"))?;
+        let user = auth::authenticate(pool, token).await?;
+        return Ok((user.user_id, user.expires_at));
+    };
+
+    let ticket = WsTicket::redeem(pool, ticket_id)
+        .await
+        .map_err(|e| {
+            error!("Failed to redeem ticket: {}", e);
+            actix_web::error::ErrorInternalServerError("Failed to redeem ticket")
+        })?
+        .ok_or_else(|| actix_web::error::ErrorUnauthorized("Invalid or expired ticket"))?;
+    if let Some(ticket_room) = &ticket.room_id {
+        if rooms.iter().any(|room| room != ticket_room) {
+            return Err(actix_web::error::ErrorForbidden("Ticket is not valid for these rooms"));
+        }
+    }
+    let revoked = User::is_token_revoked(pool, ticket.user_id, ticket.created_at)
+        .await
+        .map_err(|e| {
+            error!("Failed to check token revocation: {}", e);
+            actix_web::error::ErrorInternalServerError("Failed to redeem ticket")
+        })?;
+    if revoked {
+        return Err(actix_web::error::ErrorUnauthorized("Invalid or expired ticket"));
+    }
+    Ok((ticket.user_id, ticket.auth_expires_at.timestamp()))
+}
+
+/// Server-Sent Events stream of the given rooms, resuming after `Last-Event-ID` if set.
+/// The stream ends when the token it was opened with expires or is revoked.
+pub async fn stream(
+    req: HttpRequest,
+    query: web::Query<StreamQuery>,
+    pool: web::Data<DbPool>,
+    router: web::Data<ChatRouter>,
+    config: web::Data<AppConfig>,
+) -> Result<HttpResponse> {
+    let rooms = room_list(&query.rooms)?;
+    let (user_id, expires_at) = stream_user(&req, &pool, query.ticket, &rooms).await?;
+    check_rooms(&pool, &config, &user_id.to_string(), &rooms).await?;
+    let resume_after = req
+        .headers()
+        .get("Last-Event-ID")
+        .and_then(|h| h.to_str().ok())
+        .and_then(|id| Uuid::parse_str(id).ok())
+        .or(query.last_event_id);
+
+    let subscription = StreamSubscription::open(
+        user_id.to_string(),
+        rooms,
+        resume_after,
+        router.get_ref().clone(),
+        &pool,
+        &config.websocket,
+        None,
+    )
+    .await
+    .map_err(|e| {
+        error!("Failed to open event stream: {}", e);
+        actix_web::error::ErrorInternalServerError("Failed to open event stream")
+    })?;
+    subscription.expire_at(expires_at);
+
+    // The subscription is dropped with the stream, which ends the session
+    let events = futures_util::stream::unfold(subscription, |mut subscription| async move {
+        let events = subscription.next_events(KEEP_ALIVE_INTERVAL).await?;
+        Some((Ok::<_, actix_web::Error>(format_sse(events)), subscription))
+    });
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        .insert_header(("X-Accel-Buffering", "no"))
+        .streaming(events))
+}
+
+/// Long-poll for events. The first poll opens a session that later polls pass back.
+pub async fn poll(
+    user: AuthenticatedUser,
+    query: web::Query<PollQuery>,
+    pool: web::Data<DbPool>,
+    router: web::Data<ChatRouter>,
+    config: web::Data<AppConfig>,
+    registry: web::Data<PollRegistry>,
+) -> Result<HttpResponse> {
+    let wait = Duration::from_secs(
+        query
+            .timeout
+            .unwrap_or(DEFAULT_POLL_TIMEOUT_SECS)
+            .min(MAX_POLL_TIMEOUT_SECS),
+    );
+    let user_id = user.user_id.to_string();
+
+    let subscription = match query.session {
+        Some(session_id) => registry
+            .get(session_id)
+            .ok_or_else(|| actix_web::error::ErrorGone("Poll session expired"))?,
+        None => {
+            let rooms = room_list(query.rooms.as_deref().unwrap_or_default())?;
+            check_rooms(&pool, &config, &user_id, &rooms).await?;
+            let subscription = StreamSubscription::open(
+                user_id.clone(),
+                rooms,
+                query.cursor,
+                router.get_ref().clone(),
+                &pool,
+                &config.websocket,
+                Some(POLL_SESSION_IDLE),
+            )
+            .await
+            .map_err(|e| {
+                error!("Failed to open poll session: {}", e);
+                actix_web::error::ErrorInternalServerError("Failed to open poll session")
+            })?;
+            registry.insert(subscription)
+        }
+    };
+
+    let mut subscription = subscription
+        .try_lock()
+        .map_err(|_| actix_web::error::ErrorConflict("Poll already in progress"))?;
+    if subscription.user_id != user_id {
+        return Err(actix_web::error::ErrorNotFound("Poll session not found"));
+    }
+    subscription.touch();
+
+    let Some(events) = subscription.next_events(wait).await else {
+        registry.remove(subscription.id);
+        return Err(actix_web::error::ErrorGone("Poll session expired"));
+    };
+    subscription.touch();
+
+    let events = events
+        .into_iter()
+        .filter_map(|event| {
+            serde_json::from_str(&event.data)
+                .ok()
+                .map(|data| PollEvent { id: event.id, data })
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(PollResponse {
+        session: subscription.id,
+        events,
+    })))
+}
+
+/// Sends a client message to a room for clients without a WebSocket
+pub async fn send(
+    user: AuthenticatedUser,
+    query: web::Query<SendQuery>,
+    message: web::Json<ClientMessage>,
+    pool: web::Data<DbPool>,
+    router: web::Data<ChatRouter>,
+    config: web::Data<AppConfig>,
+) -> Result<HttpResponse> {
+    let user_id = user.user_id.to_string();
+    let message = message.into_inner();
+    if matches!(message, ClientMessage::Text { .. } | ClientMessage::Typing { .. }) {
+        check_rooms(&pool, &config, &user_id, std::slice::from_ref(&query.room_id)).await?;
+    }
+
+    match message {
+        ClientMessage::Text { content } => {
+            let restricted =
+                email_verification::is_restricted(&pool, config.auth.require_verified_email, &user_id)
+                    .await
+                    .map_err(|e| {
+                        error!("Failed to check email verification of user {}: {}", user_id, e);
+                        actix_web::error::ErrorInternalServerError("Failed to send message")
+                    })?;
+            if restricted {
+                return Err(actix_web::error::ErrorForbidden(VERIFY_FIRST));
+            }
+            let message = messaging::post_room_message(
+                &pool,
+                &router,
+                &query.room_id,
+                &user_id,
+                content,
+                DisplayOverride::default(),
+            )
+            .await
+            .map_err(|e| match e {
+                PostError::Rejected(reason) => actix_web::error::ErrorBadRequest(reason),
+                e => {
+                    error!("Failed to store message for room {}: {}", query.room_id, e);
+                    actix_web::error::ErrorInternalServerError("Failed to send message")
+                }
+            })?;
+            Ok(HttpResponse::Ok().json(ApiResponse::success(message)))
+        }
+        ClientMessage::Typing { .. } => {
+            router.room(&query.room_id).do_send(TypingIndicator {
+                room_id: query.room_id.clone(),
+                user_id,
+            });
+            Ok(HttpResponse::Accepted().finish())
+        }
+        ClientMessage::Private { to, content } => {
+            messaging::send_private_message(&pool, &router, &user_id, &to, content)
+                .await
+                .map_err(|e| match e {
+                    PostError::Rejected(reason) => actix_web::error::ErrorForbidden(reason),
+                    e => {
+                        error!("Failed to send direct message to {}: {}", to, e);
+                        actix_web::error::ErrorInternalServerError("Failed to send message")
+                    }
+                })?;
+            Ok(HttpResponse::Accepted().finish())
+        }
+        _ => Err(actix_web::error::ErrorBadRequest("Unsupported message type")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::web::Bytes;
+
+    #[test]
+    fn room_list_splits_and_trims() {
+        assert_eq!(room_list(" general, , dev ").unwrap(), vec!["general".to_string(), "dev".to_string()]);
+    }
+
+    #[test]
+    fn room_list_needs_a_room() {
+        assert!(room_list("").is_err());
+        assert!(room_list(" , ").is_err());
+    }
+
+    #[test]
+    fn format_sse_frames_events_with_their_ids() {
+        let id = Uuid::new_v4();
+        let body = format_sse(vec![
+            StreamEvent { id: Some(id), data: "{\"a\":1}".into() },
+            StreamEvent { id: None, data: "{\"b\":2}".into() },
+        ]);
+        assert_eq!(body, Bytes::from(format!("id: {}\ndata: {{\"a\":1}}\n\ndata: {{\"b\":2}}\n\n", id)));
+    }
+
+    #[test]
+    fn format_sse_keeps_idle_streams_alive() {
+        assert_eq!(format_sse(Vec::new()), Bytes::from_static(b": keep-alive\n\n"));
+    }
+}
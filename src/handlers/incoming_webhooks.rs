@@ -0,0 +1,144 @@
+use crate::{
+    database::connection::DbPool,
+    handlers::{bots::find_owned_bot, rooms::find_owned_room},
+    middleware::auth::AuthenticatedUser,
+    models::{
+        incoming_webhook::{CreateIncomingWebhook, IncomingWebhook},
+        message::DisplayOverride,
+        room_member::RoomMember,
+    },
+    requests::bot_requests::{CreateIncomingWebhookRequest, IncomingWebhookPayload},
+    services::messaging::{self, PostError},
+    utils::helpers::ApiResponse,
+    ws_router::ChatRouter,
+};
+use actix_web::{web, HttpResponse, Result};
+use serde::Serialize;
+use tracing::error;
+use uuid::Uuid;
+
+/// Returned once on creation, the only time the URL with its token is shown
+#[derive(Debug, Serialize)]
+pub struct CreatedIncomingWebhook {
+    #[serde(flatten)]
+    pub webhook: IncomingWebhook,
+    pub url: String,
+}
+
+/// Incoming webhooks are managed by the room owner
+async fn ensure_room_owner(pool: &DbPool, room_id: Uuid, user: &AuthenticatedUser) -> Result<()> {
+    find_owned_room(pool, room_id, user, "Only the room owner can manage incoming webhooks").await?;
+    Ok(())
+}
+
+pub async fn create(
+    pool: web::Data<DbPool>,
+    room_id: web::Path<Uuid>,
+    webhook_data: web::Json<CreateIncomingWebhookRequest>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse> {
+    let room_id = room_id.into_inner();
+    ensure_room_owner(&pool, room_id, &user).await?;
+    let bot = find_owned_bot(&pool, webhook_data.bot_id, &user).await?;
+
+    let create_data = CreateIncomingWebhook {
+        room_id,
+        bot_id: bot.id,
+        name: webhook_data.name.clone(),
+        created_by: user.user_id,
+    };
+
+    let (webhook, token) = IncomingWebhook::create(&pool, create_data).await.map_err(|e| {
+        error!("Failed to create incoming webhook for room {}: {}", room_id, e);
+        actix_web::error::ErrorInternalServerError("Failed to create incoming webhook")
+    })?;
+
+    // The bot posts as a member, so the webhook also works in private rooms
+    RoomMember::add(&pool, room_id, bot.id, Some(user.user_id)).await.map_err(|e| {
+        error!("Failed to add bot {} to room {}: {}", bot.id, room_id, e);
+        actix_web::error::ErrorInternalServerError("Failed to create incoming webhook")
+    })?;
+
+    let url = format!("/api/v1/hooks/{}/{}", webhook.id, token);
+    Ok(HttpResponse::Created().json(ApiResponse::success(CreatedIncomingWebhook { webhook, url })))
+}
+
+pub async fn get_room_incoming_webhooks(
+    pool: web::Data<DbPool>,
+    room_id: web::Path<Uuid>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse> {
+    let room_id = room_id.into_inner();
+    ensure_room_owner(&pool, room_id, &user).await?;
+
+    let webhooks = IncomingWebhook::find_by_room_id(&pool, room_id).await.map_err(|e| {
+        error!("Failed to fetch incoming webhooks of room {}: {}", room_id, e);
+        actix_web::error::ErrorInternalServerError("Failed to fetch incoming webhooks")
+    })?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(webhooks)))
+}
+
+pub async fn delete(
+    pool: web::Data<DbPool>,
+    path: web::Path<(Uuid, Uuid)>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse> {
+    let (room_id, webhook_id) = path.into_inner();
+    ensure_room_owner(&pool, room_id, &user).await?;
+
+    let deleted = IncomingWebhook::delete(&pool, webhook_id, room_id).await.map_err(|e| {
+        error!("Failed to delete incoming webhook {}: {}", webhook_id, e);
+        actix_web::error::ErrorInternalServerError("Failed to delete incoming webhook")
+    })?;
+
+    if !deleted {
+        return Err(actix_web::error::ErrorNotFound("Incoming webhook not found"));
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Posts a message to the webhook's room as its bot; the URL itself is the credential
+pub async fn post_message(
+    pool: web::Data<DbPool>,
+    router: web::Data<ChatRouter>,
+    path: web::Path<(Uuid, String)>,
+    payload: web::Json<IncomingWebhookPayload>,
+) -> Result<HttpResponse> {
+    let (webhook_id, token) = path.into_inner();
+    let webhook = IncomingWebhook::find_by_credentials(&pool, webhook_id, &token)
+        .await
+        .map_err(|e| {
+            error!("Failed to fetch incoming webhook {}: {}", webhook_id, e);
+            actix_web::error::ErrorInternalServerError("Failed to post message")
+        })?
+        .ok_or_else(|| actix_web::error::ErrorNotFound("Incoming webhook not found"))?;
+
+    let payload = payload.into_inner();
+    if payload.text.trim().is_empty() {
+        return Err(actix_web::error::ErrorBadRequest("Message text is empty"));
+    }
+
+    let display = DisplayOverride {
+        display_name: payload.username,
+        avatar_url: payload.avatar_url,
+    };
+    let message = messaging::post_room_message(
+        &pool,
+        &router,
+        &webhook.room_id.to_string(),
+        &webhook.bot_id.to_string(),
+        payload.text,
+        display,
+    )
+    .await
+    .map_err(|e| match e {
+        PostError::Rejected(reason) => actix_web::error::ErrorBadRequest(reason),
+        e => {
+            error!("Failed to post message through incoming webhook {}: {}", webhook_id, e);
+            actix_web::error::ErrorInternalServerError("Failed to post message")
+        }
+    })?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(message)))
+}
@@ -0,0 +1,33 @@
+use crate::{
+    database::connection::DbPool,
+    middleware::auth::AuthenticatedUser,
+    models::mention::MessageMention,
+    requests::mention_requests::MentionQuery,
+    utils::helpers::ApiResponse,
+};
+use actix_web::{web, HttpResponse, Result};
+use tracing::error;
+
+const DEFAULT_MENTION_LIMIT: i64 = 50;
+const MAX_MENTION_LIMIT: i64 = 200;
+
+/// The authenticated user's most recent mentions
+pub async fn get_mentions(
+    pool: web::Data<DbPool>,
+    query: web::Query<MentionQuery>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse> {
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_MENTION_LIMIT)
+        .clamp(1, MAX_MENTION_LIMIT);
+
+    let mentions = MessageMention::find_by_user_id(&pool, user.user_id, limit)
+        .await
+        .map_err(|e| {
+            error!("Failed to fetch mentions of user {}: {}", user.user_id, e);
+            actix_web::error::ErrorInternalServerError("Failed to fetch mentions")
+        })?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(mentions)))
+}
@@ -0,0 +1,7 @@
+use crate::{utils::helpers::ApiResponse, ws_outbox::OUTBOUND_METRICS};
+use actix_web::{HttpResponse, Result};
+
+/// Frames lost to slow WebSocket clients since the server started
+pub async fn outbound() -> Result<HttpResponse> {
+    Ok(HttpResponse::Ok().json(ApiResponse::success(OUTBOUND_METRICS.snapshot())))
+}
@@ -1,3 +1,15 @@
 pub mod auth;
+pub mod blocks;
+pub mod bots;
+pub mod content_rules;
+pub mod events;
+pub mod incoming_webhooks;
+pub mod mentions;
+pub mod metrics;
+pub mod notifications;
+pub mod reports;
 pub mod rooms;
+pub mod sanctions;
 pub mod users;
+pub mod webhooks;
+pub mod ws_tickets;
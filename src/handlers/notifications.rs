@@ -0,0 +1,121 @@
+use crate::{
+    database::connection::DbPool,
+    middleware::auth::AuthenticatedUser,
+    models::{
+        email_opt_out::EmailOptOut,
+        notification::{Notification, NotificationKind},
+    },
+    requests::notification_requests::{NotificationQuery, UpdateEmailPreferencesRequest},
+    utils::helpers::ApiResponse,
+};
+use actix_web::{web, HttpResponse, Result};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use tracing::error;
+use uuid::Uuid;
+
+const DEFAULT_NOTIFICATION_LIMIT: i64 = 50;
+const MAX_NOTIFICATION_LIMIT: i64 = 200;
+
+#[derive(Debug, Serialize)]
+pub struct NotificationPage {
+    pub notifications: Vec<Notification>,
+    pub unread_count: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MarkedRead {
+    pub marked: u64,
+}
+
+pub async fn get_notifications(
+    pool: web::Data<DbPool>,
+    query: web::Query<NotificationQuery>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse> {
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_NOTIFICATION_LIMIT)
+        .clamp(1, MAX_NOTIFICATION_LIMIT);
+    let offset = query.offset.unwrap_or(0).max(0);
+
+    let notifications = Notification::find_by_user_id(&pool, user.user_id, query.unread, limit, offset)
+        .await
+        .map_err(|e| {
+            error!("Failed to fetch notifications of user {}: {}", user.user_id, e);
+            actix_web::error::ErrorInternalServerError("Failed to fetch notifications")
+        })?;
+    let unread_count = Notification::count_unread(&pool, user.user_id)
+        .await
+        .map_err(|e| {
+            error!("Failed to count unread notifications of user {}: {}", user.user_id, e);
+            actix_web::error::ErrorInternalServerError("Failed to fetch notifications")
+        })?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(NotificationPage {
+        notifications,
+        unread_count,
+    })))
+}
+
+pub async fn mark_read(
+    pool: web::Data<DbPool>,
+    notification_id: web::Path<Uuid>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse> {
+    let notification_id = notification_id.into_inner();
+    let notification = Notification::mark_read(&pool, notification_id, user.user_id)
+        .await
+        .map_err(|e| {
+            error!("Failed to mark notification {} read: {}", notification_id, e);
+            actix_web::error::ErrorInternalServerError("Failed to update notification")
+        })?
+        .ok_or_else(|| actix_web::error::ErrorNotFound("Notification not found"))?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(notification)))
+}
+
+pub async fn mark_all_read(pool: web::Data<DbPool>, user: AuthenticatedUser) -> Result<HttpResponse> {
+    let marked = Notification::mark_all_read(&pool, user.user_id).await.map_err(|e| {
+        error!("Failed to mark notifications of user {} read: {}", user.user_id, e);
+        actix_web::error::ErrorInternalServerError("Failed to update notifications")
+    })?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(MarkedRead { marked })))
+}
+
+/// Whether each notification category is emailed when the user is offline
+async fn email_preferences(pool: &DbPool, user: &AuthenticatedUser) -> Result<BTreeMap<&'static str, bool>> {
+    let opted_out = EmailOptOut::find_by_user_id(pool, user.user_id).await.map_err(|e| {
+        error!("Failed to fetch email preferences of user {}: {}", user.user_id, e);
+        actix_web::error::ErrorInternalServerError("Failed to fetch email preferences")
+    })?;
+
+    Ok(NotificationKind::ALL
+        .iter()
+        .map(|kind| (kind.as_str(), !opted_out.iter().any(|category| category == kind.as_str())))
+        .collect())
+}
+
+pub async fn get_email_preferences(pool: web::Data<DbPool>, user: AuthenticatedUser) -> Result<HttpResponse> {
+    let preferences = email_preferences(&pool, &user).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(preferences)))
+}
+
+pub async fn update_email_preferences(
+    pool: web::Data<DbPool>,
+    preferences: web::Json<UpdateEmailPreferencesRequest>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse> {
+    for (category, enabled) in &preferences.categories {
+        EmailOptOut::set(&pool, user.user_id, *category, *enabled)
+            .await
+            .map_err(|e| {
+                error!("Failed to update email preferences of user {}: {}", user.user_id, e);
+                actix_web::error::ErrorInternalServerError("Failed to update email preferences")
+            })?;
+    }
+
+    let preferences = email_preferences(&pool, &user).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(preferences)))
+}
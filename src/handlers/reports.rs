@@ -0,0 +1,282 @@
+use crate::{
+    database::connection::DbPool,
+    handlers::{rooms::find_room, sanctions::prepare_sanction},
+    middleware::auth::AuthenticatedUser,
+    models::{
+        message::Message,
+        message_report::{CreateMessageReport, MessageReport, ReportNote, ReportStatus},
+        room_member::RoomMember,
+        user::User,
+    },
+    requests::report_requests::{
+        CreateReportNoteRequest, CreateReportRequest, ReportAction, ReportQuery, ResolveReportRequest,
+    },
+    services::{moderation, reports},
+    utils::helpers::ApiResponse,
+    ws_router::ChatRouter,
+};
+use actix_web::{web, HttpResponse, Result};
+use serde::Serialize;
+use tracing::error;
+use uuid::Uuid;
+
+const DEFAULT_REPORT_LIMIT: i64 = 50;
+const MAX_REPORT_LIMIT: i64 = 200;
+
+const MAX_DETAILS_CHARS: usize = 1000;
+const MAX_NOTE_CHARS: usize = 2000;
+
+#[derive(Debug, Serialize)]
+pub struct ReportWithNotes {
+    #[serde(flatten)]
+    pub report: MessageReport,
+    pub notes: Vec<ReportNote>,
+}
+
+/// A report the user may review, as the owner of its room or an admin
+async fn find_reviewable_report(pool: &DbPool, report_id: Uuid, user: &AuthenticatedUser) -> Result<MessageReport> {
+    let report = MessageReport::find_by_id(pool, report_id)
+        .await
+        .map_err(|e| {
+            error!("Failed to fetch report {}: {}", report_id, e);
+            actix_web::error::ErrorInternalServerError("Failed to fetch report")
+        })?
+        .ok_or_else(|| actix_web::error::ErrorNotFound("Report not found"))?;
+
+    let room = find_room(pool, report.room_id).await?;
+    if room.created_by != user.user_id && !user.is_admin(pool).await? {
+        return Err(actix_web::error::ErrorForbidden(
+            "Only the room owner or an admin can review its reports",
+        ));
+    }
+    Ok(report)
+}
+
+/// Trims optional free text, `None` when empty
+fn optional_text(text: Option<String>, name: &str, max_chars: usize) -> Result<Option<String>> {
+    let text = text.map(|text| text.trim().to_string()).filter(|text| !text.is_empty());
+    if text.as_ref().is_some_and(|text| text.chars().count() > max_chars) {
+        return Err(actix_web::error::ErrorBadRequest(format!(
+            "{} must be at most {} characters",
+            name, max_chars
+        )));
+    }
+    Ok(text)
+}
+
+/// Reports a room message the user can see
+pub async fn create_report(
+    pool: web::Data<DbPool>,
+    message_id: web::Path<Uuid>,
+    request: web::Json<CreateReportRequest>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse> {
+    let message_id = message_id.into_inner();
+    let request = request.into_inner();
+    let message = Message::find_by_id(&pool, message_id)
+        .await
+        .map_err(|e| {
+            error!("Failed to fetch message {}: {}", message_id, e);
+            actix_web::error::ErrorInternalServerError("Failed to report message")
+        })?
+        .ok_or_else(|| actix_web::error::ErrorNotFound("Message not found"))?;
+
+    // Messages of private rooms are only visible to their members
+    let room = find_room(&pool, message.room_id).await?;
+    if room.is_private {
+        let member = RoomMember::find(&pool, room.id, user.user_id).await.map_err(|e| {
+            error!("Failed to fetch membership of user {} in room {}: {}", user.user_id, room.id, e);
+            actix_web::error::ErrorInternalServerError("Failed to report message")
+        })?;
+        if member.is_none() {
+            return Err(actix_web::error::ErrorNotFound("Message not found"));
+        }
+    }
+    if message.sender_id == user.user_id {
+        return Err(actix_web::error::ErrorBadRequest("You cannot report your own message"));
+    }
+    let details = optional_text(request.details, "details", MAX_DETAILS_CHARS)?;
+
+    let report = CreateMessageReport {
+        message_id: message.id,
+        room_id: message.room_id,
+        sender_id: message.sender_id,
+        content: message.content,
+        reporter_id: Some(user.user_id),
+        category: request.category,
+        details,
+    };
+    match MessageReport::create(&pool, report).await {
+        Ok(report) => Ok(HttpResponse::Created().json(ApiResponse::success(report))),
+        Err(sqlx::Error::Database(db)) if db.is_unique_violation() => {
+            Err(actix_web::error::ErrorConflict("You already reported this message"))
+        }
+        Err(e) => {
+            error!("Failed to report message {}: {}", message_id, e);
+            Err(actix_web::error::ErrorInternalServerError("Failed to report message"))
+        }
+    }
+}
+
+/// The review queue: reports in rooms the user owns, or every report for admins
+pub async fn get_reports(
+    pool: web::Data<DbPool>,
+    query: web::Query<ReportQuery>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse> {
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_REPORT_LIMIT)
+        .clamp(1, MAX_REPORT_LIMIT);
+    let offset = query.offset.unwrap_or(0).max(0);
+    let is_admin = user.is_admin(&pool).await?;
+
+    let reports = MessageReport::find_queue(&pool, user.user_id, is_admin, query.status, query.room_id, limit, offset)
+        .await
+        .map_err(|e| {
+            error!("Failed to fetch reports for user {}: {}", user.user_id, e);
+            actix_web::error::ErrorInternalServerError("Failed to fetch reports")
+        })?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(reports)))
+}
+
+pub async fn get_report(
+    pool: web::Data<DbPool>,
+    report_id: web::Path<Uuid>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse> {
+    let report = find_reviewable_report(&pool, report_id.into_inner(), &user).await?;
+    let notes = ReportNote::find_by_report_id(&pool, report.id).await.map_err(|e| {
+        error!("Failed to fetch notes of report {}: {}", report.id, e);
+        actix_web::error::ErrorInternalServerError("Failed to fetch report")
+    })?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(ReportWithNotes { report, notes })))
+}
+
+/// Assigns a report to the current user, so other moderators leave it alone
+pub async fn claim_report(
+    pool: web::Data<DbPool>,
+    report_id: web::Path<Uuid>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse> {
+    let report = find_reviewable_report(&pool, report_id.into_inner(), &user).await?;
+    let report = MessageReport::claim(&pool, report.id, user.user_id)
+        .await
+        .map_err(|e| {
+            error!("Failed to claim report {}: {}", report.id, e);
+            actix_web::error::ErrorInternalServerError("Failed to claim report")
+        })?
+        .ok_or_else(|| {
+            actix_web::error::ErrorConflict("Report is resolved or claimed by another moderator")
+        })?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(report)))
+}
+
+/// Closes a report by dismissing it, deleting the message or sanctioning its sender,
+/// and notifies the reporter. Deleting a message closes every report of it.
+pub async fn resolve_report(
+    pool: web::Data<DbPool>,
+    router: web::Data<ChatRouter>,
+    report_id: web::Path<Uuid>,
+    request: web::Json<ResolveReportRequest>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse> {
+    let report = find_reviewable_report(&pool, report_id.into_inner(), &user).await?;
+    let request = request.into_inner();
+    if report.status == ReportStatus::Resolved.as_str() {
+        return Err(actix_web::error::ErrorConflict("Report is already resolved"));
+    }
+    if report.claimed_by.is_some_and(|moderator| moderator != user.user_id) {
+        return Err(actix_web::error::ErrorConflict("Report is claimed by another moderator"));
+    }
+    let note = optional_text(request.note, "note", MAX_NOTE_CHARS)?;
+    let resolution = request.action.resolution();
+
+    let resolved = match (request.action, report.message_id) {
+        (ReportAction::DeleteMessage, Some(message_id)) => {
+            // Closed first, deleting the message unlinks its reports
+            let resolved = MessageReport::resolve_by_message_id(&pool, message_id, user.user_id, resolution)
+                .await
+                .map_err(|e| {
+                    error!("Failed to resolve reports of message {}: {}", message_id, e);
+                    actix_web::error::ErrorInternalServerError("Failed to resolve report")
+                })?;
+            moderation::delete_message(&pool, &router, report.room_id, message_id)
+                .await
+                .map_err(|e| {
+                    error!("Failed to delete message {}: {}", message_id, e);
+                    actix_web::error::ErrorInternalServerError("Failed to delete message")
+                })?;
+            resolved
+        }
+        (action, _) => {
+            if action == ReportAction::SanctionUser {
+                let details = request.sanction.ok_or_else(|| {
+                    actix_web::error::ErrorBadRequest("sanction is required to sanction the user")
+                })?;
+                let room = find_room(&pool, report.room_id).await?;
+                let sanction = prepare_sanction(&room, user.user_id, report.sender_id, details)?;
+                let target = User::find_by_id(&pool, report.sender_id)
+                    .await
+                    .map_err(|e| {
+                        error!("Failed to fetch user {}: {}", report.sender_id, e);
+                        actix_web::error::ErrorInternalServerError("Failed to sanction user")
+                    })?
+                    .ok_or_else(|| actix_web::error::ErrorNotFound("User not found"))?;
+                moderation::sanction(&pool, &router, sanction, &target)
+                    .await
+                    .map_err(|e| {
+                        error!("Failed to sanction user {} in room {}: {}", target.id, room.id, e);
+                        actix_web::error::ErrorInternalServerError("Failed to sanction user")
+                    })?;
+            }
+            MessageReport::resolve(&pool, report.id, user.user_id, resolution)
+                .await
+                .map_err(|e| {
+                    error!("Failed to resolve report {}: {}", report.id, e);
+                    actix_web::error::ErrorInternalServerError("Failed to resolve report")
+                })?
+                .into_iter()
+                .collect()
+        }
+    };
+
+    if let Some(note) = note {
+        ReportNote::create(&pool, report.id, user.user_id, note)
+            .await
+            .map_err(|e| {
+                error!("Failed to add note to report {}: {}", report.id, e);
+                actix_web::error::ErrorInternalServerError("Failed to add note")
+            })?;
+    }
+    reports::notify_reporters(&pool, &router, &resolved).await;
+
+    let report = resolved
+        .into_iter()
+        .find(|resolved| resolved.id == report.id)
+        .ok_or_else(|| actix_web::error::ErrorConflict("Report is already resolved"))?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(report)))
+}
+
+pub async fn add_note(
+    pool: web::Data<DbPool>,
+    report_id: web::Path<Uuid>,
+    request: web::Json<CreateReportNoteRequest>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse> {
+    let report = find_reviewable_report(&pool, report_id.into_inner(), &user).await?;
+    let body = optional_text(Some(request.into_inner().body), "body", MAX_NOTE_CHARS)?
+        .ok_or_else(|| actix_web::error::ErrorBadRequest("body must not be empty"))?;
+
+    let note = ReportNote::create(&pool, report.id, user.user_id, body)
+        .await
+        .map_err(|e| {
+            error!("Failed to add note to report {}: {}", report.id, e);
+            actix_web::error::ErrorInternalServerError("Failed to add note")
+        })?;
+
+    Ok(HttpResponse::Created().json(ApiResponse::success(note)))
+}
@@ -1,14 +1,44 @@
 use crate::{
     database::connection::DbPool,
     middleware::auth::AuthenticatedUser,
-    models::room::{CreateRoom, Room},
-    requests::room_requests::CreateRoomRequest,
+    models::room::{CreateRoom, Room, UpdateRoom},
+    requests::room_requests::{CreateRoomRequest, UpdateRoomRequest},
     utils::helpers::ApiResponse,
+    ws_router::ChatRouter,
+    ws_server::RoomUpdated,
 };
 use actix_web::{web, HttpResponse, Result};
 use tracing::{error, warn};
 use uuid::Uuid;
 
+/// Looks up a room, 404 when there is none
+pub async fn find_room(pool: &DbPool, room_id: Uuid) -> Result<Room> {
+    Room::find_by_id(pool, room_id)
+        .await
+        .map_err(|e| {
+            error!("Failed to fetch room {}: {}", room_id, e);
+            actix_web::error::ErrorInternalServerError("Failed to fetch room")
+        })?
+        .ok_or_else(|| {
+            warn!("Room not found: {}", room_id);
+            actix_web::error::ErrorNotFound("Room not found")
+        })
+}
+
+/// Looks up a room only its owner may act on, `forbidden` is shown to anyone else
+pub async fn find_owned_room(
+    pool: &DbPool,
+    room_id: Uuid,
+    user: &AuthenticatedUser,
+    forbidden: &'static str,
+) -> Result<Room> {
+    let room = find_room(pool, room_id).await?;
+    if room.created_by != user.user_id {
+        return Err(actix_web::error::ErrorForbidden(forbidden));
+    }
+    Ok(room)
+}
+
 pub async fn create_room(
     pool: web::Data<DbPool>,
     room_data: web::Json<CreateRoomRequest>,
@@ -40,18 +70,37 @@ pub async fn get_all_rooms(pool: web::Data<DbPool>) -> Result<HttpResponse> {
 pub async fn get_room_by_id(
     pool: web::Data<DbPool>,
     room_id: web::Path<Uuid>,
+) -> Result<HttpResponse> {
+    let room = find_room(&pool, room_id.into_inner()).await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(room)))
+}
+
+pub async fn update_room(
+    pool: web::Data<DbPool>,
+    router: web::Data<ChatRouter>,
+    room_id: web::Path<Uuid>,
+    room_data: web::Json<UpdateRoomRequest>,
+    user: AuthenticatedUser,
 ) -> Result<HttpResponse> {
     let room_id = room_id.into_inner();
-    let room = Room::find_by_id(&pool, room_id)
+    find_owned_room(&pool, room_id, &user, "Only the room owner can update it").await?;
+
+    let update_data = UpdateRoom {
+        name: room_data.name.clone(),
+        is_private: room_data.is_private,
+        topic: room_data.topic.clone(),
+    };
+
+    let room = Room::update(&pool, room_id, update_data)
         .await
         .map_err(|e| {
-            error!("Failed to fetch room {}: {}", room_id, e);
-            actix_web::error::ErrorInternalServerError("Failed to fetch room")
+            error!("Failed to update room {}: {}", room_id, e);
+            actix_web::error::ErrorInternalServerError("Failed to update room")
         })?
-        .ok_or_else(|| {
-            warn!("Room not found: {}", room_id);
-            actix_web::error::ErrorNotFound("Room not found")
-        })?;
+        .ok_or_else(|| actix_web::error::ErrorNotFound("Room not found"))?;
+
+    router.room(&room.id.to_string()).do_send(RoomUpdated { room: room.clone() });
 
     Ok(HttpResponse::Ok().json(ApiResponse::success(room)))
 }
@@ -0,0 +1,138 @@
+use crate::{
+    database::connection::DbPool,
+    handlers::rooms::find_owned_room,
+    middleware::auth::AuthenticatedUser,
+    models::{
+        room::Room,
+        room_sanction::{CreateRoomSanction, RoomSanction, SanctionKind},
+        user::User,
+    },
+    requests::room_requests::{CreateSanctionRequest, SanctionDetails},
+    services::moderation,
+    utils::helpers::ApiResponse,
+    ws_router::ChatRouter,
+};
+use actix_web::{web, HttpResponse, Result};
+use chrono::{Duration, Utc};
+use tracing::error;
+use uuid::Uuid;
+
+const MAX_REASON_CHARS: usize = 500;
+
+async fn find_moderated_room(pool: &DbPool, room_id: Uuid, user: &AuthenticatedUser) -> Result<Room> {
+    find_owned_room(pool, room_id, user, "Only the room owner can moderate it").await
+}
+
+/// Validates a sanction against a member of `room`, issued by `moderator_id`
+pub fn prepare_sanction(
+    room: &Room,
+    moderator_id: Uuid,
+    user_id: Uuid,
+    details: SanctionDetails,
+) -> Result<CreateRoomSanction> {
+    if user_id == room.created_by {
+        return Err(actix_web::error::ErrorBadRequest("The room owner cannot be sanctioned"));
+    }
+    let duration = match details.duration_secs {
+        Some(secs) => Some(
+            Duration::try_seconds(secs)
+                .filter(|duration| *duration > Duration::zero())
+                .ok_or_else(|| actix_web::error::ErrorBadRequest("duration_secs must be positive"))?,
+        ),
+        None => None,
+    };
+    let reason = details
+        .reason
+        .map(|reason| reason.trim().to_string())
+        .filter(|reason| !reason.is_empty());
+    if reason.as_ref().is_some_and(|reason| reason.chars().count() > MAX_REASON_CHARS) {
+        return Err(actix_web::error::ErrorBadRequest(format!(
+            "reason must be at most {} characters",
+            MAX_REASON_CHARS
+        )));
+    }
+
+    let now = Utc::now();
+    let expires_at = match (details.kind, duration) {
+        // A warning is only recorded, a kick without a duration only removes the member
+        (SanctionKind::Warn, _) | (SanctionKind::Kick, None) => Some(now),
+        (_, Some(duration)) => now.checked_add_signed(duration),
+        (_, None) => None,
+    };
+    Ok(CreateRoomSanction {
+        room_id: room.id,
+        user_id,
+        kind: details.kind,
+        reason,
+        issued_by: Some(moderator_id),
+        expires_at,
+    })
+}
+
+/// Mutes, kicks or bans a member of the room
+pub async fn create_sanction(
+    pool: web::Data<DbPool>,
+    router: web::Data<ChatRouter>,
+    room_id: web::Path<Uuid>,
+    request: web::Json<CreateSanctionRequest>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse> {
+    let room = find_moderated_room(&pool, room_id.into_inner(), &user).await?;
+    let request = request.into_inner();
+    let sanction = prepare_sanction(&room, user.user_id, request.user_id, request.sanction)?;
+
+    let target = User::find_by_id(&pool, request.user_id)
+        .await
+        .map_err(|e| {
+            error!("Failed to fetch user {}: {}", request.user_id, e);
+            actix_web::error::ErrorInternalServerError("Failed to sanction user")
+        })?
+        .ok_or_else(|| actix_web::error::ErrorNotFound("User not found"))?;
+
+    let sanction = moderation::sanction(&pool, &router, sanction, &target)
+        .await
+        .map_err(|e| {
+            error!("Failed to sanction user {} in room {}: {}", target.id, room.id, e);
+            actix_web::error::ErrorInternalServerError("Failed to sanction user")
+        })?;
+
+    Ok(HttpResponse::Created().json(ApiResponse::success(sanction)))
+}
+
+/// Sanctions in force in the room
+pub async fn get_sanctions(
+    pool: web::Data<DbPool>,
+    room_id: web::Path<Uuid>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse> {
+    let room = find_moderated_room(&pool, room_id.into_inner(), &user).await?;
+    let sanctions = RoomSanction::find_active_by_room_id(&pool, room.id)
+        .await
+        .map_err(|e| {
+            error!("Failed to fetch sanctions of room {}: {}", room.id, e);
+            actix_web::error::ErrorInternalServerError("Failed to fetch sanctions")
+        })?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(sanctions)))
+}
+
+pub async fn lift_sanction(
+    pool: web::Data<DbPool>,
+    router: web::Data<ChatRouter>,
+    path: web::Path<(Uuid, Uuid)>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse> {
+    let (room_id, sanction_id) = path.into_inner();
+    let room = find_moderated_room(&pool, room_id, &user).await?;
+    let lifted = moderation::lift(&pool, &router, room.id, sanction_id)
+        .await
+        .map_err(|e| {
+            error!("Failed to lift sanction {} in room {}: {}", sanction_id, room.id, e);
+            actix_web::error::ErrorInternalServerError("Failed to lift sanction")
+        })?;
+    if lifted.is_none() {
+        return Err(actix_web::error::ErrorNotFound("Sanction not found"));
+    }
+
+    Ok(HttpResponse::NoContent().finish())
+}
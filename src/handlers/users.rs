@@ -1,12 +1,260 @@
-use crate::{database::connection::DbPool, models::user::User, utils::helpers::ApiResponse};
+use crate::{
+    config::settings::AppConfig,
+    database::connection::DbPool,
+    middleware::auth::AuthenticatedUser,
+    models::{
+        room_member::RoomMember,
+        user::{PublicUser, UpdateProfile, User, UserProfile},
+    },
+    requests::user_requests::{UpdateProfileRequest, UserDirectoryQuery},
+    services::{auth::AuthService, email_verification, mailer::Mailer},
+    utils::helpers::ApiResponse,
+    ws_router::ChatRouter,
+    ws_server::ProfileUpdated,
+};
 use actix_web::{web, HttpResponse, Result};
+use serde::Serialize;
+use std::sync::Arc;
 use tracing::error;
+use uuid::Uuid;
 
-pub async fn index(pool: web::Data<DbPool>) -> Result<HttpResponse> {
-    let users = User::find_all(&pool).await.map_err(|e| {
-        error!("Failed to fetch users: {}", e);
-        actix_web::error::ErrorInternalServerError("Failed to fetch users")
-    })?;
+const DEFAULT_DIRECTORY_LIMIT: i64 = 50;
+const MAX_DIRECTORY_LIMIT: i64 = 100;
 
-    Ok(HttpResponse::Ok().json(ApiResponse::success(users)))
+const MAX_FULL_NAME_CHARS: usize = 255;
+const MAX_DISPLAY_NAME_CHARS: usize = 64;
+const MAX_AVATAR_URL_CHARS: usize = 2048;
+const MAX_BIO_CHARS: usize = 500;
+const MAX_PRONOUNS_CHARS: usize = 32;
+const MAX_TIMEZONE_CHARS: usize = 64;
+const MAX_LOCALE_CHARS: usize = 35;
+
+#[derive(Debug, Serialize)]
+pub struct UserPage {
+    pub users: Vec<PublicUser>,
+    /// Passed as `cursor` to get the next page, `None` on the last one
+    pub next_cursor: Option<String>,
+}
+
+/// The user directory, ordered by username and optionally filtered by `q`
+pub async fn index(
+    pool: web::Data<DbPool>,
+    query: web::Query<UserDirectoryQuery>,
+) -> Result<HttpResponse> {
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_DIRECTORY_LIMIT)
+        .clamp(1, MAX_DIRECTORY_LIMIT);
+    let search = query.q.as_deref().map(str::trim).filter(|q| !q.is_empty());
+
+    let users = User::search(&pool, search, query.cursor.as_deref(), limit)
+        .await
+        .map_err(|e| {
+            error!("Failed to fetch users: {}", e);
+            actix_web::error::ErrorInternalServerError("Failed to fetch users")
+        })?;
+
+    let next_cursor = match users.last() {
+        Some(last) if users.len() as i64 == limit => Some(last.username.clone()),
+        _ => None,
+    };
+    let page = UserPage {
+        users: users.into_iter().map(PublicUser::from).collect(),
+        next_cursor,
+    };
+    Ok(HttpResponse::Ok().json(ApiResponse::success(page)))
+}
+
+pub async fn get_user(
+    pool: web::Data<DbPool>,
+    user_id: web::Path<Uuid>,
+) -> Result<HttpResponse> {
+    let user_id = user_id.into_inner();
+    let user = User::find_by_id(&pool, user_id)
+        .await
+        .map_err(|e| {
+            error!("Failed to fetch user {}: {}", user_id, e);
+            actix_web::error::ErrorInternalServerError("Failed to fetch user")
+        })?
+        .ok_or_else(|| actix_web::error::ErrorNotFound("User not found"))?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(PublicUser::from(user))))
+}
+
+pub async fn get_user_by_username(
+    pool: web::Data<DbPool>,
+    username: web::Path<String>,
+) -> Result<HttpResponse> {
+    let user = User::find_by_username(&pool, &username)
+        .await
+        .map_err(|e| {
+            error!("Failed to fetch user {}: {}", username, e);
+            actix_web::error::ErrorInternalServerError("Failed to fetch user")
+        })?
+        .ok_or_else(|| actix_web::error::ErrorNotFound("User not found"))?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(PublicUser::from(user))))
+}
+
+pub async fn get_me(pool: web::Data<DbPool>, user: AuthenticatedUser) -> Result<HttpResponse> {
+    let user = find_current_user(&pool, &user).await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(UserProfile::from(user))))
+}
+
+/// Updates the current user's profile. A new email is stored as pending and
+/// verified by email before it replaces the current one. Rooms the user is a
+/// member of are told when their name, avatar or pronouns change.
+pub async fn update_me(
+    pool: web::Data<DbPool>,
+    router: web::Data<ChatRouter>,
+    config: web::Data<AppConfig>,
+    auth_service: web::Data<AuthService>,
+    mailer: web::Data<Option<Arc<dyn Mailer>>>,
+    user: AuthenticatedUser,
+    request: web::Json<UpdateProfileRequest>,
+) -> Result<HttpResponse> {
+    let account = find_current_user(&pool, &user).await?;
+    let request = request.into_inner();
+    let mut profile = UpdateProfile::from(&account);
+
+    if let Some(full_name) = request.full_name {
+        let full_name = full_name.trim();
+        if full_name.is_empty() || full_name.chars().count() > MAX_FULL_NAME_CHARS {
+            return Err(actix_web::error::ErrorBadRequest(format!(
+                "full_name must be 1 to {} characters",
+                MAX_FULL_NAME_CHARS
+            )));
+        }
+        profile.full_name = full_name.to_string();
+    }
+    edit_field(&mut profile.display_name, request.display_name, "display_name", MAX_DISPLAY_NAME_CHARS)?;
+    edit_field(&mut profile.avatar_url, request.avatar_url, "avatar_url", MAX_AVATAR_URL_CHARS)?;
+    edit_field(&mut profile.bio, request.bio, "bio", MAX_BIO_CHARS)?;
+    edit_field(&mut profile.pronouns, request.pronouns, "pronouns", MAX_PRONOUNS_CHARS)?;
+    edit_field(&mut profile.timezone, request.timezone, "timezone", MAX_TIMEZONE_CHARS)?;
+    edit_field(&mut profile.locale, request.locale, "locale", MAX_LOCALE_CHARS)?;
+
+    let valid_avatar = profile.avatar_url.as_deref().is_none_or(|url| {
+        reqwest::Url::parse(url).is_ok_and(|url| matches!(url.scheme(), "http" | "https"))
+    });
+    if !valid_avatar {
+        return Err(actix_web::error::ErrorBadRequest("avatar_url must be an http(s) URL"));
+    }
+    // Shape checks only, clients resolve the names
+    let valid_timezone = profile.timezone.as_deref().is_none_or(|timezone| {
+        timezone
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '/' | '_' | '-' | '+'))
+    });
+    if !valid_timezone {
+        return Err(actix_web::error::ErrorBadRequest(
+            "timezone must be an IANA time zone, e.g. Europe/Berlin",
+        ));
+    }
+    let valid_locale = profile.locale.as_deref().is_none_or(|locale| {
+        locale.split('-').all(|part| {
+            !part.is_empty() && part.len() <= 8 && part.chars().all(|c| c.is_ascii_alphanumeric())
+        })
+    });
+    if !valid_locale {
+        return Err(actix_web::error::ErrorBadRequest(
+            "locale must be a BCP 47 language tag, e.g. en-GB",
+        ));
+    }
+
+    if let Some(email) = request.email {
+        let email = email.trim();
+        if email == account.email {
+            // Asking for the current address again cancels a pending change
+            profile.pending_email = None;
+        } else {
+            if email.parse::<lettre::Address>().is_err() {
+                return Err(actix_web::error::ErrorBadRequest("Invalid email address"));
+            }
+            if mailer.is_none() {
+                return Err(actix_web::error::ErrorServiceUnavailable(
+                    "Email is not configured, the address cannot be verified",
+                ));
+            }
+            let taken = User::find_by_email(&pool, email).await.map_err(|e| {
+                error!("Failed to fetch user by email: {}", e);
+                actix_web::error::ErrorInternalServerError("Failed to update profile")
+            })?;
+            if taken.is_some() {
+                return Err(actix_web::error::ErrorConflict("Email is already taken"));
+            }
+            profile.pending_email = Some(email.to_string());
+        }
+    }
+
+    let email_requested = profile.pending_email.is_some() && profile.pending_email != account.pending_email;
+    let public_changed = profile.full_name != account.full_name
+        || profile.display_name != account.display_name
+        || profile.avatar_url != account.avatar_url
+        || profile.pronouns != account.pronouns;
+
+    let updated = User::update_profile(&pool, account.id, profile)
+        .await
+        .map_err(|e| {
+            error!("Failed to update profile of user {}: {}", account.id, e);
+            actix_web::error::ErrorInternalServerError("Failed to update profile")
+        })?
+        .ok_or_else(|| actix_web::error::ErrorNotFound("User not found"))?;
+
+    if let (true, Some(mailer), Some(address)) =
+        (email_requested, mailer.get_ref().clone(), &updated.pending_email)
+    {
+        let email = email_verification::verification_email(&auth_service, &config, &updated, address);
+        tokio::spawn(async move {
+            if let Err(e) = mailer.send(&email).await {
+                error!("Failed to send verification email to {}: {}", email.to, e);
+            }
+        });
+    }
+
+    if public_changed {
+        // The profile is already saved, failing here only leaves other clients with stale names
+        match RoomMember::find_room_ids_by_user_id(&pool, updated.id).await {
+            Ok(room_ids) => {
+                for room_id in room_ids {
+                    let room_id = room_id.to_string();
+                    router.room(&room_id).do_send(ProfileUpdated {
+                        room_id,
+                        user: updated.clone(),
+                    });
+                }
+            }
+            Err(e) => error!("Failed to fetch rooms of user {}: {}", updated.id, e),
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(UserProfile::from(updated))))
+}
+
+async fn find_current_user(pool: &DbPool, user: &AuthenticatedUser) -> Result<User> {
+    User::find_by_id(pool, user.user_id)
+        .await
+        .map_err(|e| {
+            error!("Failed to fetch user {}: {}", user.user_id, e);
+            actix_web::error::ErrorInternalServerError("Failed to fetch user")
+        })?
+        .ok_or_else(|| actix_web::error::ErrorNotFound("User not found"))
+}
+
+/// Applies an optional profile field from a request: left out keeps it, an empty
+/// string clears it
+fn edit_field(field: &mut Option<String>, value: Option<String>, name: &str, max_chars: usize) -> Result<()> {
+    let Some(value) = value else {
+        return Ok(());
+    };
+    let value = value.trim();
+    if value.chars().count() > max_chars {
+        return Err(actix_web::error::ErrorBadRequest(format!(
+            "{} must be at most {} characters",
+            name, max_chars
+        )));
+    }
+    *field = (!value.is_empty()).then(|| value.to_string());
+    Ok(())
 }
@@ -0,0 +1,168 @@
+use crate::{
+    database::connection::DbPool,
+    handlers::rooms::find_room,
+    middleware::auth::AuthenticatedUser,
+    models::webhook::{CreateWebhook, Webhook, WebhookDelivery},
+    requests::webhook_requests::{CreateWebhookRequest, DeliveryLogQuery},
+    services::outbound,
+    utils::helpers::ApiResponse,
+};
+use actix_web::{web, HttpResponse, Result};
+use serde::Serialize;
+use tracing::error;
+use uuid::Uuid;
+
+const DEFAULT_DELIVERY_LOG_LIMIT: i64 = 50;
+const MAX_DELIVERY_LOG_LIMIT: i64 = 200;
+
+/// Returned once on creation, the only time the signing secret is shown
+#[derive(Debug, Serialize)]
+pub struct CreatedWebhook {
+    #[serde(flatten)]
+    pub webhook: Webhook,
+    pub secret: String,
+}
+
+/// Room webhooks are managed by the room owner, global ones by admins
+async fn ensure_can_manage(pool: &DbPool, room_id: Option<Uuid>, user: &AuthenticatedUser) -> Result<()> {
+    if let Some(room_id) = room_id {
+        if find_room(pool, room_id).await?.created_by == user.user_id {
+            return Ok(());
+        }
+    }
+
+    if user.is_admin(pool).await? {
+        Ok(())
+    } else {
+        Err(actix_web::error::ErrorForbidden("Not allowed to manage these webhooks"))
+    }
+}
+
+async fn find_managed_webhook(pool: &DbPool, webhook_id: Uuid, user: &AuthenticatedUser) -> Result<Webhook> {
+    let webhook = Webhook::find_by_id(pool, webhook_id)
+        .await
+        .map_err(|e| {
+            error!("Failed to fetch webhook {}: {}", webhook_id, e);
+            actix_web::error::ErrorInternalServerError("Failed to fetch webhook")
+        })?
+        .ok_or_else(|| actix_web::error::ErrorNotFound("Webhook not found"))?;
+
+    ensure_can_manage(pool, webhook.room_id, user).await?;
+    Ok(webhook)
+}
+
+async fn create(
+    pool: &DbPool,
+    room_id: Option<Uuid>,
+    request: CreateWebhookRequest,
+    user: &AuthenticatedUser,
+) -> Result<HttpResponse> {
+    ensure_can_manage(pool, room_id, user).await?;
+
+    if let Err(reason) = outbound::check_url(&request.url).await {
+        return Err(actix_web::error::ErrorBadRequest(format!("Webhook url {}", reason)));
+    }
+    if request.events.is_empty() {
+        return Err(actix_web::error::ErrorBadRequest("Webhook must subscribe to at least one event"));
+    }
+
+    let create_data = CreateWebhook {
+        room_id,
+        url: request.url,
+        events: request.events,
+        created_by: user.user_id,
+    };
+
+    let webhook = Webhook::create(pool, create_data).await.map_err(|e| {
+        error!("Failed to create webhook: {}", e);
+        actix_web::error::ErrorInternalServerError("Failed to create webhook")
+    })?;
+
+    let secret = webhook.secret.clone();
+    Ok(HttpResponse::Created().json(ApiResponse::success(CreatedWebhook { webhook, secret })))
+}
+
+pub async fn create_room_webhook(
+    pool: web::Data<DbPool>,
+    room_id: web::Path<Uuid>,
+    webhook_data: web::Json<CreateWebhookRequest>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse> {
+    create(&pool, Some(room_id.into_inner()), webhook_data.into_inner(), &user).await
+}
+
+pub async fn get_room_webhooks(
+    pool: web::Data<DbPool>,
+    room_id: web::Path<Uuid>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse> {
+    let room_id = room_id.into_inner();
+    ensure_can_manage(&pool, Some(room_id), &user).await?;
+
+    let webhooks = Webhook::find_by_room_id(&pool, room_id).await.map_err(|e| {
+        error!("Failed to fetch webhooks of room {}: {}", room_id, e);
+        actix_web::error::ErrorInternalServerError("Failed to fetch webhooks")
+    })?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(webhooks)))
+}
+
+pub async fn create_global_webhook(
+    pool: web::Data<DbPool>,
+    webhook_data: web::Json<CreateWebhookRequest>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse> {
+    create(&pool, None, webhook_data.into_inner(), &user).await
+}
+
+pub async fn get_global_webhooks(
+    pool: web::Data<DbPool>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse> {
+    ensure_can_manage(&pool, None, &user).await?;
+
+    let webhooks = Webhook::find_global(&pool).await.map_err(|e| {
+        error!("Failed to fetch global webhooks: {}", e);
+        actix_web::error::ErrorInternalServerError("Failed to fetch webhooks")
+    })?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(webhooks)))
+}
+
+pub async fn delete_webhook(
+    pool: web::Data<DbPool>,
+    webhook_id: web::Path<Uuid>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse> {
+    let webhook = find_managed_webhook(&pool, webhook_id.into_inner(), &user).await?;
+
+    Webhook::delete(&pool, webhook.id).await.map_err(|e| {
+        error!("Failed to delete webhook {}: {}", webhook.id, e);
+        actix_web::error::ErrorInternalServerError("Failed to delete webhook")
+    })?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Most recent deliveries of a webhook with their attempts and last error
+pub async fn get_deliveries(
+    pool: web::Data<DbPool>,
+    webhook_id: web::Path<Uuid>,
+    query: web::Query<DeliveryLogQuery>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse> {
+    let webhook = find_managed_webhook(&pool, webhook_id.into_inner(), &user).await?;
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_DELIVERY_LOG_LIMIT)
+        .clamp(1, MAX_DELIVERY_LOG_LIMIT);
+
+    let deliveries = WebhookDelivery::find_by_webhook_id(&pool, webhook.id, limit)
+        .await
+        .map_err(|e| {
+            error!("Failed to fetch deliveries of webhook {}: {}", webhook.id, e);
+            actix_web::error::ErrorInternalServerError("Failed to fetch deliveries")
+        })?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(deliveries)))
+}
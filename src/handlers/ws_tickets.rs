@@ -0,0 +1,43 @@
+use crate::{
+    database::connection::DbPool,
+    middleware::auth::AuthenticatedUser,
+    models::ws_ticket::WsTicket,
+    requests::ws_ticket_requests::CreateTicketRequest,
+    utils::helpers::ApiResponse,
+};
+use actix_web::{web, HttpResponse, Result};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tracing::error;
+use uuid::Uuid;
+
+#[derive(Debug, Serialize)]
+pub struct TicketResponse {
+    pub ticket: Uuid,
+    pub room_id: Option<String>,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Issues a single-use ticket to pass as `?ticket=` when opening the WebSocket or the SSE stream
+pub async fn issue(
+    pool: web::Data<DbPool>,
+    ticket_data: Option<web::Json<CreateTicketRequest>>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse> {
+    let room_id = ticket_data.and_then(|data| data.into_inner().room_id);
+
+    let auth_expires_at = DateTime::from_timestamp(user.expires_at, 0).unwrap_or_else(Utc::now);
+
+    let ticket = WsTicket::create(&pool, user.user_id, room_id, auth_expires_at)
+        .await
+        .map_err(|e| {
+            error!("Failed to issue WebSocket ticket: {}", e);
+            actix_web::error::ErrorInternalServerError("Failed to issue ticket")
+        })?;
+
+    Ok(HttpResponse::Created().json(ApiResponse::success(TicketResponse {
+        ticket: ticket.id,
+        room_id: ticket.room_id,
+        expires_at: ticket.expires_at,
+    })))
+}
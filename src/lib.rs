@@ -1,3 +1,15 @@
+pub mod config;
+pub mod database;
+pub mod handlers;
+pub mod middleware;
+pub mod models;
+pub mod requests;
+pub mod routes;
+pub mod services;
+pub mod stream_session;
+pub mod utils;
+pub mod ws_outbox;
+pub mod ws_protocol;
+pub mod ws_router;
 pub mod ws_server;
 pub mod ws_session;
-pub mod utils;
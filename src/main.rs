@@ -1,23 +1,19 @@
-        use crate::services::auth::AuthService;
-use actix::Actor;
 use actix_cors::Cors;
 use actix_web::{http, middleware::Logger, web, App, HttpServer};
-use config::settings::AppConfig;
-use database::connection::{create_pool, run_migrations};
 use dotenv::dotenv;
-use rusty_chat::ws_server::ChatServer;
+use rusty_chat::config::settings::AppConfig;
+use rusty_chat::database::connection::{create_pool, run_migrations};
+use rusty_chat::routes;
+use rusty_chat::services::auth::AuthService;
+use rusty_chat::services::cluster::ClusterBus;
+use rusty_chat::services::content_filter::ContentFilter;
+use rusty_chat::services::email_notifications::EmailNotifier;
+use rusty_chat::services::mailer;
+use rusty_chat::services::spam::SpamDetector;
+use rusty_chat::services::webhooks::WebhookQueue;
+use rusty_chat::stream_session::PollRegistry;
+use rusty_chat::ws_router::ChatRouter;
 use tracing::{error, info};
-use tracing_subscriber;
-
-pub mod config;
-pub mod database;
-pub mod handlers;
-pub mod middleware;
-pub mod models;
-pub mod requests;
-pub mod routes;
-pub mod services;
-pub mod utils;
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
@@ -48,13 +44,50 @@ async fn main() -> std::io::Result<()> {
     let server_host = config.server.host.clone();
     let server_port = config.server.port;
 
-    let chat_server = ChatServer::new().start();
+    let cluster = config
+        .cluster
+        .enabled
+        .then(|| ClusterBus::new(pool.clone(), config.cluster.channel.clone()));
+    let webhooks = WebhookQueue::start(pool.clone(), config.webhooks.clone());
+    let mailer = mailer::from_config(&config.mail).unwrap_or_else(|e| {
+        error!("Failed to configure mail transport: {}", e);
+        std::process::exit(1);
+    });
+    // Whether a user is offline is only known per node, so clustered nodes do not email
+    let email = mailer.clone().filter(|_| cluster.is_none()).map(|mailer| {
+        EmailNotifier::start(
+            pool.clone(),
+            mailer,
+            std::time::Duration::from_secs(config.mail.digest_delay_secs),
+        )
+    });
+    let content_filter = ContentFilter::start(pool.clone(), config.moderation.clone())
+        .await
+        .unwrap_or_else(|e| {
+            error!("Failed to load content rules: {}", e);
+            std::process::exit(1);
+        });
+    let chat_router = ChatRouter::start(
+        config.websocket.shards,
+        cluster.clone(),
+        Some(webhooks.clone()),
+        email,
+        SpamDetector::new(&config.moderation),
+        content_filter,
+    );
+    if let Some(cluster) = cluster {
+        cluster.listen(chat_router.clone());
+        info!("Cluster fan-out enabled on channel {}", config.cluster.channel);
+    }
+    info!("Started {} chat server shards", config.websocket.shards);
     let auth_service = AuthService::new().unwrap_or_else(|e| {
         error!("Failed to create auth service: {}", e);
         std::process::exit(1);
     });
     let auth_service = std::sync::Arc::new(auth_service);
 
+    let poll_registry = web::Data::new(PollRegistry::default());
+
     //use: http://localhost:8080/api/v1/users to test
     HttpServer::new(move || {
         let cors = Cors::default()
@@ -62,7 +95,7 @@ async fn main() -> std::io::Result<()> {
             .allowed_origin_fn(|origin, _req_head| {
                 origin.as_bytes().starts_with(b"http://localhost")
             })
-            .allowed_methods(vec!["GET", "POST", "PUT", "DELETE"])
+            .allowed_methods(vec!["GET", "POST", "PUT", "PATCH", "DELETE"])
             .allowed_headers(vec![
                 http::header::AUTHORIZATION,
                 http::header::ACCEPT,
@@ -74,8 +107,11 @@ async fn main() -> std::io::Result<()> {
         App::new()
             .wrap(cors)
             .app_data(web::Data::new(pool.clone()))
-            .app_data(web::Data::new(chat_server.clone()))
+            .app_data(web::Data::new(chat_router.clone()))
+            .app_data(poll_registry.clone())
+            .app_data(web::Data::new(webhooks.clone()))
             .app_data(web::Data::new(config.clone()))
+            .app_data(web::Data::new(mailer.clone()))
             .app_data(web::Data::from(auth_service.clone()))
             .wrap(Logger::default())
             .service(web::scope("/api/v1").configure(routes::api::scoped_config))
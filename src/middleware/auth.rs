@@ -1,23 +1,80 @@
 // middleware/auth.rs
+use crate::database::connection::DbPool;
+use crate::models::api_token::{ApiToken, API_TOKEN_PREFIX};
+use crate::models::user::User;
 use crate::services::auth::AuthService;
 use actix_web::{
     dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
-    error::ErrorUnauthorized,
-    Error, HttpMessage,
+    error::{ErrorInternalServerError, ErrorUnauthorized},
+    web, Error, HttpMessage,
 };
+use chrono::Utc;
 use futures_util::future::{ready, LocalBoxFuture, Ready};
 use std::rc::Rc;
+use tracing::error;
 use uuid::Uuid;
 
+/// How long sessions opened with an API token last before they must reauthenticate
+pub const API_TOKEN_SESSION_SECS: i64 = 24 * 60 * 60;
+
 #[derive(Debug, Clone)]
 pub struct AuthenticatedUser {
     pub user_id: Uuid,
     pub username: String,
+    /// Expiry of the token the request was made with (unix seconds)
+    pub expires_at: i64,
 }
 
 impl AuthenticatedUser {
-    pub fn new(user_id: Uuid, username: String) -> Self {
-        Self { user_id, username }
+    pub fn new(user_id: Uuid, username: String, expires_at: i64) -> Self {
+        Self {
+            user_id,
+            username,
+            expires_at,
+        }
+    }
+
+    /// Whether the account has admin rights, read from the database on every call
+    pub async fn is_admin(&self, pool: &DbPool) -> Result<bool, Error> {
+        let user = User::find_by_id(pool, self.user_id).await.map_err(|e| {
+            error!("Failed to fetch user {}: {}", self.user_id, e);
+            ErrorInternalServerError("Failed to fetch user")
+        })?;
+
+        Ok(user.is_some_and(|user| user.is_admin))
+    }
+}
+
+/// Checks a bearer token, either a JWT or a bot's API token
+pub async fn authenticate(pool: &DbPool, token: &str) -> Result<AuthenticatedUser, Error> {
+    let auth_service = AuthService::new().map_err(|_| ErrorUnauthorized("Authentication service error"))?;
+
+    if token.starts_with(API_TOKEN_PREFIX) {
+        let user = ApiToken::find_user(pool, token)
+            .await
+            .map_err(|e| {
+                error!("Failed to look up API token: {}", e);
+                ErrorInternalServerError("Authentication service error")
+            })?
+            .ok_or_else(|| ErrorUnauthorized("Invalid token"))?;
+
+        // API tokens do not expire, sessions opened with them reauthenticate with the same token
+        Ok(AuthenticatedUser::new(
+            user.id,
+            user.username,
+            Utc::now().timestamp() + API_TOKEN_SESSION_SECS,
+        ))
+    } else {
+        let claims = auth_service
+            .validate_active_token(pool, token)
+            .await
+            .map_err(|e| {
+                error!("Failed to check token revocation: {}", e);
+                ErrorInternalServerError("Authentication service error")
+            })?
+            .ok_or_else(|| ErrorUnauthorized("Invalid token"))?;
+
+        Ok(AuthenticatedUser::new(claims.sub, claims.username, claims.exp))
     }
 }
 
@@ -76,16 +133,12 @@ where
                 }
             };
 
-            // Validate the token
-            let auth_service = AuthService::new()
-                .map_err(|_| ErrorUnauthorized("Authentication service error"))?;
-
-            let claims = auth_service
-                .validate_token(token)
-                .map_err(|_| ErrorUnauthorized("Invalid token"))?;
+            let pool = req
+                .app_data::<web::Data<DbPool>>()
+                .ok_or_else(|| ErrorInternalServerError("Database pool not configured"))?;
+            let authenticated_user = authenticate(pool, token).await?;
 
-            // Create authenticated user and add to request extensions
-            let authenticated_user = AuthenticatedUser::new(claims.sub, claims.username);
+            // Add the authenticated user to request extensions
             req.extensions_mut().insert(authenticated_user);
 
             // Continue with the request
@@ -0,0 +1,80 @@
+use crate::database::connection::DbPool;
+use crate::models::user::User;
+use crate::utils::secrets::{generate_token, hash_token};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+/// Prefix telling API tokens apart from JWTs in the `Authorization` header
+pub const API_TOKEN_PREFIX: &str = "rcb_";
+
+/// A long-lived bearer token of a bot account
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct ApiToken {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub name: String,
+    #[serde(skip_serializing)]
+    pub token_hash: String,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+impl ApiToken {
+    /// Creates a token, returning it along with the plaintext that is never stored
+    pub async fn create(pool: &DbPool, user_id: Uuid, name: String) -> Result<(Self, String), sqlx::Error> {
+        let token = generate_token(API_TOKEN_PREFIX);
+
+        let api_token = sqlx::query_as::<_, ApiToken>(
+            "INSERT INTO api_tokens (id, user_id, name, token_hash, created_at) 
+             VALUES ($1, $2, $3, $4, $5) 
+             RETURNING *",
+        )
+        .bind(Uuid::new_v4())
+        .bind(user_id)
+        .bind(name)
+        .bind(hash_token(&token))
+        .bind(Utc::now())
+        .fetch_one(pool)
+        .await?;
+
+        Ok((api_token, token))
+    }
+
+    pub async fn find_by_user_id(pool: &DbPool, user_id: Uuid) -> Result<Vec<Self>, sqlx::Error> {
+        let tokens = sqlx::query_as::<_, ApiToken>(
+            "SELECT * FROM api_tokens WHERE user_id = $1 ORDER BY created_at DESC",
+        )
+        .bind(user_id)
+        .fetch_all(pool)
+        .await?;
+
+        Ok(tokens)
+    }
+
+    /// The user a token belongs to, recording that it was used
+    pub async fn find_user(pool: &DbPool, token: &str) -> Result<Option<User>, sqlx::Error> {
+        let user = sqlx::query_as::<_, User>(
+            "WITH used AS (
+                 UPDATE api_tokens SET last_used_at = NOW() WHERE token_hash = $1 RETURNING user_id
+             )
+             SELECT users.* FROM users JOIN used ON users.id = used.user_id",
+        )
+        .bind(hash_token(token))
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(user)
+    }
+
+    pub async fn delete(pool: &DbPool, id: Uuid, user_id: Uuid) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query("DELETE FROM api_tokens WHERE id = $1 AND user_id = $2")
+            .bind(id)
+            .bind(user_id)
+            .execute(pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
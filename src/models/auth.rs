@@ -1,4 +1,5 @@
-use chrono::Utc;
+use crate::models::user::User;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
@@ -16,6 +17,30 @@ pub struct RegisterRequest {
     pub password: String,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct VerifyEmailRequest {
+    /// From the link in the verification email
+    pub token: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ForgotPasswordRequest {
+    pub email: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ResetPasswordRequest {
+    /// From the link in the password reset email
+    pub token: String,
+    pub password: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChangePasswordRequest {
+    pub current_password: String,
+    pub new_password: String,
+}
+
 #[derive(Debug, Serialize)]
 pub struct AuthResponse {
     pub token: String,
@@ -28,6 +53,19 @@ pub struct UserInfo {
     pub full_name: String,
     pub username: String,
     pub email: String,
+    pub email_verified: bool,
+}
+
+impl From<User> for UserInfo {
+    fn from(user: User) -> Self {
+        Self {
+            id: user.id,
+            full_name: user.full_name,
+            username: user.username,
+            email: user.email,
+            email_verified: user.email_verified_at.is_some(),
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -36,16 +74,26 @@ pub struct Claims {
     pub username: String,
     pub exp: i64, // expiration time
     pub iat: i64, // issued at
+    /// Microseconds past `iat`, so tokens issued right after a revocation stay valid
+    #[serde(default)]
+    pub iat_us: u32,
 }
 
 impl Claims {
     pub fn new(user_id: Uuid, username: String) -> Self {
-        let now = Utc::now().timestamp();
+        let now = Utc::now();
         Self {
             sub: user_id,
-            username: username,
-            exp: now + (24 * 60 * 60), // 24 hours
-            iat: now,
+            username,
+            exp: now.timestamp() + (24 * 60 * 60), // 24 hours
+            iat: now.timestamp(),
+            iat_us: now.timestamp_subsec_micros(),
         }
     }
+
+    /// When the token was issued, to the microsecond. Tokens from before `iat_us`
+    /// was added count as issued at the start of their second.
+    pub fn issued_at(&self) -> DateTime<Utc> {
+        DateTime::from_timestamp(self.iat, self.iat_us.min(999_999) * 1_000).unwrap_or_default()
+    }
 }
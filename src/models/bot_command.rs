@@ -0,0 +1,102 @@
+use crate::database::connection::DbPool;
+use chrono::{DateTime, Utc};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+/// A slash command answered by a bot's callback URL
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct BotCommand {
+    pub id: Uuid,
+    pub bot_id: Uuid,
+    /// Invoked as `/<name>`
+    pub name: String,
+    pub description: String,
+    pub callback_url: String,
+    /// Key of the HMAC signature sent with each invocation
+    #[serde(skip_serializing)]
+    pub secret: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateBotCommand {
+    pub bot_id: Uuid,
+    pub name: String,
+    pub description: String,
+    pub callback_url: String,
+}
+
+impl BotCommand {
+    pub async fn create(pool: &DbPool, command: CreateBotCommand) -> Result<Self, sqlx::Error> {
+        let mut secret = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut secret);
+
+        let command = sqlx::query_as::<_, BotCommand>(
+            "INSERT INTO bot_commands (id, bot_id, name, description, callback_url, secret, created_at) 
+             VALUES ($1, $2, $3, $4, $5, $6, $7) 
+             RETURNING *",
+        )
+        .bind(Uuid::new_v4())
+        .bind(command.bot_id)
+        .bind(command.name)
+        .bind(command.description)
+        .bind(command.callback_url)
+        .bind(hex::encode(secret))
+        .bind(Utc::now())
+        .fetch_one(pool)
+        .await?;
+
+        Ok(command)
+    }
+
+    /// The command with this name of a bot that is a member of the room
+    pub async fn find_in_room(pool: &DbPool, name: &str, room_id: Uuid) -> Result<Option<Self>, sqlx::Error> {
+        let command = sqlx::query_as::<_, BotCommand>(
+            "SELECT c.* FROM bot_commands c JOIN room_members m ON m.user_id = c.bot_id 
+             WHERE c.name = $1 AND m.room_id = $2",
+        )
+        .bind(name)
+        .bind(room_id)
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(command)
+    }
+
+    /// Commands of the bots that are members of the room
+    pub async fn find_by_room_id(pool: &DbPool, room_id: Uuid) -> Result<Vec<Self>, sqlx::Error> {
+        let commands = sqlx::query_as::<_, BotCommand>(
+            "SELECT c.* FROM bot_commands c JOIN room_members m ON m.user_id = c.bot_id 
+             WHERE m.room_id = $1 ORDER BY c.name ASC",
+        )
+        .bind(room_id)
+        .fetch_all(pool)
+        .await?;
+
+        Ok(commands)
+    }
+
+    pub async fn find_by_bot_id(pool: &DbPool, bot_id: Uuid) -> Result<Vec<Self>, sqlx::Error> {
+        let commands = sqlx::query_as::<_, BotCommand>(
+            "SELECT * FROM bot_commands WHERE bot_id = $1 ORDER BY name ASC",
+        )
+        .bind(bot_id)
+        .fetch_all(pool)
+        .await?;
+
+        Ok(commands)
+    }
+
+    /// Returns whether a command of this bot was deleted
+    pub async fn delete(pool: &DbPool, id: Uuid, bot_id: Uuid) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query("DELETE FROM bot_commands WHERE id = $1 AND bot_id = $2")
+            .bind(id)
+            .bind(bot_id)
+            .execute(pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
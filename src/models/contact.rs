@@ -0,0 +1,24 @@
+use crate::database::connection::DbPool;
+use chrono::Utc;
+use uuid::Uuid;
+
+/// Users who have exchanged direct messages
+pub struct Contact;
+
+impl Contact {
+    /// Records that two users talked, returning whether this is their first conversation
+    pub async fn add(pool: &DbPool, user_id: Uuid, contact_id: Uuid) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            "INSERT INTO user_contacts (user_id, contact_id, created_at) 
+             VALUES ($1, $2, $3), ($2, $1, $3) 
+             ON CONFLICT DO NOTHING",
+        )
+        .bind(user_id)
+        .bind(contact_id)
+        .bind(Utc::now())
+        .execute(pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
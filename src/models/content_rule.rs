@@ -0,0 +1,154 @@
+use crate::database::connection::DbPool;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleKind {
+    /// A whole word, matched case-insensitively
+    Word,
+    Regex,
+    /// Links to a domain or its subdomains
+    Link,
+    /// The longest message accepted, `pattern` is a character count
+    MaxLength,
+}
+
+impl RuleKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RuleKind::Word => "word",
+            RuleKind::Regex => "regex",
+            RuleKind::Link => "link",
+            RuleKind::MaxLength => "max_length",
+        }
+    }
+
+    pub fn parse(kind: &str) -> Option<Self> {
+        match kind {
+            "word" => Some(RuleKind::Word),
+            "regex" => Some(RuleKind::Regex),
+            "link" => Some(RuleKind::Link),
+            "max_length" => Some(RuleKind::MaxLength),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleAction {
+    /// Rejects the message
+    Block,
+    /// Replaces the match with asterisks
+    Mask,
+    /// Lets the message through and queues it for review
+    Flag,
+    /// Link rules only: once a domain is allowed, links elsewhere are blocked
+    Allow,
+}
+
+impl RuleAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RuleAction::Block => "block",
+            RuleAction::Mask => "mask",
+            RuleAction::Flag => "flag",
+            RuleAction::Allow => "allow",
+        }
+    }
+
+    pub fn parse(action: &str) -> Option<Self> {
+        match action {
+            "block" => Some(RuleAction::Block),
+            "mask" => Some(RuleAction::Mask),
+            "flag" => Some(RuleAction::Flag),
+            "allow" => Some(RuleAction::Allow),
+            _ => None,
+        }
+    }
+}
+
+/// A content policy rule of one room, or of every room when `room_id` is `None`
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct ContentRule {
+    pub id: Uuid,
+    pub room_id: Option<Uuid>,
+    pub kind: String,
+    pub pattern: String,
+    pub action: String,
+    pub created_by: Uuid,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone)]
+pub struct CreateContentRule {
+    pub room_id: Option<Uuid>,
+    pub kind: RuleKind,
+    pub pattern: String,
+    pub action: RuleAction,
+    pub created_by: Uuid,
+}
+
+impl ContentRule {
+    pub async fn create(pool: &DbPool, rule: CreateContentRule) -> Result<Self, sqlx::Error> {
+        let rule = sqlx::query_as::<_, ContentRule>(
+            "INSERT INTO content_rules (id, room_id, kind, pattern, action, created_by, created_at) 
+             VALUES ($1, $2, $3, $4, $5, $6, $7) 
+             RETURNING *",
+        )
+        .bind(Uuid::new_v4())
+        .bind(rule.room_id)
+        .bind(rule.kind.as_str())
+        .bind(rule.pattern)
+        .bind(rule.action.as_str())
+        .bind(rule.created_by)
+        .bind(Utc::now())
+        .fetch_one(pool)
+        .await?;
+
+        Ok(rule)
+    }
+
+    /// Every rule, global and per room, for the in-memory policy
+    pub async fn find_all(pool: &DbPool) -> Result<Vec<Self>, sqlx::Error> {
+        let rules = sqlx::query_as::<_, ContentRule>("SELECT * FROM content_rules ORDER BY created_at ASC")
+            .fetch_all(pool)
+            .await?;
+
+        Ok(rules)
+    }
+
+    /// The rules of a room, or the global ones for `None`
+    pub async fn find_by_room_id(pool: &DbPool, room_id: Option<Uuid>) -> Result<Vec<Self>, sqlx::Error> {
+        let rules = sqlx::query_as::<_, ContentRule>(
+            "SELECT * FROM content_rules WHERE room_id IS NOT DISTINCT FROM $1 ORDER BY created_at ASC",
+        )
+        .bind(room_id)
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rules)
+    }
+
+    /// Returns whether the room (or, for `None`, the global list) had the rule
+    pub async fn delete(pool: &DbPool, id: Uuid, room_id: Option<Uuid>) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query("DELETE FROM content_rules WHERE id = $1 AND room_id IS NOT DISTINCT FROM $2")
+            .bind(id)
+            .bind(room_id)
+            .execute(pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub fn kind(&self) -> Option<RuleKind> {
+        RuleKind::parse(&self.kind)
+    }
+
+    pub fn action(&self) -> Option<RuleAction> {
+        RuleAction::parse(&self.action)
+    }
+}
@@ -0,0 +1,48 @@
+use crate::database::connection::DbPool;
+use crate::models::notification::NotificationKind;
+use chrono::Utc;
+use uuid::Uuid;
+
+/// Notification categories a user opted out of receiving emails for
+pub struct EmailOptOut;
+
+impl EmailOptOut {
+    pub async fn find_by_user_id(pool: &DbPool, user_id: Uuid) -> Result<Vec<String>, sqlx::Error> {
+        let categories = sqlx::query_scalar::<_, String>(
+            "SELECT category FROM email_opt_outs WHERE user_id = $1",
+        )
+        .bind(user_id)
+        .fetch_all(pool)
+        .await?;
+
+        Ok(categories)
+    }
+
+    pub async fn set(
+        pool: &DbPool,
+        user_id: Uuid,
+        category: NotificationKind,
+        enabled: bool,
+    ) -> Result<(), sqlx::Error> {
+        if enabled {
+            sqlx::query("DELETE FROM email_opt_outs WHERE user_id = $1 AND category = $2")
+                .bind(user_id)
+                .bind(category.as_str())
+                .execute(pool)
+                .await?;
+        } else {
+            sqlx::query(
+                "INSERT INTO email_opt_outs (user_id, category, created_at) 
+                 VALUES ($1, $2, $3) 
+                 ON CONFLICT DO NOTHING",
+            )
+            .bind(user_id)
+            .bind(category.as_str())
+            .bind(Utc::now())
+            .execute(pool)
+            .await?;
+        }
+
+        Ok(())
+    }
+}
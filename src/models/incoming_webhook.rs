@@ -0,0 +1,89 @@
+use crate::database::connection::DbPool;
+use crate::utils::secrets::{generate_token, hash_token};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+/// A room-scoped URL through which an integration posts as a bot
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct IncomingWebhook {
+    pub id: Uuid,
+    pub room_id: Uuid,
+    pub bot_id: Uuid,
+    pub name: String,
+    #[serde(skip_serializing)]
+    pub token_hash: String,
+    pub created_by: Uuid,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateIncomingWebhook {
+    pub room_id: Uuid,
+    pub bot_id: Uuid,
+    pub name: String,
+    pub created_by: Uuid,
+}
+
+impl IncomingWebhook {
+    /// Creates a webhook, returning it along with the token that is part of its URL
+    pub async fn create(pool: &DbPool, webhook: CreateIncomingWebhook) -> Result<(Self, String), sqlx::Error> {
+        let token = generate_token("");
+
+        let webhook = sqlx::query_as::<_, IncomingWebhook>(
+            "INSERT INTO incoming_webhooks (id, room_id, bot_id, name, token_hash, created_by, created_at) 
+             VALUES ($1, $2, $3, $4, $5, $6, $7) 
+             RETURNING *",
+        )
+        .bind(Uuid::new_v4())
+        .bind(webhook.room_id)
+        .bind(webhook.bot_id)
+        .bind(webhook.name)
+        .bind(hash_token(&token))
+        .bind(webhook.created_by)
+        .bind(Utc::now())
+        .fetch_one(pool)
+        .await?;
+
+        Ok((webhook, token))
+    }
+
+    /// The webhook matching both the id and the token of its URL
+    pub async fn find_by_credentials(
+        pool: &DbPool,
+        id: Uuid,
+        token: &str,
+    ) -> Result<Option<Self>, sqlx::Error> {
+        let webhook = sqlx::query_as::<_, IncomingWebhook>(
+            "SELECT * FROM incoming_webhooks WHERE id = $1 AND token_hash = $2",
+        )
+        .bind(id)
+        .bind(hash_token(token))
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(webhook)
+    }
+
+    pub async fn find_by_room_id(pool: &DbPool, room_id: Uuid) -> Result<Vec<Self>, sqlx::Error> {
+        let webhooks = sqlx::query_as::<_, IncomingWebhook>(
+            "SELECT * FROM incoming_webhooks WHERE room_id = $1 ORDER BY created_at DESC",
+        )
+        .bind(room_id)
+        .fetch_all(pool)
+        .await?;
+
+        Ok(webhooks)
+    }
+
+    pub async fn delete(pool: &DbPool, id: Uuid, room_id: Uuid) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query("DELETE FROM incoming_webhooks WHERE id = $1 AND room_id = $2")
+            .bind(id)
+            .bind(room_id)
+            .execute(pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
@@ -0,0 +1,89 @@
+use crate::database::connection::DbPool;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MentionKind {
+    /// `@username`
+    User,
+    /// `@here`, members who are online
+    Here,
+    /// `@room`, every member
+    Room,
+}
+
+impl MentionKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MentionKind::User => "user",
+            MentionKind::Here => "here",
+            MentionKind::Room => "room",
+        }
+    }
+}
+
+/// Where a mention sits in a message's content, in characters
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MentionSpan {
+    pub kind: MentionKind,
+    pub start: usize,
+    pub end: usize,
+    /// The mentioned user, for `@username` mentions
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_id: Option<Uuid>,
+}
+
+/// A user mentioned in a message
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct MessageMention {
+    pub message_id: Uuid,
+    pub user_id: Uuid,
+    pub room_id: Uuid,
+    pub kind: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl MessageMention {
+    /// Records the users mentioned in a message, keeping the first kind a user was mentioned by
+    pub async fn create_many(
+        pool: &DbPool,
+        message_id: Uuid,
+        room_id: Uuid,
+        mentions: &[(Uuid, MentionKind)],
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        let user_ids: Vec<Uuid> = mentions.iter().map(|(user_id, _)| *user_id).collect();
+        let kinds: Vec<&str> = mentions.iter().map(|(_, kind)| kind.as_str()).collect();
+
+        let mentions = sqlx::query_as::<_, MessageMention>(
+            "INSERT INTO message_mentions (message_id, user_id, room_id, kind, created_at)
+             SELECT $1, user_id, $2, kind, $3 FROM UNNEST($4::uuid[], $5::text[]) AS m(user_id, kind)
+             ON CONFLICT (message_id, user_id) DO NOTHING
+             RETURNING *",
+        )
+        .bind(message_id)
+        .bind(room_id)
+        .bind(Utc::now())
+        .bind(user_ids)
+        .bind(kinds)
+        .fetch_all(pool)
+        .await?;
+
+        Ok(mentions)
+    }
+
+    /// A user's most recent mentions
+    pub async fn find_by_user_id(pool: &DbPool, user_id: Uuid, limit: i64) -> Result<Vec<Self>, sqlx::Error> {
+        let mentions = sqlx::query_as::<_, MessageMention>(
+            "SELECT * FROM message_mentions WHERE user_id = $1 ORDER BY created_at DESC LIMIT $2",
+        )
+        .bind(user_id)
+        .bind(limit)
+        .fetch_all(pool)
+        .await?;
+
+        Ok(mentions)
+    }
+}
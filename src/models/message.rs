@@ -1,16 +1,35 @@
 use crate::database::connection::DbPool;
+use crate::models::mention::MentionSpan;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 use uuid::Uuid;
 
+/// Most messages replayed to a reconnecting session, older missed messages are skipped
+pub const MAX_REPLAY: usize = 500;
+
+/// Name and avatar shown instead of the sender's, set by integrations posting as a bot
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, FromRow)]
+pub struct DisplayOverride {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub avatar_url: Option<String>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct Message {
     pub id: Uuid,
     pub room_id: Uuid,
     pub sender_id: Uuid,
-    pub recipient_id: Uuid,
+    pub recipient_id: Option<Uuid>,
     pub content: String,
+    pub seq: i64,
+    #[sqlx(flatten)]
+    #[serde(flatten)]
+    pub display: DisplayOverride,
+    #[sqlx(json)]
+    pub mentions: Vec<MentionSpan>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
@@ -19,16 +38,22 @@ pub struct Message {
 pub struct CreateMessage {
     pub room_id: Uuid,
     pub sender_id: Uuid,
-    pub recipient_id: Uuid,
+    pub recipient_id: Option<Uuid>,
     pub content: String,
+    pub display: DisplayOverride,
+    pub mentions: Vec<MentionSpan>,
 }
 
 impl Message {
-    pub async fn create(pool: &DbPool, message: CreateMessage) -> Result<Self, sqlx::Error> {
+    /// Stores a message with the room's next sequence number, `None` if the room is not in the database
+    pub async fn create(pool: &DbPool, message: CreateMessage) -> Result<Option<Self>, sqlx::Error> {
         let now = Utc::now();
         let message = sqlx::query_as::<_, Message>(
-            "INSERT INTO messages (id, room_id, sender_id, recipient_id, content, created_at, updated_at) 
-             VALUES ($1, $2, $3, $4, $5, $6, $7) 
+            "WITH next AS (
+                 UPDATE rooms SET last_seq = last_seq + 1 WHERE id = $2 RETURNING last_seq
+             )
+             INSERT INTO messages (id, room_id, sender_id, recipient_id, content, seq, display_name, avatar_url, mentions, created_at, updated_at) 
+             SELECT $1, $2, $3, $4, $5, next.last_seq, $6, $7, $8, $9, $10 FROM next 
              RETURNING *",
         )
         .bind(Uuid::new_v4())
@@ -36,9 +61,12 @@ impl Message {
         .bind(message.sender_id)
         .bind(message.recipient_id)
         .bind(message.content)
+        .bind(message.display.display_name)
+        .bind(message.display.avatar_url)
+        .bind(sqlx::types::Json(message.mentions))
         .bind(now)
         .bind(now)
-        .fetch_one(pool)
+        .fetch_optional(pool)
         .await?;
 
         Ok(message)
@@ -53,6 +81,16 @@ impl Message {
         Ok(message)
     }
 
+    /// Returns whether the message existed
+    pub async fn delete(pool: &DbPool, id: Uuid) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query("DELETE FROM messages WHERE id = $1")
+            .bind(id)
+            .execute(pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
     pub async fn find_all(pool: &DbPool) -> Result<Vec<Self>, sqlx::Error> {
         let messages =
             sqlx::query_as::<_, Message>("SELECT * FROM messages ORDER BY created_at DESC")
@@ -73,6 +111,70 @@ impl Message {
         Ok(messages)
     }
 
+    /// The latest `limit` messages of a room with a sequence number greater than `after_seq`,
+    /// oldest first, leaving out senders `viewer_id` blocked with their messages hidden
+    pub async fn find_by_room_id_after_seq(
+        pool: &DbPool,
+        room_id: Uuid,
+        after_seq: i64,
+        viewer_id: Uuid,
+        limit: i64,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        let messages = sqlx::query_as::<_, Message>(
+            "SELECT * FROM (
+                 SELECT * FROM messages WHERE room_id = $1 AND seq > $2 
+                 AND sender_id NOT IN (
+                     SELECT blocked_id FROM user_blocks WHERE blocker_id = $3 AND hide_messages
+                 )
+                 ORDER BY seq DESC 
+                 LIMIT $4
+             ) latest 
+             ORDER BY seq ASC",
+        )
+        .bind(room_id)
+        .bind(after_seq)
+        .bind(viewer_id)
+        .bind(limit)
+        .fetch_all(pool)
+        .await?;
+
+        Ok(messages)
+    }
+
+    /// The latest `limit` messages of the given rooms stored after `anchor`, oldest first.
+    /// The anchor's own room is compared by sequence number, the others by creation time.
+    /// Senders `viewer_id` blocked with their messages hidden are left out.
+    pub async fn find_in_rooms_after(
+        pool: &DbPool,
+        room_ids: &[Uuid],
+        anchor: &Message,
+        viewer_id: Uuid,
+        limit: i64,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        let messages = sqlx::query_as::<_, Message>(
+            "SELECT * FROM (
+                 SELECT * FROM messages WHERE room_id = ANY($1)
+                 AND ((room_id = $2 AND seq > $3) OR (room_id <> $2 AND created_at > $4))
+                 AND sender_id NOT IN (
+                     SELECT blocked_id FROM user_blocks WHERE blocker_id = $5 AND hide_messages
+                 )
+                 ORDER BY created_at DESC, seq DESC 
+                 LIMIT $6
+             ) latest 
+             ORDER BY created_at ASC, seq ASC",
+        )
+        .bind(room_ids)
+        .bind(anchor.room_id)
+        .bind(anchor.seq)
+        .bind(anchor.created_at)
+        .bind(viewer_id)
+        .bind(limit)
+        .fetch_all(pool)
+        .await?;
+
+        Ok(messages)
+    }
+
     pub async fn find_by_recipient_id(
         pool: &DbPool,
         recipient_id: Uuid,
@@ -0,0 +1,265 @@
+use crate::database::connection::DbPool;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportCategory {
+    Spam,
+    Harassment,
+    Hate,
+    Violence,
+    Sexual,
+    SelfHarm,
+    Other,
+}
+
+impl ReportCategory {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReportCategory::Spam => "spam",
+            ReportCategory::Harassment => "harassment",
+            ReportCategory::Hate => "hate",
+            ReportCategory::Violence => "violence",
+            ReportCategory::Sexual => "sexual",
+            ReportCategory::SelfHarm => "self_harm",
+            ReportCategory::Other => "other",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportStatus {
+    Open,
+    /// A moderator is looking into it
+    Claimed,
+    Resolved,
+}
+
+impl ReportStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReportStatus::Open => "open",
+            ReportStatus::Claimed => "claimed",
+            ReportStatus::Resolved => "resolved",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportResolution {
+    Dismissed,
+    MessageDeleted,
+    UserSanctioned,
+}
+
+impl ReportResolution {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReportResolution::Dismissed => "dismissed",
+            ReportResolution::MessageDeleted => "message_deleted",
+            ReportResolution::UserSanctioned => "user_sanctioned",
+        }
+    }
+}
+
+/// A user's report of a room message, queued for the room's owner and admins
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct MessageReport {
+    pub id: Uuid,
+    /// `None` once the message is deleted
+    pub message_id: Option<Uuid>,
+    pub room_id: Uuid,
+    pub sender_id: Uuid,
+    /// The message's content when it was reported
+    pub content: String,
+    /// `None` when a content rule flagged the message
+    pub reporter_id: Option<Uuid>,
+    pub category: String,
+    pub details: Option<String>,
+    pub status: String,
+    pub claimed_by: Option<Uuid>,
+    pub claimed_at: Option<DateTime<Utc>>,
+    pub resolution: Option<String>,
+    pub resolved_by: Option<Uuid>,
+    pub resolved_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone)]
+pub struct CreateMessageReport {
+    pub message_id: Uuid,
+    pub room_id: Uuid,
+    pub sender_id: Uuid,
+    pub content: String,
+    pub reporter_id: Option<Uuid>,
+    pub category: ReportCategory,
+    pub details: Option<String>,
+}
+
+impl MessageReport {
+    /// Files a report, failing with a unique violation if the user already reported the message
+    pub async fn create(pool: &DbPool, report: CreateMessageReport) -> Result<Self, sqlx::Error> {
+        let report = sqlx::query_as::<_, MessageReport>(
+            "INSERT INTO message_reports (id, message_id, room_id, sender_id, content, reporter_id, category, details, created_at) 
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9) 
+             RETURNING *",
+        )
+        .bind(Uuid::new_v4())
+        .bind(report.message_id)
+        .bind(report.room_id)
+        .bind(report.sender_id)
+        .bind(report.content)
+        .bind(report.reporter_id)
+        .bind(report.category.as_str())
+        .bind(report.details)
+        .bind(Utc::now())
+        .fetch_one(pool)
+        .await?;
+
+        Ok(report)
+    }
+
+    pub async fn find_by_id(pool: &DbPool, id: Uuid) -> Result<Option<Self>, sqlx::Error> {
+        let report = sqlx::query_as::<_, MessageReport>("SELECT * FROM message_reports WHERE id = $1")
+            .bind(id)
+            .fetch_optional(pool)
+            .await?;
+
+        Ok(report)
+    }
+
+    /// Reports a moderator may review, oldest first: every report for admins,
+    /// reports in rooms they own otherwise
+    pub async fn find_queue(
+        pool: &DbPool,
+        moderator_id: Uuid,
+        is_admin: bool,
+        status: Option<ReportStatus>,
+        room_id: Option<Uuid>,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        let reports = sqlx::query_as::<_, MessageReport>(
+            "SELECT * FROM message_reports 
+             WHERE ($2 OR room_id IN (SELECT id FROM rooms WHERE created_by = $1)) 
+             AND ($3::text IS NULL OR status = $3) 
+             AND ($4::uuid IS NULL OR room_id = $4) 
+             ORDER BY created_at ASC 
+             LIMIT $5 OFFSET $6",
+        )
+        .bind(moderator_id)
+        .bind(is_admin)
+        .bind(status.map(|status| status.as_str()))
+        .bind(room_id)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(pool)
+        .await?;
+
+        Ok(reports)
+    }
+
+    /// Assigns an open report to a moderator, `None` if it is resolved or claimed by someone else
+    pub async fn claim(pool: &DbPool, id: Uuid, moderator_id: Uuid) -> Result<Option<Self>, sqlx::Error> {
+        let report = sqlx::query_as::<_, MessageReport>(
+            "UPDATE message_reports SET status = 'claimed', claimed_by = $2, claimed_at = $3 
+             WHERE id = $1 AND (status = 'open' OR (status = 'claimed' AND claimed_by = $2)) 
+             RETURNING *",
+        )
+        .bind(id)
+        .bind(moderator_id)
+        .bind(Utc::now())
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(report)
+    }
+
+    /// Closes a report, `None` if it is already resolved
+    pub async fn resolve(
+        pool: &DbPool,
+        id: Uuid,
+        moderator_id: Uuid,
+        resolution: ReportResolution,
+    ) -> Result<Option<Self>, sqlx::Error> {
+        let report = sqlx::query_as::<_, MessageReport>(
+            "UPDATE message_reports SET status = 'resolved', resolution = $3, resolved_by = $2, resolved_at = $4 
+             WHERE id = $1 AND status <> 'resolved' 
+             RETURNING *",
+        )
+        .bind(id)
+        .bind(moderator_id)
+        .bind(resolution.as_str())
+        .bind(Utc::now())
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(report)
+    }
+
+    /// Closes every other unresolved report of a message, e.g. once it is deleted
+    pub async fn resolve_by_message_id(
+        pool: &DbPool,
+        message_id: Uuid,
+        moderator_id: Uuid,
+        resolution: ReportResolution,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        let reports = sqlx::query_as::<_, MessageReport>(
+            "UPDATE message_reports SET status = 'resolved', resolution = $3, resolved_by = $2, resolved_at = $4 
+             WHERE message_id = $1 AND status <> 'resolved' 
+             RETURNING *",
+        )
+        .bind(message_id)
+        .bind(moderator_id)
+        .bind(resolution.as_str())
+        .bind(Utc::now())
+        .fetch_all(pool)
+        .await?;
+
+        Ok(reports)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct ReportNote {
+    pub id: Uuid,
+    pub report_id: Uuid,
+    pub author_id: Uuid,
+    pub body: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl ReportNote {
+    pub async fn create(pool: &DbPool, report_id: Uuid, author_id: Uuid, body: String) -> Result<Self, sqlx::Error> {
+        let note = sqlx::query_as::<_, ReportNote>(
+            "INSERT INTO report_notes (id, report_id, author_id, body, created_at) 
+             VALUES ($1, $2, $3, $4, $5) 
+             RETURNING *",
+        )
+        .bind(Uuid::new_v4())
+        .bind(report_id)
+        .bind(author_id)
+        .bind(body)
+        .bind(Utc::now())
+        .fetch_one(pool)
+        .await?;
+
+        Ok(note)
+    }
+
+    pub async fn find_by_report_id(pool: &DbPool, report_id: Uuid) -> Result<Vec<Self>, sqlx::Error> {
+        let notes = sqlx::query_as::<_, ReportNote>(
+            "SELECT * FROM report_notes WHERE report_id = $1 ORDER BY created_at ASC",
+        )
+        .bind(report_id)
+        .fetch_all(pool)
+        .await?;
+
+        Ok(notes)
+    }
+}
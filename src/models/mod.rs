@@ -1,4 +1,19 @@
+pub mod api_token;
 pub mod auth;
+pub mod bot_command;
+pub mod contact;
+pub mod content_rule;
+pub mod email_opt_out;
+pub mod incoming_webhook;
+pub mod mention;
+pub mod message_report;
 pub mod message;
+pub mod notification;
+pub mod password_reset;
 pub mod room;
+pub mod room_member;
+pub mod room_sanction;
 pub mod user;
+pub mod user_block;
+pub mod webhook;
+pub mod ws_ticket;
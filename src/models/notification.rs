@@ -0,0 +1,137 @@
+use crate::database::connection::DbPool;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationKind {
+    Mention,
+    /// A direct message from someone the user has not talked to before
+    DirectMessage,
+    RoomInvite,
+    /// The user was warned, muted, kicked or banned in a room, or such a sanction was lifted
+    RoleChange,
+    /// A moderator resolved a message the user reported
+    ReportResolved,
+}
+
+impl NotificationKind {
+    pub const ALL: [NotificationKind; 5] = [
+        NotificationKind::Mention,
+        NotificationKind::DirectMessage,
+        NotificationKind::RoomInvite,
+        NotificationKind::RoleChange,
+        NotificationKind::ReportResolved,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NotificationKind::Mention => "mention",
+            NotificationKind::DirectMessage => "direct_message",
+            NotificationKind::RoomInvite => "room_invite",
+            NotificationKind::RoleChange => "role_change",
+            NotificationKind::ReportResolved => "report_resolved",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Notification {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub kind: String,
+    /// Kind-specific details, e.g. the room and message of a mention
+    pub data: serde_json::Value,
+    pub read_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl Notification {
+    pub async fn create(
+        pool: &DbPool,
+        user_id: Uuid,
+        kind: NotificationKind,
+        data: serde_json::Value,
+    ) -> Result<Self, sqlx::Error> {
+        let notification = sqlx::query_as::<_, Notification>(
+            "INSERT INTO notifications (id, user_id, kind, data, created_at) 
+             VALUES ($1, $2, $3, $4, $5) 
+             RETURNING *",
+        )
+        .bind(Uuid::new_v4())
+        .bind(user_id)
+        .bind(kind.as_str())
+        .bind(data)
+        .bind(Utc::now())
+        .fetch_one(pool)
+        .await?;
+
+        Ok(notification)
+    }
+
+    /// A page of a user's notifications, newest first
+    pub async fn find_by_user_id(
+        pool: &DbPool,
+        user_id: Uuid,
+        unread_only: bool,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        let notifications = sqlx::query_as::<_, Notification>(
+            "SELECT * FROM notifications 
+             WHERE user_id = $1 AND (NOT $2 OR read_at IS NULL) 
+             ORDER BY created_at DESC 
+             LIMIT $3 OFFSET $4",
+        )
+        .bind(user_id)
+        .bind(unread_only)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(pool)
+        .await?;
+
+        Ok(notifications)
+    }
+
+    pub async fn count_unread(pool: &DbPool, user_id: Uuid) -> Result<i64, sqlx::Error> {
+        let count = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM notifications WHERE user_id = $1 AND read_at IS NULL",
+        )
+        .bind(user_id)
+        .fetch_one(pool)
+        .await?;
+
+        Ok(count)
+    }
+
+    /// Marks one of the user's notifications read, `None` if there is no such notification
+    pub async fn mark_read(pool: &DbPool, id: Uuid, user_id: Uuid) -> Result<Option<Self>, sqlx::Error> {
+        let notification = sqlx::query_as::<_, Notification>(
+            "UPDATE notifications SET read_at = COALESCE(read_at, $3) 
+             WHERE id = $1 AND user_id = $2 
+             RETURNING *",
+        )
+        .bind(id)
+        .bind(user_id)
+        .bind(Utc::now())
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(notification)
+    }
+
+    /// Returns how many notifications were unread
+    pub async fn mark_all_read(pool: &DbPool, user_id: Uuid) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query(
+            "UPDATE notifications SET read_at = $2 WHERE user_id = $1 AND read_at IS NULL",
+        )
+        .bind(user_id)
+        .bind(Utc::now())
+        .execute(pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+}
@@ -0,0 +1,71 @@
+use crate::database::connection::DbPool;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+/// A pending password reset, redeemed with the token emailed to the user
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct PasswordReset {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    #[serde(skip_serializing)]
+    pub token_hash: String,
+    pub expires_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl PasswordReset {
+    pub async fn create(
+        pool: &DbPool,
+        user_id: Uuid,
+        token_hash: &str,
+        ttl: Duration,
+    ) -> Result<Self, sqlx::Error> {
+        // Resets that were never used are cleaned up as new ones are requested
+        sqlx::query("DELETE FROM password_resets WHERE expires_at < NOW()")
+            .execute(pool)
+            .await?;
+
+        let now = Utc::now();
+        let reset = sqlx::query_as::<_, PasswordReset>(
+            "INSERT INTO password_resets (id, user_id, token_hash, expires_at, created_at) 
+             VALUES ($1, $2, $3, $4, $5) 
+             RETURNING *",
+        )
+        .bind(Uuid::new_v4())
+        .bind(user_id)
+        .bind(token_hash)
+        .bind(now + ttl)
+        .bind(now)
+        .fetch_one(pool)
+        .await?;
+
+        Ok(reset)
+    }
+
+    /// Consumes a reset, returning it only if it existed and had not expired
+    pub async fn redeem(pool: &DbPool, token_hash: &str) -> Result<Option<Self>, sqlx::Error> {
+        let reset = sqlx::query_as::<_, PasswordReset>(
+            "WITH redeemed AS (
+                 DELETE FROM password_resets WHERE token_hash = $1 RETURNING *
+             )
+             SELECT * FROM redeemed WHERE expires_at > NOW()",
+        )
+        .bind(token_hash)
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(reset)
+    }
+
+    /// Drops every pending reset of a user, once their password changed
+    pub async fn delete_by_user_id(pool: &DbPool, user_id: Uuid) -> Result<(), sqlx::Error> {
+        sqlx::query("DELETE FROM password_resets WHERE user_id = $1")
+            .bind(user_id)
+            .execute(pool)
+            .await?;
+
+        Ok(())
+    }
+}
@@ -10,6 +10,8 @@ pub struct Room {
     pub name: String,
     pub created_by: Uuid,
     pub is_private: bool,
+    pub last_seq: i64,
+    pub topic: Option<String>,
     pub created_at: DateTime<Utc>,
 }
 
@@ -20,13 +22,27 @@ pub struct CreateRoom {
     pub is_private: bool,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpdateRoom {
+    pub name: Option<String>,
+    pub is_private: Option<bool>,
+    pub topic: Option<String>,
+}
+
 impl Room {
+    /// Creates a room with its creator as the first member
     pub async fn create(pool: &DbPool, room: CreateRoom) -> Result<Self, sqlx::Error> {
         let now = Utc::now();
         let room = sqlx::query_as::<_, Room>(
-            "INSERT INTO rooms (id, name, created_by, is_private, created_at) 
-             VALUES ($1, $2, $3, $4, $5) 
-             RETURNING *",
+            "WITH room AS (
+                 INSERT INTO rooms (id, name, created_by, is_private, created_at) 
+                 VALUES ($1, $2, $3, $4, $5) 
+                 RETURNING *
+             ), member AS (
+                 INSERT INTO room_members (room_id, user_id, joined_at) 
+                 SELECT id, created_by, created_at FROM room
+             )
+             SELECT * FROM room",
         )
         .bind(Uuid::new_v4())
         .bind(room.name)
@@ -55,4 +71,21 @@ impl Room {
 
         Ok(rooms)
     }
+
+    pub async fn update(pool: &DbPool, id: Uuid, room: UpdateRoom) -> Result<Option<Self>, sqlx::Error> {
+        let room = sqlx::query_as::<_, Room>(
+            "UPDATE rooms SET name = COALESCE($2, name), is_private = COALESCE($3, is_private), 
+             topic = COALESCE($4, topic) 
+             WHERE id = $1 
+             RETURNING *",
+        )
+        .bind(id)
+        .bind(room.name)
+        .bind(room.is_private)
+        .bind(room.topic)
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(room)
+    }
 }
@@ -0,0 +1,119 @@
+use crate::database::connection::DbPool;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+/// A user's membership of a room, kept while they are disconnected
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct RoomMember {
+    pub room_id: Uuid,
+    pub user_id: Uuid,
+    pub muted: bool,
+    pub invited_by: Option<Uuid>,
+    pub joined_at: DateTime<Utc>,
+}
+
+impl RoomMember {
+    /// Adds a member, `None` if they already were one
+    pub async fn add(
+        pool: &DbPool,
+        room_id: Uuid,
+        user_id: Uuid,
+        invited_by: Option<Uuid>,
+    ) -> Result<Option<Self>, sqlx::Error> {
+        let member = sqlx::query_as::<_, RoomMember>(
+            "INSERT INTO room_members (room_id, user_id, invited_by, joined_at) 
+             VALUES ($1, $2, $3, $4) 
+             ON CONFLICT (room_id, user_id) DO NOTHING 
+             RETURNING *",
+        )
+        .bind(room_id)
+        .bind(user_id)
+        .bind(invited_by)
+        .bind(Utc::now())
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(member)
+    }
+
+    pub async fn find(pool: &DbPool, room_id: Uuid, user_id: Uuid) -> Result<Option<Self>, sqlx::Error> {
+        let member = sqlx::query_as::<_, RoomMember>(
+            "SELECT * FROM room_members WHERE room_id = $1 AND user_id = $2",
+        )
+        .bind(room_id)
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(member)
+    }
+
+    /// Whether the user may read the room: it is public, not in the database, or they are a member
+    pub async fn may_read(pool: &DbPool, room_id: Uuid, user_id: Uuid) -> Result<bool, sqlx::Error> {
+        let readable = sqlx::query_scalar::<_, bool>(
+            "SELECT NOT EXISTS (
+                 SELECT 1 FROM rooms r WHERE r.id = $1 AND r.is_private AND NOT EXISTS (
+                     SELECT 1 FROM room_members m WHERE m.room_id = r.id AND m.user_id = $2
+                 )
+             )",
+        )
+        .bind(room_id)
+        .bind(user_id)
+        .fetch_one(pool)
+        .await?;
+
+        Ok(readable)
+    }
+
+    pub async fn find_by_room_id(pool: &DbPool, room_id: Uuid) -> Result<Vec<Self>, sqlx::Error> {
+        let members = sqlx::query_as::<_, RoomMember>(
+            "SELECT * FROM room_members WHERE room_id = $1 ORDER BY joined_at ASC",
+        )
+        .bind(room_id)
+        .fetch_all(pool)
+        .await?;
+
+        Ok(members)
+    }
+
+    /// Mutes or unmutes a room for a member, `None` if the user is not one
+    pub async fn set_muted(
+        pool: &DbPool,
+        room_id: Uuid,
+        user_id: Uuid,
+        muted: bool,
+    ) -> Result<Option<Self>, sqlx::Error> {
+        let member = sqlx::query_as::<_, RoomMember>(
+            "UPDATE room_members SET muted = $3 WHERE room_id = $1 AND user_id = $2 RETURNING *",
+        )
+        .bind(room_id)
+        .bind(user_id)
+        .bind(muted)
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(member)
+    }
+
+    pub async fn find_room_ids_by_user_id(pool: &DbPool, user_id: Uuid) -> Result<Vec<Uuid>, sqlx::Error> {
+        let room_ids = sqlx::query_scalar::<_, Uuid>("SELECT room_id FROM room_members WHERE user_id = $1")
+            .bind(user_id)
+            .fetch_all(pool)
+            .await?;
+
+        Ok(room_ids)
+    }
+
+    /// Returns whether the user was a member
+    pub async fn remove(pool: &DbPool, room_id: Uuid, user_id: Uuid) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query("DELETE FROM room_members WHERE room_id = $1 AND user_id = $2")
+            .bind(room_id)
+            .bind(user_id)
+            .execute(pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
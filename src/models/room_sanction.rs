@@ -0,0 +1,147 @@
+use crate::database::connection::DbPool;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SanctionKind {
+    /// Only recorded and announced, never in force
+    Warn,
+    /// Can read the room but not post
+    Mute,
+    /// Removed from the room, and kept out until it expires
+    Kick,
+    /// Removed from the room and kept out until it expires or is lifted
+    Ban,
+}
+
+impl SanctionKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SanctionKind::Warn => "warn",
+            SanctionKind::Mute => "mute",
+            SanctionKind::Kick => "kick",
+            SanctionKind::Ban => "ban",
+        }
+    }
+
+    pub fn parse(kind: &str) -> Option<Self> {
+        match kind {
+            "warn" => Some(SanctionKind::Warn),
+            "mute" => Some(SanctionKind::Mute),
+            "kick" => Some(SanctionKind::Kick),
+            "ban" => Some(SanctionKind::Ban),
+            _ => None,
+        }
+    }
+
+    /// Whether the user is removed from the room rather than only silenced
+    pub fn removes(&self) -> bool {
+        matches!(self, SanctionKind::Kick | SanctionKind::Ban)
+    }
+}
+
+/// A moderator action against a room member, in force until `expires_at` unless lifted
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct RoomSanction {
+    pub id: Uuid,
+    pub room_id: Uuid,
+    pub user_id: Uuid,
+    pub kind: String,
+    pub reason: Option<String>,
+    /// `None` when spam detection issued it
+    pub issued_by: Option<Uuid>,
+    /// `None` until lifted, a warning or a kick without a duration expires as it is issued
+    pub expires_at: Option<DateTime<Utc>>,
+    pub lifted_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone)]
+pub struct CreateRoomSanction {
+    pub room_id: Uuid,
+    pub user_id: Uuid,
+    pub kind: SanctionKind,
+    pub reason: Option<String>,
+    pub issued_by: Option<Uuid>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl RoomSanction {
+    pub async fn create(pool: &DbPool, sanction: CreateRoomSanction) -> Result<Self, sqlx::Error> {
+        let sanction = sqlx::query_as::<_, RoomSanction>(
+            "INSERT INTO room_sanctions (id, room_id, user_id, kind, reason, issued_by, expires_at, created_at) 
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8) 
+             RETURNING *",
+        )
+        .bind(Uuid::new_v4())
+        .bind(sanction.room_id)
+        .bind(sanction.user_id)
+        .bind(sanction.kind.as_str())
+        .bind(sanction.reason)
+        .bind(sanction.issued_by)
+        .bind(sanction.expires_at)
+        .bind(Utc::now())
+        .fetch_one(pool)
+        .await?;
+
+        Ok(sanction)
+    }
+
+    /// Sanctions in force against a user in a room
+    pub async fn find_active(pool: &DbPool, room_id: Uuid, user_id: Uuid) -> Result<Vec<Self>, sqlx::Error> {
+        let sanctions = sqlx::query_as::<_, RoomSanction>(
+            "SELECT * FROM room_sanctions 
+             WHERE room_id = $1 AND user_id = $2 AND lifted_at IS NULL 
+             AND (expires_at IS NULL OR expires_at > $3) 
+             ORDER BY created_at DESC",
+        )
+        .bind(room_id)
+        .bind(user_id)
+        .bind(Utc::now())
+        .fetch_all(pool)
+        .await?;
+
+        Ok(sanctions)
+    }
+
+    /// Sanctions in force in a room, newest first
+    pub async fn find_active_by_room_id(pool: &DbPool, room_id: Uuid) -> Result<Vec<Self>, sqlx::Error> {
+        let sanctions = sqlx::query_as::<_, RoomSanction>(
+            "SELECT * FROM room_sanctions 
+             WHERE room_id = $1 AND lifted_at IS NULL 
+             AND (expires_at IS NULL OR expires_at > $2) 
+             ORDER BY created_at DESC",
+        )
+        .bind(room_id)
+        .bind(Utc::now())
+        .fetch_all(pool)
+        .await?;
+
+        Ok(sanctions)
+    }
+
+    /// Lifts a sanction before it expires, `None` if there is no such sanction in force
+    pub async fn lift(pool: &DbPool, room_id: Uuid, id: Uuid) -> Result<Option<Self>, sqlx::Error> {
+        let now = Utc::now();
+        let sanction = sqlx::query_as::<_, RoomSanction>(
+            "UPDATE room_sanctions SET lifted_at = $3 
+             WHERE id = $1 AND room_id = $2 AND lifted_at IS NULL 
+             AND (expires_at IS NULL OR expires_at > $3) 
+             RETURNING *",
+        )
+        .bind(id)
+        .bind(room_id)
+        .bind(now)
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(sanction)
+    }
+
+    pub fn kind(&self) -> Option<SanctionKind> {
+        SanctionKind::parse(&self.kind)
+    }
+}
@@ -18,12 +18,124 @@ pub struct User {
     pub full_name: String,
     pub username: String,
     pub email: String,
+    #[serde(skip_serializing)]
     pub password: String,
     pub status: OnlineStatus,
+    pub is_admin: bool,
+    pub is_bot: bool,
+    /// The user managing a bot account
+    pub owner_id: Option<Uuid>,
+    /// When the user proved they own `email`, `None` until then
+    pub email_verified_at: Option<DateTime<Utc>>,
+    /// Shown instead of `full_name` when set
+    pub display_name: Option<String>,
+    pub avatar_url: Option<String>,
+    pub bio: Option<String>,
+    pub pronouns: Option<String>,
+    pub timezone: Option<String>,
+    pub locale: Option<String>,
+    /// Address the user is changing to, `email` is replaced once it is verified
+    pub pending_email: Option<String>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
 
+/// The profile as the user sees it, without credentials
+#[derive(Debug, Clone, Serialize)]
+pub struct UserProfile {
+    pub id: Uuid,
+    pub full_name: String,
+    pub username: String,
+    pub email: String,
+    pub email_verified: bool,
+    pub pending_email: Option<String>,
+    pub display_name: Option<String>,
+    pub avatar_url: Option<String>,
+    pub bio: Option<String>,
+    pub pronouns: Option<String>,
+    pub timezone: Option<String>,
+    pub locale: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<User> for UserProfile {
+    fn from(user: User) -> Self {
+        Self {
+            id: user.id,
+            full_name: user.full_name,
+            username: user.username,
+            email: user.email,
+            email_verified: user.email_verified_at.is_some(),
+            pending_email: user.pending_email,
+            display_name: user.display_name,
+            avatar_url: user.avatar_url,
+            bio: user.bio,
+            pronouns: user.pronouns,
+            timezone: user.timezone,
+            locale: user.locale,
+            created_at: user.created_at,
+        }
+    }
+}
+
+/// What other users see of a user
+#[derive(Debug, Clone, Serialize)]
+pub struct PublicUser {
+    pub id: Uuid,
+    pub username: String,
+    pub full_name: String,
+    pub display_name: Option<String>,
+    pub avatar_url: Option<String>,
+    pub bio: Option<String>,
+    pub pronouns: Option<String>,
+    pub is_bot: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<User> for PublicUser {
+    fn from(user: User) -> Self {
+        Self {
+            id: user.id,
+            username: user.username,
+            full_name: user.full_name,
+            display_name: user.display_name,
+            avatar_url: user.avatar_url,
+            bio: user.bio,
+            pronouns: user.pronouns,
+            is_bot: user.is_bot,
+            created_at: user.created_at,
+        }
+    }
+}
+
+/// Every editable profile field, written as a whole
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UpdateProfile {
+    pub full_name: String,
+    pub display_name: Option<String>,
+    pub avatar_url: Option<String>,
+    pub bio: Option<String>,
+    pub pronouns: Option<String>,
+    pub timezone: Option<String>,
+    pub locale: Option<String>,
+    pub pending_email: Option<String>,
+}
+
+impl From<&User> for UpdateProfile {
+    fn from(user: &User) -> Self {
+        Self {
+            full_name: user.full_name.clone(),
+            display_name: user.display_name.clone(),
+            avatar_url: user.avatar_url.clone(),
+            bio: user.bio.clone(),
+            pronouns: user.pronouns.clone(),
+            timezone: user.timezone.clone(),
+            locale: user.locale.clone(),
+            pending_email: user.pending_email.clone(),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CreateUser {
     pub full_name: String,
@@ -33,6 +145,13 @@ pub struct CreateUser {
     pub status: OnlineStatus,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateBot {
+    pub full_name: String,
+    pub username: String,
+    pub owner_id: Uuid,
+}
+
 impl User {
     pub async fn create(pool: &DbPool, user: CreateUser) -> Result<Self, sqlx::Error> {
         let now = Utc::now();
@@ -58,6 +177,42 @@ impl User {
         Ok(user)
     }
 
+    /// Creates a bot account. Bots authenticate with API tokens only, their
+    /// password is not a valid bcrypt hash so it never verifies.
+    pub async fn create_bot(pool: &DbPool, bot: CreateBot) -> Result<Self, sqlx::Error> {
+        let now = Utc::now();
+        let id = Uuid::new_v4();
+
+        let user = sqlx::query_as::<_, User>(
+            "INSERT INTO users (id, full_name, username, email, password, status, is_bot, owner_id, created_at, updated_at) 
+             VALUES ($1, $2, $3, $4, '!', $5, TRUE, $6, $7, $8) 
+             RETURNING *",
+        )
+        .bind(id)
+        .bind(bot.full_name)
+        .bind(bot.username)
+        .bind(format!("{}@bots.invalid", id))
+        .bind(OnlineStatus::Offline)
+        .bind(bot.owner_id)
+        .bind(now)
+        .bind(now)
+        .fetch_one(pool)
+        .await?;
+
+        Ok(user)
+    }
+
+    pub async fn find_bots_by_owner(pool: &DbPool, owner_id: Uuid) -> Result<Vec<Self>, sqlx::Error> {
+        let users = sqlx::query_as::<_, User>(
+            "SELECT * FROM users WHERE is_bot AND owner_id = $1 ORDER BY created_at DESC",
+        )
+        .bind(owner_id)
+        .fetch_all(pool)
+        .await?;
+
+        Ok(users)
+    }
+
     pub async fn find_by_id(pool: &DbPool, id: Uuid) -> Result<Option<Self>, sqlx::Error> {
         let user = sqlx::query_as::<_, User>("SELECT * FROM users WHERE id = $1")
             .bind(id)
@@ -67,6 +222,15 @@ impl User {
         Ok(user)
     }
 
+    pub async fn find_by_email(pool: &DbPool, email: &str) -> Result<Option<Self>, sqlx::Error> {
+        let user = sqlx::query_as::<_, User>("SELECT * FROM users WHERE email = $1")
+            .bind(email)
+            .fetch_optional(pool)
+            .await?;
+
+        Ok(user)
+    }
+
     pub async fn find_by_username(
         pool: &DbPool,
         username: &str,
@@ -79,14 +243,47 @@ impl User {
         Ok(user)
     }
 
-    pub async fn find_all(pool: &DbPool) -> Result<Vec<Self>, sqlx::Error> {
-        let users = sqlx::query_as::<_, User>("SELECT * FROM users ORDER BY created_at DESC")
+    pub async fn find_by_usernames(pool: &DbPool, usernames: &[String]) -> Result<Vec<Self>, sqlx::Error> {
+        let users = sqlx::query_as::<_, User>("SELECT * FROM users WHERE username = ANY($1)")
+            .bind(usernames)
             .fetch_all(pool)
             .await?;
 
         Ok(users)
     }
 
+    /// Users ordered by username, starting after `after`, whose username or full name
+    /// contains `query`
+    pub async fn search(
+        pool: &DbPool,
+        query: Option<&str>,
+        after: Option<&str>,
+        limit: i64,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        let pattern = query.map(|query| {
+            let escaped = query
+                .replace('\\', "\\\\")
+                .replace('%', "\\%")
+                .replace('_', "\\_");
+            format!("%{}%", escaped)
+        });
+
+        let users = sqlx::query_as::<_, User>(
+            "SELECT * FROM users 
+             WHERE ($1::TEXT IS NULL OR username ILIKE $1 OR full_name ILIKE $1) 
+               AND ($2::TEXT IS NULL OR username > $2) 
+             ORDER BY username 
+             LIMIT $3",
+        )
+        .bind(pattern)
+        .bind(after)
+        .bind(limit)
+        .fetch_all(pool)
+        .await?;
+
+        Ok(users)
+    }
+
     pub fn verify_password(&self, password: &str) -> Result<bool, bcrypt::BcryptError> {
         verify(password, &self.password)
     }
@@ -97,10 +294,141 @@ impl User {
         password: &str,
     ) -> Result<Option<Self>, sqlx::Error> {
         if let Some(user) = Self::find_by_username(pool, username).await? {
-            if user.verify_password(password).unwrap_or(false) {
+            if !user.is_bot && user.verify_password(password).unwrap_or(false) {
                 return Ok(Some(user));
             }
         }
         Ok(None)
     }
+
+    pub async fn update_password(pool: &DbPool, id: Uuid, password: &str) -> Result<(), sqlx::Error> {
+        let hashed_password =
+            hash(password.as_bytes(), DEFAULT_COST).map_err(|_| sqlx::Error::RowNotFound)?;
+
+        sqlx::query("UPDATE users SET password = $2, updated_at = NOW() WHERE id = $1")
+            .bind(id)
+            .bind(hashed_password)
+            .execute(pool)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn update_profile(
+        pool: &DbPool,
+        id: Uuid,
+        profile: UpdateProfile,
+    ) -> Result<Option<Self>, sqlx::Error> {
+        let user = sqlx::query_as::<_, User>(
+            "UPDATE users SET full_name = $2, display_name = $3, avatar_url = $4, bio = $5, 
+                 pronouns = $6, timezone = $7, locale = $8, pending_email = $9, updated_at = NOW() 
+             WHERE id = $1 
+             RETURNING *",
+        )
+        .bind(id)
+        .bind(profile.full_name)
+        .bind(profile.display_name)
+        .bind(profile.avatar_url)
+        .bind(profile.bio)
+        .bind(profile.pronouns)
+        .bind(profile.timezone)
+        .bind(profile.locale)
+        .bind(profile.pending_email)
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(user)
+    }
+
+    /// Replaces the email with the pending one once it is verified, unless the
+    /// user asked for another address since the link was sent
+    pub async fn confirm_email_change(pool: &DbPool, id: Uuid, email: &str) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            "UPDATE users SET email = pending_email, pending_email = NULL, email_verified_at = NOW(), 
+                 updated_at = NOW() 
+             WHERE id = $1 AND pending_email = $2",
+        )
+        .bind(id)
+        .bind(email)
+        .execute(pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Marks the email as verified, unless the user changed it since the link was sent
+    pub async fn mark_email_verified(pool: &DbPool, id: Uuid, email: &str) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            "UPDATE users SET email_verified_at = COALESCE(email_verified_at, NOW()), updated_at = NOW() 
+             WHERE id = $1 AND email = $2",
+        )
+        .bind(id)
+        .bind(email)
+        .execute(pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Invalidates every token issued to the user so far. The time comes from this
+    /// server's clock, the one tokens are issued by.
+    pub async fn revoke_tokens(pool: &DbPool, id: Uuid) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "UPDATE users SET tokens_revoked_at = $2, updated_at = NOW() 
+             WHERE id = $1",
+        )
+        .bind(id)
+        .bind(Utc::now())
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Whether a token issued at `issued_at` is no longer valid, either because it
+    /// was revoked or because the account is gone
+    pub async fn is_token_revoked(
+        pool: &DbPool,
+        id: Uuid,
+        issued_at: DateTime<Utc>,
+    ) -> Result<bool, sqlx::Error> {
+        let revoked_at: Option<Option<DateTime<Utc>>> =
+            sqlx::query_scalar("SELECT tokens_revoked_at FROM users WHERE id = $1")
+                .bind(id)
+                .fetch_optional(pool)
+                .await?;
+
+        Ok(revoked_at.is_none_or(|revoked_at| revokes(revoked_at, issued_at)))
+    }
+}
+
+/// Whether a revocation at `revoked_at` covers a token issued at `issued_at`. Both are
+/// microseconds, as Postgres stores them; a token issued in the same microsecond is revoked.
+fn revokes(revoked_at: Option<DateTime<Utc>>, issued_at: DateTime<Utc>) -> bool {
+    revoked_at.is_some_and(|revoked_at| revoked_at >= issued_at)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(secs: i64, micros: u32) -> DateTime<Utc> {
+        Utc.timestamp_opt(secs, micros * 1_000).unwrap()
+    }
+
+    #[test]
+    fn tokens_are_valid_until_a_revocation() {
+        assert!(!revokes(None, at(1_000, 0)));
+    }
+
+    #[test]
+    fn revocations_cover_earlier_tokens_only() {
+        let revoked_at = at(1_000, 500_000);
+        assert!(revokes(Some(revoked_at), at(999, 0)));
+        assert!(revokes(Some(revoked_at), at(1_000, 499_999)));
+        assert!(revokes(Some(revoked_at), revoked_at));
+        assert!(!revokes(Some(revoked_at), at(1_000, 500_001)));
+        assert!(!revokes(Some(revoked_at), at(1_001, 0)));
+    }
 }
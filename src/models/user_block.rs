@@ -0,0 +1,101 @@
+use crate::database::connection::DbPool;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+/// `blocker_id` does not get direct messages, mentions or invites from `blocked_id`,
+/// who in turn does not see the blocker's presence
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct UserBlock {
+    pub blocker_id: Uuid,
+    pub blocked_id: Uuid,
+    /// Whether the blocked user's room messages are hidden from the blocker too
+    pub hide_messages: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+impl UserBlock {
+    /// Blocks a user, or updates `hide_messages` of an existing block
+    pub async fn set(
+        pool: &DbPool,
+        blocker_id: Uuid,
+        blocked_id: Uuid,
+        hide_messages: bool,
+    ) -> Result<Self, sqlx::Error> {
+        let block = sqlx::query_as::<_, UserBlock>(
+            "INSERT INTO user_blocks (blocker_id, blocked_id, hide_messages, created_at) 
+             VALUES ($1, $2, $3, $4) 
+             ON CONFLICT (blocker_id, blocked_id) DO UPDATE SET hide_messages = EXCLUDED.hide_messages 
+             RETURNING *",
+        )
+        .bind(blocker_id)
+        .bind(blocked_id)
+        .bind(hide_messages)
+        .bind(Utc::now())
+        .fetch_one(pool)
+        .await?;
+
+        Ok(block)
+    }
+
+    /// Returns whether the user was blocked
+    pub async fn remove(pool: &DbPool, blocker_id: Uuid, blocked_id: Uuid) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query("DELETE FROM user_blocks WHERE blocker_id = $1 AND blocked_id = $2")
+            .bind(blocker_id)
+            .bind(blocked_id)
+            .execute(pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn find_by_blocker_id(pool: &DbPool, blocker_id: Uuid) -> Result<Vec<Self>, sqlx::Error> {
+        let blocks = sqlx::query_as::<_, UserBlock>(
+            "SELECT * FROM user_blocks WHERE blocker_id = $1 ORDER BY created_at DESC",
+        )
+        .bind(blocker_id)
+        .fetch_all(pool)
+        .await?;
+
+        Ok(blocks)
+    }
+
+    pub async fn find_by_blocked_id(pool: &DbPool, blocked_id: Uuid) -> Result<Vec<Self>, sqlx::Error> {
+        let blocks = sqlx::query_as::<_, UserBlock>("SELECT * FROM user_blocks WHERE blocked_id = $1")
+            .bind(blocked_id)
+            .fetch_all(pool)
+            .await?;
+
+        Ok(blocks)
+    }
+
+    pub async fn exists(pool: &DbPool, blocker_id: Uuid, blocked_id: Uuid) -> Result<bool, sqlx::Error> {
+        let exists: bool = sqlx::query_scalar(
+            "SELECT EXISTS(SELECT 1 FROM user_blocks WHERE blocker_id = $1 AND blocked_id = $2)",
+        )
+        .bind(blocker_id)
+        .bind(blocked_id)
+        .fetch_one(pool)
+        .await?;
+
+        Ok(exists)
+    }
+
+    /// Which of `user_ids` blocked `blocked_id`
+    pub async fn find_blocker_ids(
+        pool: &DbPool,
+        blocked_id: Uuid,
+        user_ids: &[Uuid],
+    ) -> Result<Vec<Uuid>, sqlx::Error> {
+        let blocker_ids = sqlx::query_scalar::<_, Uuid>(
+            "SELECT blocker_id FROM user_blocks WHERE blocked_id = $1 AND blocker_id = ANY($2)",
+        )
+        .bind(blocked_id)
+        .bind(user_ids)
+        .fetch_all(pool)
+        .await?;
+
+        Ok(blocker_ids)
+    }
+}
@@ -0,0 +1,244 @@
+use crate::database::connection::DbPool;
+use chrono::{DateTime, Utc};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+/// Room events webhooks can subscribe to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WebhookEvent {
+    #[serde(rename = "message.created")]
+    MessageCreated,
+    #[serde(rename = "member.joined")]
+    MemberJoined,
+    #[serde(rename = "room.updated")]
+    RoomUpdated,
+}
+
+impl WebhookEvent {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WebhookEvent::MessageCreated => "message.created",
+            WebhookEvent::MemberJoined => "member.joined",
+            WebhookEvent::RoomUpdated => "room.updated",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Webhook {
+    pub id: Uuid,
+    /// `None` for global webhooks receiving events of every room
+    pub room_id: Option<Uuid>,
+    pub url: String,
+    /// Key deliveries are signed with, only shown when the webhook is created
+    #[serde(skip_serializing)]
+    pub secret: String,
+    pub events: Vec<String>,
+    pub is_active: bool,
+    pub created_by: Uuid,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateWebhook {
+    pub room_id: Option<Uuid>,
+    pub url: String,
+    pub events: Vec<WebhookEvent>,
+    pub created_by: Uuid,
+}
+
+impl Webhook {
+    pub async fn create(pool: &DbPool, webhook: CreateWebhook) -> Result<Self, sqlx::Error> {
+        let mut secret = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut secret);
+        let events: Vec<&str> = webhook.events.iter().map(WebhookEvent::as_str).collect();
+
+        let webhook = sqlx::query_as::<_, Webhook>(
+            "INSERT INTO webhooks (id, room_id, url, secret, events, is_active, created_by, created_at) 
+             VALUES ($1, $2, $3, $4, $5, TRUE, $6, $7) 
+             RETURNING *",
+        )
+        .bind(Uuid::new_v4())
+        .bind(webhook.room_id)
+        .bind(webhook.url)
+        .bind(hex::encode(secret))
+        .bind(events)
+        .bind(webhook.created_by)
+        .bind(Utc::now())
+        .fetch_one(pool)
+        .await?;
+
+        Ok(webhook)
+    }
+
+    pub async fn find_by_id(pool: &DbPool, id: Uuid) -> Result<Option<Self>, sqlx::Error> {
+        let webhook = sqlx::query_as::<_, Webhook>("SELECT * FROM webhooks WHERE id = $1")
+            .bind(id)
+            .fetch_optional(pool)
+            .await?;
+
+        Ok(webhook)
+    }
+
+    pub async fn find_by_ids(pool: &DbPool, ids: &[Uuid]) -> Result<Vec<Self>, sqlx::Error> {
+        let webhooks = sqlx::query_as::<_, Webhook>("SELECT * FROM webhooks WHERE id = ANY($1)")
+            .bind(ids)
+            .fetch_all(pool)
+            .await?;
+
+        Ok(webhooks)
+    }
+
+    pub async fn find_by_room_id(pool: &DbPool, room_id: Uuid) -> Result<Vec<Self>, sqlx::Error> {
+        let webhooks = sqlx::query_as::<_, Webhook>(
+            "SELECT * FROM webhooks WHERE room_id = $1 ORDER BY created_at DESC",
+        )
+        .bind(room_id)
+        .fetch_all(pool)
+        .await?;
+
+        Ok(webhooks)
+    }
+
+    pub async fn find_global(pool: &DbPool) -> Result<Vec<Self>, sqlx::Error> {
+        let webhooks = sqlx::query_as::<_, Webhook>(
+            "SELECT * FROM webhooks WHERE room_id IS NULL ORDER BY created_at DESC",
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(webhooks)
+    }
+
+    pub async fn delete(pool: &DbPool, id: Uuid) -> Result<(), sqlx::Error> {
+        sqlx::query("DELETE FROM webhooks WHERE id = $1")
+            .bind(id)
+            .execute(pool)
+            .await?;
+
+        Ok(())
+    }
+}
+
+/// One event to be delivered to one webhook, doubling as its delivery log
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct WebhookDelivery {
+    pub id: Uuid,
+    pub webhook_id: Uuid,
+    pub event: String,
+    pub payload: serde_json::Value,
+    /// `pending`, `delivered` or `failed`
+    pub status: String,
+    pub attempts: i32,
+    pub next_attempt_at: DateTime<Utc>,
+    pub last_status_code: Option<i32>,
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub delivered_at: Option<DateTime<Utc>>,
+}
+
+impl WebhookDelivery {
+    /// Queues an event for every active webhook of the room, and every global one,
+    /// subscribed to it. Returns the number of deliveries queued.
+    pub async fn enqueue(
+        pool: &DbPool,
+        room_id: Uuid,
+        event: WebhookEvent,
+        payload: &serde_json::Value,
+    ) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query(
+            "INSERT INTO webhook_deliveries (id, webhook_id, event, payload, status, attempts, next_attempt_at, created_at) 
+             SELECT gen_random_uuid(), w.id, $2, $3, 'pending', 0, NOW(), NOW() 
+             FROM webhooks w 
+             WHERE w.is_active AND (w.room_id = $1 OR w.room_id IS NULL) AND $2 = ANY(w.events)",
+        )
+        .bind(room_id)
+        .bind(event.as_str())
+        .bind(payload)
+        .execute(pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Claims up to `limit` due deliveries, counting the attempt and leasing them for
+    /// `lease_secs` so another node picks them up if this one dies mid-delivery
+    pub async fn claim_due(pool: &DbPool, limit: i64, lease_secs: i64) -> Result<Vec<Self>, sqlx::Error> {
+        let deliveries = sqlx::query_as::<_, WebhookDelivery>(
+            "WITH due AS (
+                 SELECT id FROM webhook_deliveries 
+                 WHERE status = 'pending' AND next_attempt_at <= NOW() 
+                 ORDER BY next_attempt_at 
+                 LIMIT $1 
+                 FOR UPDATE SKIP LOCKED
+             )
+             UPDATE webhook_deliveries d 
+             SET attempts = d.attempts + 1, next_attempt_at = NOW() + make_interval(secs => $2) 
+             FROM due WHERE d.id = due.id 
+             RETURNING d.*",
+        )
+        .bind(limit)
+        .bind(lease_secs as f64)
+        .fetch_all(pool)
+        .await?;
+
+        Ok(deliveries)
+    }
+
+    pub async fn mark_delivered(pool: &DbPool, id: Uuid, status_code: i32) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "UPDATE webhook_deliveries 
+             SET status = 'delivered', last_status_code = $2, last_error = NULL, delivered_at = NOW() 
+             WHERE id = $1",
+        )
+        .bind(id)
+        .bind(status_code)
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Records a failed attempt, to be retried at `retry_at` or given up on when `None`
+    pub async fn mark_attempt_failed(
+        pool: &DbPool,
+        id: Uuid,
+        status_code: Option<i32>,
+        error: &str,
+        retry_at: Option<DateTime<Utc>>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "UPDATE webhook_deliveries 
+             SET status = CASE WHEN $4::timestamptz IS NULL THEN 'failed' ELSE 'pending' END, 
+                 last_status_code = $2, last_error = $3, 
+                 next_attempt_at = COALESCE($4, next_attempt_at) 
+             WHERE id = $1",
+        )
+        .bind(id)
+        .bind(status_code)
+        .bind(error)
+        .bind(retry_at)
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn find_by_webhook_id(
+        pool: &DbPool,
+        webhook_id: Uuid,
+        limit: i64,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        let deliveries = sqlx::query_as::<_, WebhookDelivery>(
+            "SELECT * FROM webhook_deliveries WHERE webhook_id = $1 ORDER BY created_at DESC LIMIT $2",
+        )
+        .bind(webhook_id)
+        .bind(limit)
+        .fetch_all(pool)
+        .await?;
+
+        Ok(deliveries)
+    }
+}
@@ -0,0 +1,67 @@
+use crate::database::connection::DbPool;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+/// How long a ticket can be redeemed after it was issued
+pub const TICKET_TTL_SECS: i64 = 30;
+
+/// A single-use credential for opening one WebSocket connection or event stream
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct WsTicket {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    /// When set, the ticket is only valid for connecting to this room
+    pub room_id: Option<String>,
+    pub expires_at: DateTime<Utc>,
+    /// Expiry of the token the ticket was issued with, the session must reauthenticate by then
+    pub auth_expires_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl WsTicket {
+    pub async fn create(
+        pool: &DbPool,
+        user_id: Uuid,
+        room_id: Option<String>,
+        auth_expires_at: DateTime<Utc>,
+    ) -> Result<Self, sqlx::Error> {
+        // Tickets that were never redeemed are cleaned up as new ones are issued
+        sqlx::query("DELETE FROM ws_tickets WHERE expires_at < NOW()")
+            .execute(pool)
+            .await?;
+
+        let now = Utc::now();
+        let ticket = sqlx::query_as::<_, WsTicket>(
+            "INSERT INTO ws_tickets (id, user_id, room_id, expires_at, auth_expires_at, created_at) 
+             VALUES ($1, $2, $3, $4, $5, $6) 
+             RETURNING *",
+        )
+        .bind(Uuid::new_v4())
+        .bind(user_id)
+        .bind(room_id)
+        .bind(now + Duration::seconds(TICKET_TTL_SECS))
+        .bind(auth_expires_at)
+        .bind(now)
+        .fetch_one(pool)
+        .await?;
+
+        Ok(ticket)
+    }
+
+    /// Consumes a ticket, returning it only if it existed and had not expired
+    pub async fn redeem(pool: &DbPool, id: Uuid) -> Result<Option<Self>, sqlx::Error> {
+        let ticket = sqlx::query_as::<_, WsTicket>(
+            "WITH redeemed AS (
+                 DELETE FROM ws_tickets WHERE id = $1 RETURNING *
+             )
+             SELECT * FROM redeemed WHERE expires_at > NOW()",
+        )
+        .bind(id)
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(ticket)
+    }
+}
@@ -0,0 +1,7 @@
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+pub struct BlockUserRequest {
+    /// Also hide the user's room messages, defaults to true
+    pub hide_messages: Option<bool>,
+}
@@ -0,0 +1,35 @@
+use serde::Deserialize;
+use uuid::Uuid;
+
+#[derive(Deserialize)]
+pub struct CreateBotRequest {
+    pub full_name: String,
+    pub username: String,
+}
+
+#[derive(Deserialize)]
+pub struct CreateApiTokenRequest {
+    pub name: String,
+}
+
+#[derive(Deserialize)]
+pub struct CreateBotCommandRequest {
+    pub name: String,
+    pub description: String,
+    pub callback_url: String,
+}
+
+#[derive(Deserialize)]
+pub struct CreateIncomingWebhookRequest {
+    pub bot_id: Uuid,
+    pub name: String,
+}
+
+/// What integrations post to an incoming webhook URL
+#[derive(Deserialize)]
+pub struct IncomingWebhookPayload {
+    pub text: String,
+    /// Shown instead of the bot's name
+    pub username: Option<String>,
+    pub avatar_url: Option<String>,
+}
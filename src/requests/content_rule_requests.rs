@@ -0,0 +1,10 @@
+use crate::models::content_rule::{RuleAction, RuleKind};
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+pub struct CreateContentRuleRequest {
+    pub kind: RuleKind,
+    /// A word, a regex, a domain or a character count, depending on `kind`
+    pub pattern: String,
+    pub action: RuleAction,
+}
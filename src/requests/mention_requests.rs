@@ -0,0 +1,6 @@
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+pub struct MentionQuery {
+    pub limit: Option<i64>,
+}
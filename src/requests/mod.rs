@@ -1 +1,10 @@
+pub mod block_requests;
+pub mod bot_requests;
+pub mod content_rule_requests;
+pub mod mention_requests;
+pub mod notification_requests;
+pub mod report_requests;
 pub mod room_requests;
+pub mod user_requests;
+pub mod webhook_requests;
+pub mod ws_ticket_requests;
@@ -0,0 +1,19 @@
+use crate::models::notification::NotificationKind;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+#[derive(Deserialize)]
+pub struct NotificationQuery {
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+    /// Only list notifications that were not read yet
+    #[serde(default)]
+    pub unread: bool,
+}
+
+/// Categories to turn emails on or off for, e.g. `{"mention": false}`
+#[derive(Deserialize)]
+pub struct UpdateEmailPreferencesRequest {
+    #[serde(flatten)]
+    pub categories: HashMap<NotificationKind, bool>,
+}
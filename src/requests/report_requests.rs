@@ -0,0 +1,50 @@
+use crate::models::message_report::{ReportCategory, ReportResolution, ReportStatus};
+use crate::requests::room_requests::SanctionDetails;
+use serde::Deserialize;
+use uuid::Uuid;
+
+#[derive(Deserialize)]
+pub struct CreateReportRequest {
+    pub category: ReportCategory,
+    pub details: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct ReportQuery {
+    pub status: Option<ReportStatus>,
+    pub room_id: Option<Uuid>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportAction {
+    Dismiss,
+    DeleteMessage,
+    /// Sanctions the message's sender in its room, with `sanction`
+    SanctionUser,
+}
+
+impl ReportAction {
+    pub fn resolution(&self) -> ReportResolution {
+        match self {
+            ReportAction::Dismiss => ReportResolution::Dismissed,
+            ReportAction::DeleteMessage => ReportResolution::MessageDeleted,
+            ReportAction::SanctionUser => ReportResolution::UserSanctioned,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ResolveReportRequest {
+    pub action: ReportAction,
+    pub sanction: Option<SanctionDetails>,
+    /// Added to the report's notes
+    pub note: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct CreateReportNoteRequest {
+    pub body: String,
+}
@@ -1,7 +1,31 @@
+use crate::models::room_sanction::SanctionKind;
 use serde::Deserialize;
+use uuid::Uuid;
 
 #[derive(Deserialize)]
 pub struct CreateRoomRequest {
     pub name: String,
     pub is_private: bool,
 }
+
+#[derive(Deserialize)]
+pub struct UpdateRoomRequest {
+    pub name: Option<String>,
+    pub is_private: Option<bool>,
+    pub topic: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct SanctionDetails {
+    pub kind: SanctionKind,
+    /// How long the sanction lasts, until lifted when left out
+    pub duration_secs: Option<i64>,
+    pub reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct CreateSanctionRequest {
+    pub user_id: Uuid,
+    #[serde(flatten)]
+    pub sanction: SanctionDetails,
+}
@@ -0,0 +1,24 @@
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+pub struct UserDirectoryQuery {
+    /// Matched anywhere in the username or full name
+    pub q: Option<String>,
+    pub limit: Option<i64>,
+    /// `next_cursor` of the previous page
+    pub cursor: Option<String>,
+}
+
+/// Fields left out stay as they are, an empty string clears an optional one
+#[derive(Deserialize)]
+pub struct UpdateProfileRequest {
+    pub full_name: Option<String>,
+    /// Takes effect once the new address is verified
+    pub email: Option<String>,
+    pub display_name: Option<String>,
+    pub avatar_url: Option<String>,
+    pub bio: Option<String>,
+    pub pronouns: Option<String>,
+    pub timezone: Option<String>,
+    pub locale: Option<String>,
+}
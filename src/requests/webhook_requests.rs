@@ -0,0 +1,13 @@
+use crate::models::webhook::WebhookEvent;
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+pub struct CreateWebhookRequest {
+    pub url: String,
+    pub events: Vec<WebhookEvent>,
+}
+
+#[derive(Deserialize)]
+pub struct DeliveryLogQuery {
+    pub limit: Option<i64>,
+}
@@ -0,0 +1,6 @@
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+pub struct CreateTicketRequest {
+    pub room_id: Option<String>,
+}
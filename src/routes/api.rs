@@ -7,14 +7,65 @@ pub fn scoped_config(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::scope("/auth")
             .service(web::resource("/register").route(web::post().to(handlers::auth::register)))
-            .service(web::resource("/login").route(web::post().to(handlers::auth::login))),
+            .service(web::resource("/login").route(web::post().to(handlers::auth::login)))
+            .service(
+                web::resource("/verify-email")
+                    .route(web::post().to(handlers::auth::verify_email)),
+            )
+            .service(
+                web::resource("/verify-email/resend").route(
+                    web::post()
+                        .to(handlers::auth::resend_verification)
+                        .wrap(AuthMiddleware),
+                ),
+            )
+            .service(
+                web::resource("/password/forgot")
+                    .route(web::post().to(handlers::auth::forgot_password)),
+            )
+            .service(
+                web::resource("/password/reset")
+                    .route(web::post().to(handlers::auth::reset_password)),
+            )
+            .service(
+                web::resource("/revoke").route(
+                    web::post()
+                        .to(handlers::auth::revoke)
+                        .wrap(AuthMiddleware),
+                ),
+            ),
     )
     .service(
-        web::scope("/users").service(
-            web::resource("")
-                .route(web::get().to(handlers::users::index))
-                .route(web::head().to(HttpResponse::MethodNotAllowed)),
-        ),
+        web::scope("/me")
+            .wrap(AuthMiddleware)
+            .service(
+                web::resource("")
+                    .route(web::get().to(handlers::users::get_me))
+                    .route(web::patch().to(handlers::users::update_me)),
+            )
+            .service(
+                web::resource("/password").route(web::put().to(handlers::auth::change_password)),
+            )
+            .service(web::resource("/blocks").route(web::get().to(handlers::blocks::get_blocks)))
+            .service(
+                web::resource("/blocks/{user_id}")
+                    .route(web::put().to(handlers::blocks::block_user))
+                    .route(web::delete().to(handlers::blocks::unblock_user)),
+            ),
+    )
+    .service(
+        web::scope("/users")
+            .wrap(AuthMiddleware)
+            .service(
+                web::resource("")
+                    .route(web::get().to(handlers::users::index))
+                    .route(web::head().to(HttpResponse::MethodNotAllowed)),
+            )
+            .service(
+                web::resource("/by-username/{username}")
+                    .route(web::get().to(handlers::users::get_user_by_username)),
+            )
+            .service(web::resource("/{id}").route(web::get().to(handlers::users::get_user))),
     )
     .service(
         web::scope("/rooms")
@@ -31,9 +82,190 @@ pub fn scoped_config(cfg: &mut web::ServiceConfig) {
             .service(
                 web::resource("/{id}")
                     .route(web::get().to(handlers::rooms::get_room_by_id))
+                    .route(
+                        web::put()
+                            .to(handlers::rooms::update_room)
+                            .wrap(AuthMiddleware),
+                    )
                     .route(web::head().to(HttpResponse::MethodNotAllowed)),
+            )
+            .service(
+                web::resource("/{id}/sanctions")
+                    .wrap(AuthMiddleware)
+                    .route(web::post().to(handlers::sanctions::create_sanction))
+                    .route(web::get().to(handlers::sanctions::get_sanctions)),
+            )
+            .service(
+                web::resource("/{id}/sanctions/{sanction_id}")
+                    .wrap(AuthMiddleware)
+                    .route(web::delete().to(handlers::sanctions::lift_sanction)),
+            )
+            .service(
+                web::resource("/{id}/content-rules")
+                    .wrap(AuthMiddleware)
+                    .route(web::post().to(handlers::content_rules::create_room_rule))
+                    .route(web::get().to(handlers::content_rules::get_room_rules)),
+            )
+            .service(
+                web::resource("/{id}/content-rules/{rule_id}")
+                    .wrap(AuthMiddleware)
+                    .route(web::delete().to(handlers::content_rules::delete_room_rule)),
+            )
+            .service(
+                web::resource("/{id}/webhooks")
+                    .wrap(AuthMiddleware)
+                    .route(web::post().to(handlers::webhooks::create_room_webhook))
+                    .route(web::get().to(handlers::webhooks::get_room_webhooks)),
+            )
+            .service(
+                web::resource("/{id}/incoming-webhooks")
+                    .wrap(AuthMiddleware)
+                    .route(web::post().to(handlers::incoming_webhooks::create))
+                    .route(web::get().to(handlers::incoming_webhooks::get_room_incoming_webhooks)),
+            )
+            .service(
+                web::resource("/{id}/incoming-webhooks/{webhook_id}")
+                    .wrap(AuthMiddleware)
+                    .route(web::delete().to(handlers::incoming_webhooks::delete)),
+            ),
+    )
+    .service(
+        web::resource("/messages/{id}/reports")
+            .route(
+                web::post()
+                    .to(handlers::reports::create_report)
+                    .wrap(AuthMiddleware),
+            )
+            .route(web::head().to(HttpResponse::MethodNotAllowed)),
+    )
+    .service(
+        web::scope("/reports")
+            .wrap(AuthMiddleware)
+            .service(web::resource("").route(web::get().to(handlers::reports::get_reports)))
+            .service(web::resource("/{id}").route(web::get().to(handlers::reports::get_report)))
+            .service(
+                web::resource("/{id}/claim").route(web::post().to(handlers::reports::claim_report)),
+            )
+            .service(
+                web::resource("/{id}/resolve")
+                    .route(web::post().to(handlers::reports::resolve_report)),
+            )
+            .service(web::resource("/{id}/notes").route(web::post().to(handlers::reports::add_note))),
+    )
+    .service(
+        web::scope("/bots")
+            .wrap(AuthMiddleware)
+            .service(
+                web::resource("")
+                    .route(web::post().to(handlers::bots::create_bot))
+                    .route(web::get().to(handlers::bots::get_bots)),
+            )
+            .service(
+                web::resource("/{id}/tokens")
+                    .route(web::post().to(handlers::bots::create_token))
+                    .route(web::get().to(handlers::bots::get_tokens)),
+            )
+            .service(
+                web::resource("/{id}/tokens/{token_id}")
+                    .route(web::delete().to(handlers::bots::delete_token)),
+            )
+            .service(
+                web::resource("/{id}/commands")
+                    .route(web::post().to(handlers::bots::create_command))
+                    .route(web::get().to(handlers::bots::get_commands)),
+            )
+            .service(
+                web::resource("/{id}/commands/{command_id}")
+                    .route(web::delete().to(handlers::bots::delete_command)),
             ),
     )
+    // Incoming webhooks authenticate with the token in their URL
+    .service(
+        web::resource("/hooks/{id}/{token}")
+            .route(web::post().to(handlers::incoming_webhooks::post_message)),
+    )
+    .service(
+        web::scope("/webhooks")
+            .wrap(AuthMiddleware)
+            .service(
+                web::resource("")
+                    .route(web::post().to(handlers::webhooks::create_global_webhook))
+                    .route(web::get().to(handlers::webhooks::get_global_webhooks)),
+            )
+            .service(
+                web::resource("/{id}").route(web::delete().to(handlers::webhooks::delete_webhook)),
+            )
+            .service(
+                web::resource("/{id}/deliveries")
+                    .route(web::get().to(handlers::webhooks::get_deliveries)),
+            ),
+    )
+    .service(
+        web::scope("/content-rules")
+            .wrap(AuthMiddleware)
+            .service(
+                web::resource("")
+                    .route(web::post().to(handlers::content_rules::create_global_rule))
+                    .route(web::get().to(handlers::content_rules::get_global_rules)),
+            )
+            .service(
+                web::resource("/{id}")
+                    .route(web::delete().to(handlers::content_rules::delete_global_rule)),
+            ),
+    )
+    .service(
+        web::resource("/mentions")
+            .route(
+                web::get()
+                    .to(handlers::mentions::get_mentions)
+                    .wrap(AuthMiddleware),
+            )
+            .route(web::head().to(HttpResponse::MethodNotAllowed)),
+    )
+    .service(
+        web::scope("/notifications")
+            .wrap(AuthMiddleware)
+            .service(
+                web::resource("").route(web::get().to(handlers::notifications::get_notifications)),
+            )
+            .service(
+                web::resource("/email-preferences")
+                    .route(web::get().to(handlers::notifications::get_email_preferences))
+                    .route(web::put().to(handlers::notifications::update_email_preferences)),
+            )
+            .service(
+                web::resource("/read-all")
+                    .route(web::post().to(handlers::notifications::mark_all_read)),
+            )
+            .service(
+                web::resource("/{id}/read").route(web::post().to(handlers::notifications::mark_read)),
+            ),
+    )
+    .service(
+        web::scope("/metrics").service(
+            web::resource("/outbound")
+                .route(web::get().to(handlers::metrics::outbound))
+                .route(web::head().to(HttpResponse::MethodNotAllowed)),
+        ),
+    )
+    // Fallback transports for clients that cannot open a WebSocket. The SSE stream also
+    // takes a ticket, since EventSource cannot send an Authorization header.
+    .service(web::resource("/events").route(web::get().to(handlers::events::stream)))
+    .service(
+        web::scope("/events")
+            .wrap(AuthMiddleware)
+            .service(web::resource("/poll").route(web::get().to(handlers::events::poll)))
+            .service(web::resource("/send").route(web::post().to(handlers::events::send))),
+    )
     // Add the websocket endpoint for room chat
-    .service(web::resource("/ws/").route(web::get().to(ws_route)));
+    .service(web::resource("/ws/").route(web::get().to(ws_route)))
+    .service(
+        web::resource("/ws/ticket")
+            .route(
+                web::post()
+                    .to(handlers::ws_tickets::issue)
+                    .wrap(AuthMiddleware),
+            )
+            .route(web::head().to(HttpResponse::MethodNotAllowed)),
+    );
 }
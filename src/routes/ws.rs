@@ -1,60 +1,139 @@
+use crate::config::settings::AppConfig;
+use crate::database::connection::DbPool;
+use crate::models::user::User;
+use crate::models::ws_ticket::WsTicket;
 use crate::services::auth::AuthService;
-use actix::Addr;
+use crate::services::{blocks, moderation};
+use crate::ws_outbox::Outbox;
+use crate::ws_protocol::{bearer_token, WireCodec, SUPPORTED_PROTOCOLS};
 use actix_web::{web, Error, HttpRequest, HttpResponse};
 use actix_web_actors::ws;
-use rusty_chat::{ws_server::ChatServer, ws_session::ChatSession};
+use crate::{ws_router::ChatRouter, ws_session::{ChatSession, SessionAuth}};
+use serde::Deserialize;
+use std::sync::Arc;
+use tracing::{error, warn};
+use uuid::Uuid;
+
+#[derive(Debug, Deserialize)]
+pub struct WsQuery {
+    pub room_id: Option<String>,
+    /// Highest sequence number seen before reconnecting, missed messages are replayed
+    pub last_seq: Option<i64>,
+    /// Issued by `POST /ws/ticket`
+    pub ticket: Option<Uuid>,
+    /// Deprecated: the raw JWT ends up in access logs
+    pub token: Option<String>,
+}
 
 pub async fn ws_route(
     req: HttpRequest,
     stream: web::Payload,
-    srv: web::Data<Addr<ChatServer>>,
+    query: web::Query<WsQuery>,
+    router: web::Data<ChatRouter>,
+    pool: web::Data<DbPool>,
+    config: web::Data<AppConfig>,
     auth_service: web::Data<AuthService>,
 ) -> Result<HttpResponse, Error> {
-    let token = req
-        .headers()
-        .get("Authorization")
-        .and_then(|h| h.to_str().ok())
-        .and_then(|h| h.strip_prefix("Bearer "))
-        .or_else(|| {
-            req.query_string().split('&').find_map(|kv| {
-                let mut split = kv.split('=');
-                if split.next()? == "token" {
-                    split.next()
-                } else {
-                    None
-                }
-            })
-        });
-
-    let token = match token {
-        Some(t) => t,
-        None => return Ok(HttpResponse::Unauthorized().body("Missing token")),
-    };
+    let query = query.into_inner();
+    let mut room_id = query.room_id;
 
-    // Validate JWT using AuthService
-    let claims = match auth_service.validate_token(token) {
-        Ok(claims) => claims,
-        Err(_) => return Ok(HttpResponse::Unauthorized().body("Invalid token")),
-    };
-    let user_id = claims.sub.to_string();
-
-    let room_id = req
-        .query_string()
-        .split('&')
-        .find_map(|kv| {
-            let mut split = kv.split('=');
-            if split.next()? == "room_id" {
-                split.next()
-            } else {
-                None
+    let (user_id, expires_at) = if let Some(ticket_id) = query.ticket {
+        let ticket = WsTicket::redeem(&pool, ticket_id).await.map_err(|e| {
+            error!("Failed to redeem WebSocket ticket: {}", e);
+            actix_web::error::ErrorInternalServerError("Failed to redeem ticket")
+        })?;
+        let Some(ticket) = ticket else {
+            return Ok(HttpResponse::Unauthorized().body("Invalid or expired ticket"));
+        };
+        // A ticket bound to a room can only be used to connect to that room
+        if let Some(ticket_room) = ticket.room_id {
+            if room_id.as_ref().is_some_and(|room| *room != ticket_room) {
+                return Ok(HttpResponse::Forbidden().body("Ticket is not valid for this room"));
+            }
+            room_id = Some(ticket_room);
+        }
+        let revoked = User::is_token_revoked(&pool, ticket.user_id, ticket.created_at)
+            .await
+            .map_err(|e| {
+                error!("Failed to check token revocation: {}", e);
+                actix_web::error::ErrorInternalServerError("Failed to redeem ticket")
+            })?;
+        if revoked {
+            return Ok(HttpResponse::Unauthorized().body("Invalid or expired ticket"));
+        }
+        (ticket.user_id.to_string(), ticket.auth_expires_at.timestamp())
+    } else {
+        let header_token = req
+            .headers()
+            .get("Authorization")
+            .and_then(|h| h.to_str().ok())
+            .and_then(|h| h.strip_prefix("Bearer "));
+
+        let token = match (bearer_token(&req).or(header_token), query.token.as_deref()) {
+            (Some(t), _) => t,
+            (None, Some(t)) if config.websocket.allow_query_token => {
+                warn!("JWT passed in the WebSocket query string, use a ticket instead");
+                t
             }
-        })
-        .unwrap_or("default")
-        .to_string();
+            _ => return Ok(HttpResponse::Unauthorized().body("Missing token")),
+        };
+
+        // Validate JWT using AuthService
+        let claims = auth_service
+            .validate_active_token(&pool, token)
+            .await
+            .map_err(|e| {
+                error!("Failed to check token revocation: {}", e);
+                actix_web::error::ErrorInternalServerError("Authentication service error")
+            })?;
+        match claims {
+            Some(claims) => (claims.sub.to_string(), claims.exp),
+            None => return Ok(HttpResponse::Unauthorized().body("Invalid token")),
+        }
+    };
+
+    let room_id = room_id.unwrap_or_else(|| "default".to_string());
+    let refusal = match moderation::privacy_refusal(&pool, &user_id, &room_id).await {
+        Ok(None) => moderation::join_refusal(&pool, &user_id, &room_id).await,
+        refused => refused,
+    }
+    .map_err(|e| {
+        error!("Failed to check whether user {} may join room {}: {}", user_id, room_id, e);
+        actix_web::error::ErrorInternalServerError("Failed to open session")
+    })?;
+    if let Some(refusal) = refusal {
+        return Ok(HttpResponse::Forbidden().body(refusal));
+    }
+    let filter = blocks::session_filter(&pool, &user_id).await.map_err(|e| {
+        error!("Failed to load blocks of user {}: {}", user_id, e);
+        actix_web::error::ErrorInternalServerError("Failed to open session")
+    })?;
+
+    // Clients pick JSON or MessagePack through Sec-WebSocket-Protocol
+    let outbox = Arc::new(Outbox::new(
+        config.websocket.outbound_capacity,
+        config.websocket.slow_consumer_policy,
+        WireCodec::negotiate(&req),
+    ));
 
-    ws::start(
-        ChatSession::new(user_id, room_id, srv.get_ref().clone()),
+    ws::WsResponseBuilder::new(
+        ChatSession::new(
+            user_id,
+            room_id,
+            query.last_seq,
+            router.get_ref().clone(),
+            pool.get_ref().clone(),
+            outbox,
+            SessionAuth {
+                service: auth_service.into_inner(),
+                expires_at,
+                require_verified_email: config.auth.require_verified_email,
+            },
+        )
+        .with_filter(filter),
         &req,
         stream,
     )
+    .protocols(SUPPORTED_PROTOCOLS)
+    .start()
 }
@@ -1,12 +1,21 @@
 use crate::database::connection::DbPool;
 use crate::models::auth::Claims;
 use crate::models::user::User;
+use chrono::Utc;
+use hmac::{Hmac, Mac};
 use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use sha2::Sha256;
 use std::env;
+use std::time::Duration;
+use uuid::Uuid;
+
+type HmacSha256 = Hmac<Sha256>;
 
 pub struct AuthService {
     encoding_key: EncodingKey,
     decoding_key: DecodingKey,
+    /// Signs email verification tokens
+    secret: Vec<u8>,
 }
 
 impl AuthService {
@@ -16,6 +25,7 @@ impl AuthService {
         Ok(Self {
             encoding_key: EncodingKey::from_secret(secret.as_ref()),
             decoding_key: DecodingKey::from_secret(secret.as_ref()),
+            secret: secret.into_bytes(),
         })
     }
 
@@ -29,6 +39,64 @@ impl AuthService {
         Ok(token_data.claims)
     }
 
+    /// Validates a token and checks it was not revoked since it was issued.
+    /// Returns `None` for invalid, expired or revoked tokens.
+    pub async fn validate_active_token(
+        &self,
+        pool: &DbPool,
+        token: &str,
+    ) -> Result<Option<Claims>, Box<dyn std::error::Error>> {
+        let Ok(claims) = self.validate_token(token) else {
+            return Ok(None);
+        };
+        if User::is_token_revoked(pool, claims.sub, claims.issued_at()).await? {
+            return Ok(None);
+        }
+        Ok(Some(claims))
+    }
+
+    /// A token proving control of an email address of the user,
+    /// `<user id>.<expiry>.<hex HMAC-SHA256>`
+    pub fn email_verification_token(&self, user_id: Uuid, email: &str, ttl: Duration) -> String {
+        let expires_at = Utc::now().timestamp() + ttl.as_secs() as i64;
+        let signature = self.email_verification_mac(user_id, email, expires_at).finalize();
+        format!("{}.{}.{}", user_id, expires_at, hex::encode(signature.into_bytes()))
+    }
+
+    /// The user an email verification token was issued to and the address it verifies,
+    /// if it is authentic, unexpired and was issued for their current or pending address
+    pub async fn redeem_email_verification_token(
+        &self,
+        pool: &DbPool,
+        token: &str,
+    ) -> Result<Option<(User, String)>, sqlx::Error> {
+        let Some((user_id, expires_at, signature)) = parse_email_verification_token(token) else {
+            return Ok(None);
+        };
+        let Some(user) = User::find_by_id(pool, user_id).await? else {
+            return Ok(None);
+        };
+        let address = [Some(&user.email), user.pending_email.as_ref()]
+            .into_iter()
+            .flatten()
+            .find(|email| self.email_verification_signed(user.id, email, expires_at, &signature))
+            .cloned();
+        Ok(address.map(|address| (user, address)))
+    }
+
+    /// Whether `signature` was made by this server for the user, address and expiry
+    fn email_verification_signed(&self, user_id: Uuid, email: &str, expires_at: i64, signature: &[u8]) -> bool {
+        self.email_verification_mac(user_id, email, expires_at)
+            .verify_slice(signature)
+            .is_ok()
+    }
+
+    fn email_verification_mac(&self, user_id: Uuid, email: &str, expires_at: i64) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(&self.secret).expect("HMAC accepts keys of any size");
+        mac.update(format!("verify-email\n{}\n{}\n{}", user_id, email, expires_at).as_bytes());
+        mac
+    }
+
     pub async fn authenticate_user(
         &self,
         pool: &DbPool,
@@ -40,3 +108,127 @@ impl AuthService {
             .map_err(|e| e.into())
     }
 }
+
+/// The user id, expiry and signature of an unexpired email verification token
+fn parse_email_verification_token(token: &str) -> Option<(Uuid, i64, Vec<u8>)> {
+    let mut parts = token.splitn(3, '.');
+    let (Some(Ok(user_id)), Some(Ok(expires_at)), Some(Ok(signature))) = (
+        parts.next().map(Uuid::parse_str),
+        parts.next().map(str::parse::<i64>),
+        parts.next().map(hex::decode),
+    ) else {
+        return None;
+    };
+    if expires_at < Utc::now().timestamp() {
+        return None;
+    }
+    Some((user_id, expires_at, signature))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn service(secret: &str) -> AuthService {
+        AuthService {
+            encoding_key: EncodingKey::from_secret(secret.as_bytes()),
+            decoding_key: DecodingKey::from_secret(secret.as_bytes()),
+            secret: secret.as_bytes().to_vec(),
+        }
+    }
+
+    fn sign(service: &AuthService, claims: &Claims) -> String {
+        encode(&Header::default(), claims, &service.encoding_key).unwrap()
+    }
+
+    #[test]
+    fn tokens_validate_with_their_claims() {
+        let auth = service("secret");
+        let user_id = Uuid::new_v4();
+        let token = sign(&auth, &Claims::new(user_id, "alice".to_string()));
+        let claims = auth.validate_token(&token).unwrap();
+        assert_eq!(claims.sub, user_id);
+        assert_eq!(claims.username, "alice");
+    }
+
+    #[test]
+    fn expired_tokens_are_refused() {
+        let auth = service("secret");
+        let mut claims = Claims::new(Uuid::new_v4(), "alice".to_string());
+        claims.exp = Utc::now().timestamp() - 3600;
+        assert!(auth.validate_token(&sign(&auth, &claims)).is_err());
+    }
+
+    #[test]
+    fn tokens_signed_with_another_secret_are_refused() {
+        let claims = Claims::new(Uuid::new_v4(), "alice".to_string());
+        let token = sign(&service("other"), &claims);
+        assert!(service("secret").validate_token(&token).is_err());
+    }
+
+    #[test]
+    fn tampered_tokens_are_refused() {
+        let auth = service("secret");
+        let token = sign(&auth, &Claims::new(Uuid::new_v4(), "alice".to_string()));
+        let forged = sign(&service("other"), &Claims::new(Uuid::new_v4(), "mallory".to_string()));
+        let (header_and_claims, _) = forged.rsplit_once('.').unwrap();
+        let (_, signature) = token.rsplit_once('.').unwrap();
+        assert!(auth.validate_token(&format!("{}.{}", header_and_claims, signature)).is_err());
+    }
+
+    fn redeem(auth: &AuthService, token: &str, user_id: Uuid, email: &str) -> bool {
+        parse_email_verification_token(token).is_some_and(|(token_user, expires_at, signature)| {
+            token_user == user_id && auth.email_verification_signed(user_id, email, expires_at, &signature)
+        })
+    }
+
+    #[test]
+    fn verification_tokens_round_trip() {
+        let auth = service("secret");
+        let user_id = Uuid::new_v4();
+        let token = auth.email_verification_token(user_id, "alice@example.com", Duration::from_secs(3600));
+        assert!(redeem(&auth, &token, user_id, "alice@example.com"));
+    }
+
+    #[test]
+    fn verification_tokens_only_verify_their_address() {
+        let auth = service("secret");
+        let user_id = Uuid::new_v4();
+        let token = auth.email_verification_token(user_id, "alice@example.com", Duration::from_secs(3600));
+        assert!(!redeem(&auth, &token, user_id, "mallory@example.com"));
+        assert!(!redeem(&service("other"), &token, user_id, "alice@example.com"));
+    }
+
+    #[test]
+    fn tampered_verification_tokens_are_refused() {
+        let auth = service("secret");
+        let user_id = Uuid::new_v4();
+        let token = auth.email_verification_token(user_id, "alice@example.com", Duration::from_secs(3600));
+        let (_, expires_at, signature) = parse_email_verification_token(&token).unwrap();
+
+        let other_user = Uuid::new_v4();
+        let moved = format!("{}.{}.{}", other_user, expires_at, hex::encode(&signature));
+        assert!(!redeem(&auth, &moved, other_user, "alice@example.com"));
+
+        let extended = format!("{}.{}.{}", user_id, expires_at + 86400, hex::encode(&signature));
+        assert!(!redeem(&auth, &extended, user_id, "alice@example.com"));
+
+        let mut flipped = signature.clone();
+        flipped[0] ^= 1;
+        let forged = format!("{}.{}.{}", user_id, expires_at, hex::encode(&flipped));
+        assert!(!redeem(&auth, &forged, user_id, "alice@example.com"));
+    }
+
+    #[test]
+    fn malformed_and_expired_verification_tokens_are_refused() {
+        let auth = service("secret");
+        let user_id = Uuid::new_v4();
+        for token in ["", "not-a-token", &format!("{}.soon.abcd", user_id), &format!("{}.4102444800.zz", user_id)] {
+            assert!(parse_email_verification_token(token).is_none(), "{token} should be refused");
+        }
+        let expires_at = Utc::now().timestamp() - 1;
+        let signature = auth.email_verification_mac(user_id, "alice@example.com", expires_at).finalize();
+        let expired = format!("{}.{}.{}", user_id, expires_at, hex::encode(signature.into_bytes()));
+        assert!(parse_email_verification_token(&expired).is_none());
+    }
+}
@@ -0,0 +1,59 @@
+use crate::database::connection::DbPool;
+use crate::models::user_block::UserBlock;
+use crate::ws_router::ChatRouter;
+use crate::ws_server::{BlockChanged, SessionFilter};
+use uuid::Uuid;
+
+/// The filter a new session of the user starts with
+pub async fn session_filter(pool: &DbPool, user_id: &str) -> Result<SessionFilter, sqlx::Error> {
+    let Ok(user_id) = Uuid::parse_str(user_id) else {
+        return Ok(SessionFilter::default());
+    };
+    let blocked = UserBlock::find_by_blocker_id(pool, user_id)
+        .await?
+        .into_iter()
+        .map(|block| (block.blocked_id.to_string(), block.hide_messages))
+        .collect();
+    let blocked_by = UserBlock::find_by_blocked_id(pool, user_id)
+        .await?
+        .into_iter()
+        .map(|block| block.blocker_id.to_string())
+        .collect();
+    Ok(SessionFilter::new(blocked, blocked_by))
+}
+
+/// Stores a block and applies it to both users' live sessions
+pub async fn block(
+    pool: &DbPool,
+    router: &ChatRouter,
+    blocker_id: Uuid,
+    blocked_id: Uuid,
+    hide_messages: bool,
+) -> Result<UserBlock, sqlx::Error> {
+    let block = UserBlock::set(pool, blocker_id, blocked_id, hide_messages).await?;
+    notify_sessions(router, blocker_id, blocked_id, Some(block.hide_messages));
+    Ok(block)
+}
+
+/// Lifts a block, returning whether there was one
+pub async fn unblock(
+    pool: &DbPool,
+    router: &ChatRouter,
+    blocker_id: Uuid,
+    blocked_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let removed = UserBlock::remove(pool, blocker_id, blocked_id).await?;
+    if removed {
+        notify_sessions(router, blocker_id, blocked_id, None);
+    }
+    Ok(removed)
+}
+
+fn notify_sessions(router: &ChatRouter, blocker_id: Uuid, blocked_id: Uuid, hide_messages: Option<bool>) {
+    let blocker_id = blocker_id.to_string();
+    router.user(&blocker_id).do_send(BlockChanged {
+        blocker_id: blocker_id.clone(),
+        blocked_id: blocked_id.to_string(),
+        hide_messages,
+    });
+}
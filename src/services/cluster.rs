@@ -0,0 +1,261 @@
+use crate::database::connection::DbPool;
+use crate::utils::types::ServerMessage;
+use crate::ws_router::ChatRouter;
+use serde::{Deserialize, Serialize};
+use sqlx::postgres::PgListener;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+/// An event that must reach sessions connected to every node
+#[derive(Debug, Serialize, Deserialize, actix::Message)]
+#[rtype(result = "()")]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ClusterEvent {
+    Room {
+        room_id: String,
+        seq: Option<i64>,
+        message_id: Option<Uuid>,
+        message: ServerMessage,
+    },
+    Private {
+        to: String,
+        message: ServerMessage,
+    },
+    /// Reaches every session of the user, unlike `Private`
+    User {
+        user_id: String,
+        message: ServerMessage,
+    },
+    /// Sessions of this user must be closed
+    Revoke {
+        user_id: String,
+    },
+    /// The user's sessions must leave the room, they were kicked or banned
+    Kick {
+        room_id: String,
+        user_id: String,
+    },
+    /// A user blocked or unblocked another, both users' sessions update their filters
+    Block {
+        blocker_id: String,
+        blocked_id: String,
+        /// `None` when the block was lifted
+        hide_messages: Option<bool>,
+    },
+}
+
+/// Postgres refuses notification payloads of this many bytes or more
+const MAX_NOTIFY_BYTES: usize = 8000;
+/// Stored events are kept this long for nodes to load, then cleaned up as new ones are stored
+const STORED_EVENT_TTL_SECS: i64 = 5 * 60;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ClusterEnvelope<E = ClusterEvent> {
+    node_id: Uuid,
+    #[serde(flatten)]
+    body: EnvelopeBody<E>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum EnvelopeBody<E> {
+    Event(E),
+    /// Id of the `cluster_events` row holding an event too large to notify
+    Stored(Uuid),
+}
+
+/// Fans room events out to other rusty-chat nodes through Postgres `NOTIFY`.
+///
+/// Every node publishes the events its local sessions produce and listens on the
+/// same channel, delivering what other nodes published to its own sessions.
+/// Notifications are limited to 8000 bytes by Postgres, larger events are stored
+/// in `cluster_events` and the notification carries their id.
+#[derive(Clone)]
+pub struct ClusterBus {
+    node_id: Uuid,
+    channel: String,
+    pool: DbPool,
+    outgoing: mpsc::UnboundedSender<ClusterEvent>,
+}
+
+impl ClusterBus {
+    /// Creates the bus and starts the task publishing events in the order they were sent
+    pub fn new(pool: DbPool, channel: String) -> Self {
+        let node_id = Uuid::new_v4();
+        let (outgoing, mut rx) = mpsc::unbounded_channel::<ClusterEvent>();
+
+        let publish_pool = pool.clone();
+        let publish_channel = channel.clone();
+        tokio::spawn(async move {
+            while let Some(event) = rx.recv().await {
+                if let Err(e) = Self::send(&publish_pool, &publish_channel, node_id, &event).await {
+                    warn!("Failed to publish cluster event: {}", e);
+                }
+            }
+        });
+
+        Self {
+            node_id,
+            channel,
+            pool,
+            outgoing,
+        }
+    }
+
+    pub fn publish(&self, event: ClusterEvent) {
+        if self.outgoing.send(event).is_err() {
+            error!("Cluster publisher has stopped, event dropped");
+        }
+    }
+
+    /// Listens for events published by other nodes and hands them to the local chat shards
+    pub fn listen(&self, router: ChatRouter) {
+        let pool = self.pool.clone();
+        let channel = self.channel.clone();
+        let node_id = self.node_id;
+
+        tokio::spawn(async move {
+            let mut listener = loop {
+                match Self::connect_listener(&pool, &channel).await {
+                    Ok(listener) => break listener,
+                    Err(e) => {
+                        error!("Failed to listen on cluster channel {}: {}", channel, e);
+                        tokio::time::sleep(Duration::from_secs(5)).await;
+                    }
+                }
+            };
+            info!("Node {} listening on cluster channel {}", node_id, channel);
+
+            loop {
+                // recv() transparently reconnects, events published meanwhile are lost
+                let notification = match listener.recv().await {
+                    Ok(notification) => notification,
+                    Err(e) => {
+                        warn!("Cluster listener error: {}", e);
+                        tokio::time::sleep(Duration::from_secs(1)).await;
+                        continue;
+                    }
+                };
+
+                let envelope = match serde_json::from_str::<ClusterEnvelope>(notification.payload()) {
+                    Ok(envelope) if envelope.node_id == node_id => continue,
+                    Ok(envelope) => envelope,
+                    Err(e) => {
+                        warn!("Ignoring malformed cluster event: {}", e);
+                        continue;
+                    }
+                };
+                match envelope.body {
+                    EnvelopeBody::Event(event) => router.deliver(event),
+                    // Loaded before the next notification is read, so events stay in order
+                    EnvelopeBody::Stored(id) => match Self::load(&pool, id).await {
+                        Ok(Some(event)) => router.deliver(event),
+                        Ok(None) => warn!("Stored cluster event {} is gone", id),
+                        Err(e) => error!("Failed to load stored cluster event {}: {}", id, e),
+                    },
+                }
+            }
+        });
+    }
+
+    /// Notifies the event, or stores it and notifies its id when it is too large
+    async fn send(pool: &DbPool, channel: &str, node_id: Uuid, event: &ClusterEvent) -> Result<(), sqlx::Error> {
+        let mut payload = encode(node_id, EnvelopeBody::Event(event))?;
+        if payload.len() >= MAX_NOTIFY_BYTES {
+            let id = Self::store(pool, event).await?;
+            payload = encode(node_id, EnvelopeBody::Stored(id))?;
+        }
+        sqlx::query("SELECT pg_notify($1, $2)")
+            .bind(channel)
+            .bind(&payload)
+            .execute(pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn store(pool: &DbPool, event: &ClusterEvent) -> Result<Uuid, sqlx::Error> {
+        sqlx::query("DELETE FROM cluster_events WHERE created_at < NOW() - make_interval(secs => $1)")
+            .bind(STORED_EVENT_TTL_SECS as f64)
+            .execute(pool)
+            .await?;
+
+        let id = Uuid::new_v4();
+        let payload = serde_json::to_string(event).map_err(|e| sqlx::Error::Encode(Box::new(e)))?;
+        sqlx::query("INSERT INTO cluster_events (id, payload, created_at) VALUES ($1, $2, NOW())")
+            .bind(id)
+            .bind(payload)
+            .execute(pool)
+            .await?;
+        Ok(id)
+    }
+
+    async fn load(pool: &DbPool, id: Uuid) -> Result<Option<ClusterEvent>, sqlx::Error> {
+        let payload: Option<String> = sqlx::query_scalar("SELECT payload FROM cluster_events WHERE id = $1")
+            .bind(id)
+            .fetch_optional(pool)
+            .await?;
+        payload
+            .map(|payload| serde_json::from_str(&payload).map_err(|e| sqlx::Error::Decode(Box::new(e))))
+            .transpose()
+    }
+
+    async fn connect_listener(pool: &DbPool, channel: &str) -> Result<PgListener, sqlx::Error> {
+        let mut listener = PgListener::connect_with(pool).await?;
+        listener.listen(channel).await?;
+        Ok(listener)
+    }
+}
+
+fn encode(node_id: Uuid, body: EnvelopeBody<&ClusterEvent>) -> Result<String, sqlx::Error> {
+    serde_json::to_string(&ClusterEnvelope { node_id, body }).map_err(|e| sqlx::Error::Encode(Box::new(e)))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::message::DisplayOverride;
+
+    fn room_event(content: String) -> ClusterEvent {
+        ClusterEvent::Room {
+            room_id: "general".to_string(),
+            seq: Some(7),
+            message_id: None,
+            message: ServerMessage::Text {
+                room_id: "general".to_string(),
+                content,
+                user_id: "alice".to_string(),
+                seq: Some(7),
+                message_id: None,
+                display: DisplayOverride::default(),
+                mentions: Vec::new(),
+            },
+        }
+    }
+
+    #[test]
+    fn events_round_trip_inline() {
+        let node_id = Uuid::new_v4();
+        let payload = encode(node_id, EnvelopeBody::Event(&room_event("hi".to_string()))).unwrap();
+        let envelope: ClusterEnvelope = serde_json::from_str(&payload).unwrap();
+        assert_eq!(envelope.node_id, node_id);
+        assert!(matches!(envelope.body, EnvelopeBody::Event(ClusterEvent::Room { seq: Some(7), .. })));
+    }
+
+    #[test]
+    fn stored_events_are_notified_by_id() {
+        let (node_id, id) = (Uuid::new_v4(), Uuid::new_v4());
+        let payload = encode(node_id, EnvelopeBody::Stored(id)).unwrap();
+        let envelope: ClusterEnvelope = serde_json::from_str(&payload).unwrap();
+        assert!(matches!(envelope.body, EnvelopeBody::Stored(stored) if stored == id));
+    }
+
+    #[test]
+    fn long_multi_byte_messages_do_not_fit_a_notification() {
+        // 4000 characters, the default message limit, of 3-byte text
+        let payload = encode(Uuid::new_v4(), EnvelopeBody::Event(&room_event("語".repeat(4000)))).unwrap();
+        assert!(payload.len() >= MAX_NOTIFY_BYTES);
+    }
+}
@@ -0,0 +1,584 @@
+use crate::database::connection::DbPool;
+use crate::models::bot_command::BotCommand;
+use crate::models::message::DisplayOverride;
+use crate::models::notification::NotificationKind;
+use crate::models::room::{Room, UpdateRoom};
+use crate::models::room_member::RoomMember;
+use crate::models::user::User;
+use crate::models::user_block::UserBlock;
+use crate::services::messaging::{self, PostError};
+use crate::services::{notifications, outbound};
+use crate::services::webhooks::{sign, SIGNATURE_HEADER};
+use crate::utils::types::ServerMessage;
+use crate::ws_router::ChatRouter;
+use crate::ws_server::{MemberAdded, NotifyUser, RoomUpdated};
+use futures_util::future::LocalBoxFuture;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::LazyLock;
+use std::time::Duration;
+use tracing::warn;
+use uuid::Uuid;
+
+/// Bots get this long to answer a command
+const BOT_COMMAND_TIMEOUT: Duration = Duration::from_secs(5);
+/// Shown when something needs a room but the session is in none
+pub const NO_ROOM: &str = "Join a room first";
+/// Longer answers are dropped
+const MAX_BOT_RESPONSE_BYTES: usize = 64 * 1024;
+
+static BUILTINS: LazyLock<CommandRegistry> = LazyLock::new(CommandRegistry::builtins);
+
+static BOT_CLIENT: LazyLock<reqwest::Client> = LazyLock::new(|| {
+    outbound::client_builder()
+        .timeout(BOT_COMMAND_TIMEOUT)
+        .build()
+        .unwrap_or_default()
+});
+
+/// The registry of built-in commands used by chat sessions
+pub fn registry() -> &'static CommandRegistry {
+    &BUILTINS
+}
+
+/// A `/name args` text frame
+#[derive(Debug, Clone)]
+pub struct Invocation {
+    pub name: String,
+    pub args: String,
+}
+
+/// Reads a slash command out of a text frame. Text starting with `//` is an escaped
+/// slash and is not a command.
+pub fn parse(content: &str) -> Option<Invocation> {
+    let input = content.strip_prefix('/')?;
+    if input.starts_with('/') {
+        return None;
+    }
+    let (name, args) = input.split_once(char::is_whitespace).unwrap_or((input, ""));
+    if name.is_empty() {
+        return None;
+    }
+    Some(Invocation {
+        name: name.to_lowercase(),
+        args: args.trim().to_string(),
+    })
+}
+
+/// Strips the escaping slash from text starting with `//`
+pub fn unescape(content: String) -> String {
+    match content.strip_prefix("//") {
+        Some(rest) => format!("/{}", rest),
+        None => content,
+    }
+}
+
+/// Who ran a command, and where
+pub struct CommandContext {
+    pub pool: DbPool,
+    pub router: ChatRouter,
+    pub user_id: String,
+    /// Empty when the session is not in a room
+    pub room_id: String,
+}
+
+impl CommandContext {
+    /// The room id, for rooms backed by the `rooms` table
+    fn room_uuid(&self) -> Option<Uuid> {
+        Uuid::parse_str(&self.room_id).ok()
+    }
+
+    fn user_uuid(&self) -> Option<Uuid> {
+        Uuid::parse_str(&self.user_id).ok()
+    }
+}
+
+pub enum CommandOutcome {
+    /// Shown to the invoker only
+    Reply(String),
+    /// The invoker left the current room
+    Leave,
+    /// Nothing more to do, e.g. the command posted to the room itself
+    Done,
+}
+
+pub trait SlashCommand: Send + Sync {
+    /// Invoked as `/<name>`
+    fn name(&self) -> &'static str;
+    fn usage(&self) -> &'static str;
+    fn description(&self) -> &'static str;
+    fn run<'a>(
+        &'a self,
+        ctx: &'a CommandContext,
+        args: &'a str,
+    ) -> LocalBoxFuture<'a, Result<CommandOutcome, sqlx::Error>>;
+}
+
+/// Command handlers by name. Names not registered here are looked up among the
+/// commands bots registered through the API.
+#[derive(Default)]
+pub struct CommandRegistry {
+    commands: BTreeMap<&'static str, Box<dyn SlashCommand>>,
+}
+
+impl CommandRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn builtins() -> Self {
+        let mut registry = Self::new();
+        registry.register(Help);
+        registry.register(Me);
+        registry.register(Topic);
+        registry.register(Invite);
+        registry.register(Leave);
+        registry.register(Mute);
+        registry
+    }
+
+    pub fn register(&mut self, command: impl SlashCommand + 'static) {
+        self.commands.insert(command.name(), Box::new(command));
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn SlashCommand> {
+        self.commands.get(name).map(|command| command.as_ref())
+    }
+
+    pub fn is_registered(&self, name: &str) -> bool {
+        self.commands.contains_key(name)
+    }
+
+    /// Registered commands, sorted by name
+    pub fn commands(&self) -> impl Iterator<Item = &dyn SlashCommand> {
+        self.commands.values().map(|command| command.as_ref())
+    }
+
+    pub async fn dispatch(
+        &self,
+        ctx: &CommandContext,
+        invocation: &Invocation,
+    ) -> Result<CommandOutcome, sqlx::Error> {
+        if let Some(command) = self.get(&invocation.name) {
+            return command.run(ctx, &invocation.args).await;
+        }
+        let command = match ctx.room_uuid() {
+            Some(room_id) => BotCommand::find_in_room(&ctx.pool, &invocation.name, room_id).await?,
+            None => None,
+        };
+        match command {
+            Some(command) => run_bot_command(ctx, &command, &invocation.args).await,
+            None => Ok(CommandOutcome::Reply(format!(
+                "Unknown command /{}, try /help",
+                invocation.name
+            ))),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct BotCommandRequest<'a> {
+    command: &'a str,
+    args: &'a str,
+    user_id: &'a str,
+    room_id: &'a str,
+}
+
+#[derive(Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum ResponseType {
+    /// Only the invoker sees the reply
+    #[default]
+    Ephemeral,
+    /// The reply is posted to the room as the bot
+    InChannel,
+}
+
+/// What a bot's callback URL answers with, an empty body means no reply
+#[derive(Debug, Deserialize)]
+struct BotCommandResponse {
+    text: String,
+    #[serde(default)]
+    response_type: ResponseType,
+}
+
+/// POSTs the invocation to the bot, signed like outgoing webhooks with the command's secret
+async fn run_bot_command(
+    ctx: &CommandContext,
+    command: &BotCommand,
+    args: &str,
+) -> Result<CommandOutcome, sqlx::Error> {
+    let body = serde_json::to_vec(&BotCommandRequest {
+        command: &command.name,
+        args,
+        user_id: &ctx.user_id,
+        room_id: &ctx.room_id,
+    })
+    .unwrap_or_default();
+    let failed = || Ok(CommandOutcome::Reply(format!("/{} did not respond", command.name)));
+    let url = match outbound::parse_url(&command.callback_url) {
+        Ok(url) => url,
+        Err(reason) => {
+            warn!("Callback url of bot command /{} {}", command.name, reason);
+            return failed();
+        }
+    };
+
+    let response = BOT_CLIENT
+        .post(url)
+        .header("Content-Type", "application/json")
+        .header(SIGNATURE_HEADER, sign(&command.secret, &body))
+        .body(body)
+        .send()
+        .await;
+    let body = match response {
+        Ok(response) if response.status().is_success() => read_capped(response).await,
+        Ok(response) => {
+            warn!("Bot command /{} answered {}", command.name, response.status());
+            return failed();
+        }
+        Err(e) => {
+            warn!("Bot command /{} failed: {}", command.name, e);
+            return failed();
+        }
+    };
+    let body = match body {
+        Ok(Some(body)) if body.is_empty() => return Ok(CommandOutcome::Done),
+        Ok(Some(body)) => body,
+        Ok(None) => {
+            warn!("Bot command /{} answered with more than {} bytes", command.name, MAX_BOT_RESPONSE_BYTES);
+            return failed();
+        }
+        Err(e) => {
+            warn!("Failed to read the answer of bot command /{}: {}", command.name, e);
+            return failed();
+        }
+    };
+    let Ok(reply) = serde_json::from_slice::<BotCommandResponse>(&body) else {
+        warn!("Bot command /{} answered with an invalid body", command.name);
+        return failed();
+    };
+
+    match reply.response_type {
+        ResponseType::Ephemeral => Ok(CommandOutcome::Reply(reply.text)),
+        ResponseType::InChannel => post(ctx, &command.bot_id.to_string(), reply.text).await,
+    }
+}
+
+/// Reads a response body, `None` if it is longer than `MAX_BOT_RESPONSE_BYTES`
+async fn read_capped(mut response: reqwest::Response) -> Result<Option<Vec<u8>>, reqwest::Error> {
+    if response
+        .content_length()
+        .is_some_and(|length| length > MAX_BOT_RESPONSE_BYTES as u64)
+    {
+        return Ok(None);
+    }
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        if body.len() + chunk.len() > MAX_BOT_RESPONSE_BYTES {
+            return Ok(None);
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(Some(body))
+}
+
+/// Posts to the command's room, telling the invoker if a message hook rejected it
+async fn post(ctx: &CommandContext, sender_id: &str, content: String) -> Result<CommandOutcome, sqlx::Error> {
+    if ctx.room_id.is_empty() {
+        return Ok(CommandOutcome::Reply(NO_ROOM.to_string()));
+    }
+    let posted = messaging::post_room_message(
+        &ctx.pool,
+        &ctx.router,
+        &ctx.room_id,
+        sender_id,
+        content,
+        DisplayOverride::default(),
+    )
+    .await;
+    match posted {
+        Ok(_) => Ok(CommandOutcome::Done),
+        Err(PostError::Rejected(reason)) => Ok(CommandOutcome::Reply(reason)),
+        Err(PostError::Database(e)) => Err(e),
+    }
+}
+
+fn not_supported(name: &str) -> Result<CommandOutcome, sqlx::Error> {
+    Ok(CommandOutcome::Reply(format!("/{} is not available in this room", name)))
+}
+
+fn usage(command: &dyn SlashCommand) -> Result<CommandOutcome, sqlx::Error> {
+    Ok(CommandOutcome::Reply(format!("Usage: {}", command.usage())))
+}
+
+struct Help;
+
+impl SlashCommand for Help {
+    fn name(&self) -> &'static str {
+        "help"
+    }
+
+    fn usage(&self) -> &'static str {
+        "/help"
+    }
+
+    fn description(&self) -> &'static str {
+        "List the available commands"
+    }
+
+    fn run<'a>(&'a self, ctx: &'a CommandContext, _: &'a str) -> LocalBoxFuture<'a, Result<CommandOutcome, sqlx::Error>> {
+        Box::pin(async move {
+            let mut lines: Vec<String> = registry()
+                .commands()
+                .map(|command| format!("{} - {}", command.usage(), command.description()))
+                .collect();
+            if let Some(room_id) = ctx.room_uuid() {
+                for command in BotCommand::find_by_room_id(&ctx.pool, room_id).await? {
+                    lines.push(format!("/{} - {}", command.name, command.description));
+                }
+            }
+            Ok(CommandOutcome::Reply(lines.join("\n")))
+        })
+    }
+}
+
+struct Me;
+
+impl SlashCommand for Me {
+    fn name(&self) -> &'static str {
+        "me"
+    }
+
+    fn usage(&self) -> &'static str {
+        "/me <action>"
+    }
+
+    fn description(&self) -> &'static str {
+        "Post an action, e.g. /me waves"
+    }
+
+    fn run<'a>(&'a self, ctx: &'a CommandContext, args: &'a str) -> LocalBoxFuture<'a, Result<CommandOutcome, sqlx::Error>> {
+        Box::pin(async move {
+            if args.is_empty() {
+                return usage(self);
+            }
+            let user = match ctx.user_uuid() {
+                Some(user_id) => User::find_by_id(&ctx.pool, user_id).await?,
+                None => None,
+            };
+            let name = user.map_or_else(|| ctx.user_id.clone(), |user| user.username);
+            post(ctx, &ctx.user_id, format!("* {} {}", name, args)).await
+        })
+    }
+}
+
+struct Topic;
+
+impl SlashCommand for Topic {
+    fn name(&self) -> &'static str {
+        "topic"
+    }
+
+    fn usage(&self) -> &'static str {
+        "/topic [new topic]"
+    }
+
+    fn description(&self) -> &'static str {
+        "Show the room topic, or set it if you own the room"
+    }
+
+    fn run<'a>(&'a self, ctx: &'a CommandContext, args: &'a str) -> LocalBoxFuture<'a, Result<CommandOutcome, sqlx::Error>> {
+        Box::pin(async move {
+            let Some(room_id) = ctx.room_uuid() else {
+                return not_supported(self.name());
+            };
+            let Some(room) = Room::find_by_id(&ctx.pool, room_id).await? else {
+                return not_supported(self.name());
+            };
+            if args.is_empty() {
+                let topic = room.topic.unwrap_or_else(|| "No topic set".to_string());
+                return Ok(CommandOutcome::Reply(topic));
+            }
+            if ctx.user_uuid() != Some(room.created_by) {
+                return Ok(CommandOutcome::Reply("Only the room owner can set the topic".to_string()));
+            }
+
+            let update = UpdateRoom {
+                name: None,
+                is_private: None,
+                topic: Some(args.to_string()),
+            };
+            if let Some(room) = Room::update(&ctx.pool, room_id, update).await? {
+                ctx.router.room(&ctx.room_id).do_send(RoomUpdated { room });
+            }
+            Ok(CommandOutcome::Done)
+        })
+    }
+}
+
+struct Invite;
+
+impl SlashCommand for Invite {
+    fn name(&self) -> &'static str {
+        "invite"
+    }
+
+    fn usage(&self) -> &'static str {
+        "/invite @user"
+    }
+
+    fn description(&self) -> &'static str {
+        "Add a user to the room"
+    }
+
+    fn run<'a>(&'a self, ctx: &'a CommandContext, args: &'a str) -> LocalBoxFuture<'a, Result<CommandOutcome, sqlx::Error>> {
+        Box::pin(async move {
+            let Some(username) = args.strip_prefix('@').filter(|name| !name.is_empty()) else {
+                return usage(self);
+            };
+            let (Some(room_id), Some(user_id)) = (ctx.room_uuid(), ctx.user_uuid()) else {
+                return not_supported(self.name());
+            };
+            if RoomMember::find(&ctx.pool, room_id, user_id).await?.is_none() {
+                return Ok(CommandOutcome::Reply("Only members can invite to this room".to_string()));
+            }
+            let Some(invitee) = User::find_by_username(&ctx.pool, username).await? else {
+                return Ok(CommandOutcome::Reply(format!("No user named @{}", username)));
+            };
+            if UserBlock::exists(&ctx.pool, invitee.id, user_id).await? {
+                return Ok(CommandOutcome::Reply(format!("You cannot invite @{}", invitee.username)));
+            }
+
+            if RoomMember::add(&ctx.pool, room_id, invitee.id, Some(user_id)).await?.is_none() {
+                return Ok(CommandOutcome::Reply(format!("@{} is already a member", invitee.username)));
+            }
+            ctx.router.room(&ctx.room_id).do_send(MemberAdded {
+                room_id: ctx.room_id.clone(),
+                user_id: invitee.id.to_string(),
+                invited_by: ctx.user_id.clone(),
+            });
+            let to = invitee.id.to_string();
+            ctx.router.user(&to).do_send(NotifyUser {
+                to: to.clone(),
+                message: ServerMessage::Invite {
+                    room_id: ctx.room_id.clone(),
+                    from: ctx.user_id.clone(),
+                },
+            });
+            notifications::notify(
+                &ctx.pool,
+                &ctx.router,
+                invitee.id,
+                NotificationKind::RoomInvite,
+                serde_json::json!({ "room_id": room_id, "from": user_id }),
+            )
+            .await?;
+            Ok(CommandOutcome::Reply(format!("Invited @{}", invitee.username)))
+        })
+    }
+}
+
+struct Leave;
+
+impl SlashCommand for Leave {
+    fn name(&self) -> &'static str {
+        "leave"
+    }
+
+    fn usage(&self) -> &'static str {
+        "/leave"
+    }
+
+    fn description(&self) -> &'static str {
+        "Leave the room"
+    }
+
+    fn run<'a>(&'a self, ctx: &'a CommandContext, _: &'a str) -> LocalBoxFuture<'a, Result<CommandOutcome, sqlx::Error>> {
+        Box::pin(async move {
+            if let (Some(room_id), Some(user_id)) = (ctx.room_uuid(), ctx.user_uuid()) {
+                RoomMember::remove(&ctx.pool, room_id, user_id).await?;
+            }
+            Ok(CommandOutcome::Leave)
+        })
+    }
+}
+
+struct Mute;
+
+impl SlashCommand for Mute {
+    fn name(&self) -> &'static str {
+        "mute"
+    }
+
+    fn usage(&self) -> &'static str {
+        "/mute [off]"
+    }
+
+    fn description(&self) -> &'static str {
+        "Mute notifications from the room, or unmute them with /mute off"
+    }
+
+    fn run<'a>(&'a self, ctx: &'a CommandContext, args: &'a str) -> LocalBoxFuture<'a, Result<CommandOutcome, sqlx::Error>> {
+        Box::pin(async move {
+            let muted = match args {
+                "" | "on" => true,
+                "off" => false,
+                _ => return usage(self),
+            };
+            let (Some(room_id), Some(user_id)) = (ctx.room_uuid(), ctx.user_uuid()) else {
+                return not_supported(self.name());
+            };
+            if RoomMember::set_muted(&ctx.pool, room_id, user_id, muted).await?.is_none() {
+                return Ok(CommandOutcome::Reply("You are not a member of this room".to_string()));
+            }
+            let reply = if muted { "Room muted" } else { "Room unmuted" };
+            Ok(CommandOutcome::Reply(reply.to_string()))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn invocation(content: &str) -> Option<(String, String)> {
+        parse(content).map(|invocation| (invocation.name, invocation.args))
+    }
+
+    #[test]
+    fn parse_splits_name_and_args() {
+        assert_eq!(invocation("/me waves"), Some(("me".to_string(), "waves".to_string())));
+        assert_eq!(invocation("/TOPIC  New topic  "), Some(("topic".to_string(), "New topic".to_string())));
+        assert_eq!(invocation("/help"), Some(("help".to_string(), String::new())));
+        assert_eq!(invocation("/invite\tbob"), Some(("invite".to_string(), "bob".to_string())));
+    }
+
+    #[test]
+    fn parse_ignores_plain_and_escaped_text() {
+        assert_eq!(invocation("hello /me"), None);
+        assert_eq!(invocation("//me is not a command"), None);
+        assert_eq!(invocation("/"), None);
+        assert_eq!(invocation("/ me"), None);
+    }
+
+    #[test]
+    fn unescape_strips_one_slash() {
+        assert_eq!(unescape("//me".to_string()), "/me");
+        assert_eq!(unescape("///".to_string()), "//");
+        assert_eq!(unescape("/me".to_string()), "/me");
+        assert_eq!(unescape("a // b".to_string()), "a // b");
+    }
+
+    #[test]
+    fn builtins_are_registered_by_name() {
+        let registry = CommandRegistry::builtins();
+        for name in ["help", "me", "topic", "invite", "leave", "mute"] {
+            assert!(registry.is_registered(name), "/{name} should be registered");
+        }
+        let names: Vec<&str> = registry.commands().map(|command| command.name()).collect();
+        let mut sorted = names.clone();
+        sorted.sort();
+        assert_eq!(names, sorted);
+    }
+}
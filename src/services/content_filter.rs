@@ -0,0 +1,432 @@
+use crate::config::settings::ModerationConfig;
+use crate::database::connection::DbPool;
+use crate::models::content_rule::{ContentRule, RuleAction, RuleKind};
+use regex::{Regex, RegexBuilder};
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::{Arc, LazyLock, RwLock};
+use std::time::Duration;
+use tracing::{error, warn};
+use uuid::Uuid;
+
+/// Shown when a block rule matches, without saying which one
+pub const BLOCKED: &str = "Your message was blocked by the content filter";
+
+/// Compiled regexes are capped so one rule cannot make every message slow
+const REGEX_SIZE_LIMIT: usize = 1 << 20;
+
+/// Links, capturing the host in one of the three groups: `http(s)` URLs, `www.` hosts, and
+/// bare hosts followed by a path. A bare `name.tld` alone is not taken for a link, since
+/// file names look the same.
+static LINK: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(concat!(
+        r"(?i)\bhttps?://([^\s/?#<>]+)[^\s<>]*",
+        r"|\b(www\.[a-z0-9-]+(?:\.[a-z0-9-]+)+(?::\d+)?)(?:[/?#][^\s<>]*)?",
+        r"|\b([a-z0-9-]+(?:\.[a-z0-9-]+)*\.[a-z]{2,63}(?::\d+)?)[/?#][^\s<>]*",
+    ))
+    .expect("valid link regex")
+});
+
+/// What the filter decided about a message
+pub enum Verdict {
+    /// The message may go out as `content`, with masked parts replaced. `flagged_by`
+    /// describes the flag rules it matched, it is queued for review when not empty.
+    Allow { content: String, flagged_by: Vec<String> },
+    /// The message is dropped, the reason is shown to the sender
+    Block(String),
+}
+
+/// Number of links in a message
+pub fn count_links(content: &str) -> usize {
+    LINK.find_iter(content).count()
+}
+
+/// The stored content rules, compiled and kept in memory. Clones share the same policy.
+///
+/// Until the rules are first loaded every message is allowed.
+#[derive(Clone, Default)]
+pub struct ContentFilter {
+    policy: Arc<RwLock<Arc<ContentPolicy>>>,
+}
+
+impl ContentFilter {
+    /// Loads the rules into memory, then reloads them every `rules_refresh_secs` so
+    /// changes made through other nodes are picked up
+    pub async fn start(pool: DbPool, config: ModerationConfig) -> Result<Self, sqlx::Error> {
+        let filter = Self::default();
+        filter.reload(&pool, config.max_message_chars).await?;
+        let refreshed = filter.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(config.rules_refresh_secs.max(1)));
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                if let Err(e) = refreshed.reload(&pool, config.max_message_chars).await {
+                    error!("Failed to reload content rules: {}", e);
+                }
+            }
+        });
+        Ok(filter)
+    }
+
+    /// Replaces the in-memory policy with the stored rules, right after they change
+    pub async fn reload(&self, pool: &DbPool, max_message_chars: usize) -> Result<(), sqlx::Error> {
+        let rules = ContentRule::find_all(pool).await?;
+        let policy = ContentPolicy::compile(rules, max_message_chars);
+        *self.policy.write().unwrap() = Arc::new(policy);
+        Ok(())
+    }
+
+    /// Checks a message against the global rules and, for room messages, the room's own
+    pub fn check(&self, room_id: Option<Uuid>, content: String) -> Verdict {
+        let policy = self.policy.read().unwrap().clone();
+        policy.check(room_id, content)
+    }
+}
+
+/// Why a rule cannot be stored, checked before it is
+pub fn validate(kind: RuleKind, pattern: &str, action: RuleAction) -> Result<(), String> {
+    if pattern.trim().is_empty() {
+        return Err("pattern must not be empty".to_string());
+    }
+    match kind {
+        RuleKind::MaxLength => {
+            if action != RuleAction::Block {
+                return Err("max_length rules only block".to_string());
+            }
+            match pattern.parse::<usize>() {
+                Ok(chars) if chars > 0 => Ok(()),
+                _ => Err("max_length rules take a positive character count".to_string()),
+            }
+        }
+        RuleKind::Link => {
+            if pattern.contains(|c: char| c.is_whitespace() || matches!(c, '/' | ':' | '@')) {
+                return Err("link rules take a domain, e.g. example.com".to_string());
+            }
+            Ok(())
+        }
+        RuleKind::Word | RuleKind::Regex => {
+            if action == RuleAction::Allow {
+                return Err("allow is only used by link rules".to_string());
+            }
+            compile_pattern(kind, pattern).map(|_| ()).map_err(|e| format!("invalid regex: {}", e))
+        }
+    }
+}
+
+fn compile_pattern(kind: RuleKind, pattern: &str) -> Result<Regex, regex::Error> {
+    let pattern = match kind {
+        RuleKind::Word => {
+            let word = pattern.trim();
+            // `\b` only applies next to word characters, e.g. not after `c++`
+            let boundary = |c: Option<char>| if c.is_some_and(|c| c.is_alphanumeric() || c == '_') { r"\b" } else { "" };
+            format!(
+                "{}{}{}",
+                boundary(word.chars().next()),
+                regex::escape(word),
+                boundary(word.chars().last())
+            )
+        }
+        _ => pattern.to_string(),
+    };
+    RegexBuilder::new(&pattern)
+        .case_insensitive(kind == RuleKind::Word)
+        .size_limit(REGEX_SIZE_LIMIT)
+        .build()
+}
+
+struct PatternRule {
+    regex: Regex,
+    action: RuleAction,
+    description: String,
+}
+
+/// The rules of one room, or the global ones
+#[derive(Default)]
+struct RuleSet {
+    patterns: Vec<PatternRule>,
+    allowed_domains: Vec<String>,
+    denied_domains: Vec<(String, RuleAction)>,
+    max_chars: Option<usize>,
+}
+
+impl RuleSet {
+    fn add(&mut self, rule: &ContentRule) {
+        let (Some(kind), Some(action)) = (rule.kind(), rule.action()) else {
+            warn!("Skipping content rule {} of unknown kind or action", rule.id);
+            return;
+        };
+        match kind {
+            RuleKind::MaxLength => match rule.pattern.parse::<usize>() {
+                Ok(chars) => self.max_chars = Some(self.max_chars.map_or(chars, |max| max.min(chars))),
+                Err(_) => warn!("Skipping content rule {} with an invalid length", rule.id),
+            },
+            RuleKind::Link => {
+                let domain = rule.pattern.trim().trim_start_matches("*.").to_lowercase();
+                match action {
+                    RuleAction::Allow => self.allowed_domains.push(domain),
+                    action => self.denied_domains.push((domain, action)),
+                }
+            }
+            RuleKind::Word | RuleKind::Regex => match compile_pattern(kind, &rule.pattern) {
+                Ok(regex) => self.patterns.push(PatternRule {
+                    regex,
+                    action,
+                    description: format!("{} \"{}\"", kind.as_str(), rule.pattern),
+                }),
+                Err(e) => warn!("Skipping content rule {}: {}", rule.id, e),
+            },
+        }
+    }
+}
+
+/// Every rule compiled, swapped as a whole on reload
+#[derive(Default)]
+struct ContentPolicy {
+    /// `None` until the rules are first loaded
+    max_message_chars: Option<usize>,
+    global: RuleSet,
+    rooms: HashMap<Uuid, RuleSet>,
+}
+
+impl ContentPolicy {
+    fn compile(rules: Vec<ContentRule>, max_message_chars: usize) -> Self {
+        let mut policy = Self {
+            max_message_chars: Some(max_message_chars),
+            ..Self::default()
+        };
+        for rule in &rules {
+            match rule.room_id {
+                Some(room_id) => policy.rooms.entry(room_id).or_default().add(rule),
+                None => policy.global.add(rule),
+            }
+        }
+        policy
+    }
+
+    fn check(&self, room_id: Option<Uuid>, content: String) -> Verdict {
+        let sets: Vec<&RuleSet> = std::iter::once(&self.global)
+            .chain(room_id.and_then(|room_id| self.rooms.get(&room_id)))
+            .collect();
+
+        let limit = sets
+            .iter()
+            .filter_map(|set| set.max_chars)
+            .chain(self.max_message_chars)
+            .min();
+        if let Some(limit) = limit {
+            if content.chars().count() > limit {
+                return Verdict::Block(format!("Messages are limited to {} characters", limit));
+            }
+        }
+
+        let mut masked: Vec<Range<usize>> = Vec::new();
+        let mut flagged_by = Vec::new();
+        for rule in sets.iter().flat_map(|set| &set.patterns) {
+            let mut matches = rule.regex.find_iter(&content).peekable();
+            if matches.peek().is_none() {
+                continue;
+            }
+            match rule.action {
+                RuleAction::Block => return Verdict::Block(BLOCKED.to_string()),
+                RuleAction::Mask => masked.extend(matches.map(|m| m.range())),
+                RuleAction::Flag => flagged_by.push(rule.description.clone()),
+                RuleAction::Allow => {}
+            }
+        }
+
+        let allow_list: Vec<&String> = sets.iter().flat_map(|set| &set.allowed_domains).collect();
+        for link in LINK.captures_iter(&content) {
+            let (Some(url), Some(host)) = (link.get(0), link.get(1).or(link.get(2)).or(link.get(3))) else {
+                continue;
+            };
+            let host = link_host(host.as_str());
+            let denied = sets
+                .iter()
+                .flat_map(|set| &set.denied_domains)
+                .find(|(domain, _)| on_domain(&host, domain))
+                .map(|(_, action)| *action);
+            let action = match denied {
+                Some(action) => action,
+                None if !allow_list.is_empty() && !allow_list.iter().any(|domain| on_domain(&host, domain)) => {
+                    return Verdict::Block(format!("Links to {} are not allowed here", host));
+                }
+                None => continue,
+            };
+            match action {
+                RuleAction::Block => return Verdict::Block(format!("Links to {} are not allowed here", host)),
+                RuleAction::Mask => masked.push(url.range()),
+                RuleAction::Flag => flagged_by.push(format!("link \"{}\"", host)),
+                RuleAction::Allow => {}
+            }
+        }
+
+        let content = if masked.is_empty() {
+            content
+        } else {
+            content
+                .char_indices()
+                .map(|(i, c)| {
+                    if !c.is_whitespace() && masked.iter().any(|range| range.contains(&i)) {
+                        '*'
+                    } else {
+                        c
+                    }
+                })
+                .collect()
+        };
+        Verdict::Allow { content, flagged_by }
+    }
+}
+
+/// The lowercase host of a link, without credentials or port
+fn link_host(authority: &str) -> String {
+    let host = authority.rsplit('@').next().unwrap_or(authority);
+    let host = host.split(':').next().unwrap_or(host);
+    host.trim_end_matches('.').to_lowercase()
+}
+
+fn on_domain(host: &str, domain: &str) -> bool {
+    host == domain || host.strip_suffix(domain).is_some_and(|sub| sub.ends_with('.'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn rule(room_id: Option<Uuid>, kind: RuleKind, pattern: &str, action: RuleAction) -> ContentRule {
+        ContentRule {
+            id: Uuid::new_v4(),
+            room_id,
+            kind: kind.as_str().to_string(),
+            pattern: pattern.to_string(),
+            action: action.as_str().to_string(),
+            created_by: Uuid::new_v4(),
+            created_at: Utc::now(),
+        }
+    }
+
+    fn policy(rules: Vec<ContentRule>) -> ContentPolicy {
+        ContentPolicy::compile(rules, 100)
+    }
+
+    fn allowed(verdict: Verdict) -> (String, Vec<String>) {
+        match verdict {
+            Verdict::Allow { content, flagged_by } => (content, flagged_by),
+            Verdict::Block(reason) => panic!("unexpectedly blocked: {reason}"),
+        }
+    }
+
+    fn blocked(verdict: Verdict) -> String {
+        match verdict {
+            Verdict::Block(reason) => reason,
+            Verdict::Allow { content, .. } => panic!("unexpectedly allowed: {content}"),
+        }
+    }
+
+    #[test]
+    fn block_rules_reject_whole_words_only() {
+        let policy = policy(vec![rule(None, RuleKind::Word, "spam", RuleAction::Block)]);
+        assert_eq!(blocked(policy.check(None, "Buy SPAM now".to_string())), BLOCKED);
+        assert_eq!(allowed(policy.check(None, "spammy but fine".to_string())).0, "spammy but fine");
+    }
+
+    #[test]
+    fn mask_rules_replace_matches_with_asterisks() {
+        let policy = policy(vec![
+            rule(None, RuleKind::Word, "darn", RuleAction::Mask),
+            rule(None, RuleKind::Regex, r"\d{4}-\d{4}", RuleAction::Mask),
+        ]);
+        let (content, flagged_by) = allowed(policy.check(None, "darn, call 1234-5678".to_string()));
+        assert_eq!(content, "****, call *********");
+        assert!(flagged_by.is_empty());
+    }
+
+    #[test]
+    fn flag_rules_let_the_message_through() {
+        let policy = policy(vec![rule(None, RuleKind::Word, "refund", RuleAction::Flag)]);
+        let (content, flagged_by) = allowed(policy.check(None, "I want a refund".to_string()));
+        assert_eq!(content, "I want a refund");
+        assert_eq!(flagged_by, vec!["word \"refund\"".to_string()]);
+    }
+
+    #[test]
+    fn room_rules_only_apply_in_their_room() {
+        let room = Uuid::new_v4();
+        let policy = policy(vec![rule(Some(room), RuleKind::Word, "offtopic", RuleAction::Block)]);
+        assert_eq!(blocked(policy.check(Some(room), "offtopic".to_string())), BLOCKED);
+        allowed(policy.check(Some(Uuid::new_v4()), "offtopic".to_string()));
+        allowed(policy.check(None, "offtopic".to_string()));
+    }
+
+    #[test]
+    fn the_lowest_length_limit_applies() {
+        let room = Uuid::new_v4();
+        let policy = policy(vec![rule(Some(room), RuleKind::MaxLength, "5", RuleAction::Block)]);
+        assert_eq!(
+            blocked(policy.check(Some(room), "too long".to_string())),
+            "Messages are limited to 5 characters"
+        );
+        allowed(policy.check(None, "too long".to_string()));
+        assert_eq!(
+            blocked(policy.check(None, "x".repeat(101))),
+            "Messages are limited to 100 characters"
+        );
+    }
+
+    #[test]
+    fn denied_domains_cover_subdomains_and_every_link_form() {
+        let policy = policy(vec![rule(None, RuleKind::Link, "*.evil.com", RuleAction::Block)]);
+        for content in [
+            "see https://evil.com",
+            "see http://user@Sub.EVIL.com:8080/path",
+            "see www.evil.com",
+            "see evil.com/path",
+        ] {
+            let reason = blocked(policy.check(None, content.to_string()));
+            assert!(reason.ends_with("evil.com are not allowed here"), "{content}: {reason}");
+        }
+        allowed(policy.check(None, "see https://notevil.com and evil.com".to_string()));
+    }
+
+    #[test]
+    fn allow_lists_block_other_domains() {
+        let room = Uuid::new_v4();
+        let policy = policy(vec![rule(Some(room), RuleKind::Link, "example.com", RuleAction::Allow)]);
+        allowed(policy.check(Some(room), "https://docs.example.com/a".to_string()));
+        assert_eq!(
+            blocked(policy.check(Some(room), "https://other.org".to_string())),
+            "Links to other.org are not allowed here"
+        );
+        allowed(policy.check(None, "https://other.org".to_string()));
+    }
+
+    #[test]
+    fn masked_links_keep_surrounding_text() {
+        let policy = policy(vec![rule(None, RuleKind::Link, "tracker.io", RuleAction::Mask)]);
+        let (content, _) = allowed(policy.check(None, "go to https://tracker.io/x now".to_string()));
+        assert_eq!(content, format!("go to {} now", "*".repeat("https://tracker.io/x".len())));
+    }
+
+    #[test]
+    fn link_regex_skips_text_that_only_looks_like_hosts() {
+        assert_eq!(count_links("https://a.com www.b.org c.net/path"), 3);
+        assert_eq!(count_links("notes.txt, e.g. and/or 1.5/2 bob@evil.com"), 0);
+        assert_eq!(count_links("ftp://files.example.com"), 0);
+    }
+
+    #[test]
+    fn link_host_strips_credentials_port_and_case() {
+        assert_eq!(link_host("User:Pass@Example.COM:8443"), "example.com");
+        assert_eq!(link_host("example.com."), "example.com");
+    }
+
+    #[test]
+    fn on_domain_matches_subdomains_only() {
+        assert!(on_domain("example.com", "example.com"));
+        assert!(on_domain("a.b.example.com", "example.com"));
+        assert!(!on_domain("badexample.com", "example.com"));
+        assert!(!on_domain("example.com.evil.org", "example.com"));
+    }
+}
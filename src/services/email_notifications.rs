@@ -0,0 +1,147 @@
+use crate::database::connection::DbPool;
+use crate::models::email_opt_out::EmailOptOut;
+use crate::models::room::Room;
+use crate::models::user::User;
+use crate::services::email_templates;
+use crate::services::mailer::Mailer;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+use tracing::error;
+use uuid::Uuid;
+
+/// Longest excerpt of a message quoted in an email
+const EXCERPT_CHARS: usize = 140;
+
+/// Something that happened to an offline user, `category` is a notification kind
+struct PendingEmail {
+    user_id: Uuid,
+    category: String,
+    data: serde_json::Value,
+}
+
+/// Emails users about notifications they got while not connected.
+///
+/// The first notification of a user starts a digest window; everything arriving for them
+/// before it closes goes out in the same email.
+#[derive(Clone)]
+pub struct EmailNotifier {
+    outgoing: mpsc::UnboundedSender<PendingEmail>,
+}
+
+impl EmailNotifier {
+    pub fn start(pool: DbPool, mailer: Arc<dyn Mailer>, digest_delay: Duration) -> Self {
+        let (outgoing, mut rx) = mpsc::unbounded_channel::<PendingEmail>();
+
+        tokio::spawn(async move {
+            let mut batches: HashMap<Uuid, (Instant, Vec<PendingEmail>)> = HashMap::new();
+            loop {
+                let next_due = batches.values().map(|(due, _)| *due).min();
+                tokio::select! {
+                    pending = rx.recv() => {
+                        let Some(pending) = pending else {
+                            break;
+                        };
+                        batches
+                            .entry(pending.user_id)
+                            .or_insert_with(|| (Instant::now() + digest_delay, Vec::new()))
+                            .1
+                            .push(pending);
+                    }
+                    _ = tokio::time::sleep_until(next_due.unwrap_or_else(Instant::now)), if next_due.is_some() => {
+                        let now = Instant::now();
+                        let due: Vec<Uuid> = batches
+                            .iter()
+                            .filter(|(_, (due, _))| *due <= now)
+                            .map(|(user_id, _)| *user_id)
+                            .collect();
+                        for user_id in due {
+                            let Some((_, items)) = batches.remove(&user_id) else {
+                                continue;
+                            };
+                            let pool = pool.clone();
+                            let mailer = mailer.clone();
+                            tokio::spawn(async move {
+                                if let Err(e) = send_digest(&pool, mailer.as_ref(), user_id, items).await {
+                                    error!("Failed to email notifications to user {}: {}", user_id, e);
+                                }
+                            });
+                        }
+                    }
+                }
+            }
+        });
+
+        Self { outgoing }
+    }
+
+    pub fn enqueue(&self, user_id: Uuid, category: &str, data: serde_json::Value) {
+        let _ = self.outgoing.send(PendingEmail {
+            user_id,
+            category: category.to_string(),
+            data,
+        });
+    }
+}
+
+async fn send_digest(
+    pool: &DbPool,
+    mailer: &dyn Mailer,
+    user_id: Uuid,
+    items: Vec<PendingEmail>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let Some(user) = User::find_by_id(pool, user_id).await? else {
+        return Ok(());
+    };
+    // Anyone can sign up with someone else's address, only verified ones get mail
+    if user.is_bot || user.email_verified_at.is_none() {
+        return Ok(());
+    }
+    let opted_out = EmailOptOut::find_by_user_id(pool, user_id).await?;
+
+    let mut lines = Vec::new();
+    for item in items.iter().filter(|item| !opted_out.contains(&item.category)) {
+        lines.push(describe(pool, item).await?);
+    }
+    if lines.is_empty() {
+        return Ok(());
+    }
+
+    let email = email_templates::notification_digest(&user.email, &user.username, &lines);
+    mailer.send(&email).await?;
+    Ok(())
+}
+
+/// One line of the digest, e.g. "@alice mentioned you in #general: lunch?"
+async fn describe(pool: &DbPool, item: &PendingEmail) -> Result<String, sqlx::Error> {
+    let from = match uuid_field(&item.data, "from") {
+        Some(id) => User::find_by_id(pool, id).await?.map(|user| format!("@{}", user.username)),
+        None => None,
+    }
+    .unwrap_or_else(|| "Someone".to_string());
+    let room = match uuid_field(&item.data, "room_id") {
+        Some(id) => Room::find_by_id(pool, id).await?.map(|room| format!("#{}", room.name)),
+        None => None,
+    }
+    .unwrap_or_else(|| "a room".to_string());
+    let excerpt = item.data["content"]
+        .as_str()
+        .map(|content| content.chars().take(EXCERPT_CHARS).collect::<String>())
+        .unwrap_or_default();
+
+    let line = match item.category.as_str() {
+        "mention" => format!("{} mentioned you in {}: {}", from, room, excerpt),
+        "direct_message" => format!("{} sent you a message: {}", from, excerpt),
+        "room_invite" => format!("{} invited you to {}", from, room),
+        "role_change" => format!("Your role in {} changed: {}", room, excerpt),
+        "report_resolved" => format!("Your report of a message in {} was reviewed", room),
+        other => format!("New {} notification", other),
+    };
+    Ok(line)
+}
+
+fn uuid_field(data: &serde_json::Value, key: &str) -> Option<Uuid> {
+    data[key].as_str().and_then(|id| Uuid::parse_str(id).ok())
+}
@@ -0,0 +1,59 @@
+use crate::services::mailer::Email;
+use std::time::Duration;
+
+const EMAIL_VERIFICATION: &str = include_str!("../../templates/email/email_verification.txt");
+const NOTIFICATION_DIGEST: &str = include_str!("../../templates/email/notification_digest.txt");
+const PASSWORD_RESET: &str = include_str!("../../templates/email/password_reset.txt");
+
+/// Replaces every `{{key}}` in a template
+fn render(template: &str, values: &[(&str, &str)]) -> String {
+    values.iter().fold(template.to_string(), |body, (key, value)| {
+        body.replace(&format!("{{{{{}}}}}", key), value)
+    })
+}
+
+/// One email summing up notifications a user missed, one line each
+pub fn notification_digest(to: &str, username: &str, lines: &[String]) -> Email {
+    let subject = match lines {
+        [line] => line.clone(),
+        _ => format!("{} new notifications", lines.len()),
+    };
+    let items: Vec<String> = lines.iter().map(|line| format!("- {}", line)).collect();
+
+    Email {
+        to: to.to_string(),
+        subject,
+        body: render(
+            NOTIFICATION_DIGEST,
+            &[("username", username), ("items", &items.join("\n"))],
+        ),
+    }
+}
+
+/// The link a new user follows to prove they own their address
+pub fn email_verification(to: &str, username: &str, link: &str, expires_in: Duration) -> Email {
+    let hours = (expires_in.as_secs() / 3600).max(1).to_string();
+
+    Email {
+        to: to.to_string(),
+        subject: "Verify your email address".to_string(),
+        body: render(
+            EMAIL_VERIFICATION,
+            &[("username", username), ("link", link), ("hours", &hours)],
+        ),
+    }
+}
+
+/// The link a user who forgot their password follows to choose a new one
+pub fn password_reset(to: &str, username: &str, link: &str, expires_in: Duration) -> Email {
+    let minutes = (expires_in.as_secs() / 60).max(1).to_string();
+
+    Email {
+        to: to.to_string(),
+        subject: "Reset your password".to_string(),
+        body: render(
+            PASSWORD_RESET,
+            &[("username", username), ("link", link), ("minutes", &minutes)],
+        ),
+    }
+}
@@ -0,0 +1,60 @@
+use crate::config::settings::AppConfig;
+use crate::database::connection::DbPool;
+use crate::models::room::Room;
+use crate::models::room_member::RoomMember;
+use crate::models::user::User;
+use crate::services::auth::AuthService;
+use crate::services::email_templates;
+use crate::services::mailer::Email;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Shown to users `auth.require_verified_email` keeps out
+pub const VERIFY_FIRST: &str = "Verify your email address first";
+
+/// The email with the link verifying `address`, the user's current or pending one
+pub fn verification_email(auth: &AuthService, config: &AppConfig, user: &User, address: &str) -> Email {
+    let ttl = Duration::from_secs(config.auth.email_verification_ttl_secs);
+    let token = auth.email_verification_token(user.id, address, ttl);
+    let link = format!(
+        "{}/verify-email?token={}",
+        config.mail.app_url.trim_end_matches('/'),
+        token
+    );
+    email_templates::email_verification(address, &user.username, &link, ttl)
+}
+
+/// Whether the user may not post or join public rooms because `required` is set and
+/// they have not verified their email. Bots have no address and are never restricted.
+pub async fn is_restricted(pool: &DbPool, required: bool, user_id: &str) -> Result<bool, sqlx::Error> {
+    if !required {
+        return Ok(false);
+    }
+    let Ok(user_id) = Uuid::parse_str(user_id) else {
+        return Ok(true);
+    };
+    let restricted = match User::find_by_id(pool, user_id).await? {
+        Some(user) => !user.is_bot && user.email_verified_at.is_none(),
+        None => true,
+    };
+    Ok(restricted)
+}
+
+/// Whether the user may join a room, restricted users only get into private ones they are members of
+pub async fn may_join(
+    pool: &DbPool,
+    required: bool,
+    user_id: &str,
+    room_id: &str,
+) -> Result<bool, sqlx::Error> {
+    if !is_restricted(pool, required, user_id).await? {
+        return Ok(true);
+    }
+    let (Ok(user_id), Ok(room_id)) = (Uuid::parse_str(user_id), Uuid::parse_str(room_id)) else {
+        return Ok(false);
+    };
+    let private = Room::find_by_id(pool, room_id)
+        .await?
+        .is_some_and(|room| room.is_private);
+    Ok(private && RoomMember::find(pool, room_id, user_id).await?.is_some())
+}
@@ -0,0 +1,233 @@
+use crate::models::api_token::API_TOKEN_PREFIX;
+use crate::models::message::{DisplayOverride, Message};
+use crate::ws_server::BroadcastMessage;
+use std::sync::LazyLock;
+use tracing::info;
+
+static HOOKS: LazyLock<HookRegistry> = LazyLock::new(HookRegistry::compiled_in);
+
+/// The hooks every room message goes through
+pub fn registry() -> &'static HookRegistry {
+    &HOOKS
+}
+
+/// A room message on its way to being stored
+#[derive(Debug, Clone)]
+pub struct DraftMessage {
+    pub room_id: String,
+    pub sender_id: String,
+    pub content: String,
+    pub display: DisplayOverride,
+}
+
+pub enum HookOutcome {
+    Continue,
+    /// Drops the message, the reason is shown to the sender
+    Reject(String),
+}
+
+/// A compiled-in plugin seeing every room message, whichever transport it came in through.
+///
+/// Hooks run on the actor or request handling the message, so anything slow should be
+/// spawned rather than awaited.
+pub trait MessageHook: Send + Sync {
+    /// Identifies the hook in logs
+    fn name(&self) -> &'static str;
+
+    /// May rewrite or reject the message. Runs for ephemeral rooms too, which are never stored.
+    fn before_persist(&self, _message: &mut DraftMessage) -> HookOutcome {
+        HookOutcome::Continue
+    }
+
+    /// The message as stored, with its id and sequence number
+    fn after_persist(&self, _message: &Message) {}
+
+    /// May rewrite what room members receive, or keep a stored message from being broadcast
+    fn before_fanout(&self, _message: &mut BroadcastMessage) -> HookOutcome {
+        HookOutcome::Continue
+    }
+}
+
+/// Hooks in the order they run; the first rejection stops the message
+#[derive(Default)]
+pub struct HookRegistry {
+    hooks: Vec<Box<dyn MessageHook>>,
+}
+
+impl HookRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Plugins built into the server, register new ones here
+    pub fn compiled_in() -> Self {
+        let mut registry = Self::new();
+        registry.register(RedactApiTokens);
+        registry
+    }
+
+    pub fn register(&mut self, hook: impl MessageHook + 'static) {
+        self.hooks.push(Box::new(hook));
+    }
+
+    /// The rejection reason, if a hook rejected the message
+    pub fn before_persist(&self, message: &mut DraftMessage) -> Option<String> {
+        self.hooks.iter().find_map(|hook| match hook.before_persist(message) {
+            HookOutcome::Continue => None,
+            HookOutcome::Reject(reason) => {
+                info!(
+                    "Message hook {} rejected a message of user {} in room {}: {}",
+                    hook.name(),
+                    message.sender_id,
+                    message.room_id,
+                    reason
+                );
+                Some(reason)
+            }
+        })
+    }
+
+    pub fn after_persist(&self, message: &Message) {
+        for hook in &self.hooks {
+            hook.after_persist(message);
+        }
+    }
+
+    /// Whether the message should be broadcast
+    pub fn before_fanout(&self, message: &mut BroadcastMessage) -> bool {
+        self.hooks.iter().all(|hook| match hook.before_fanout(message) {
+            HookOutcome::Continue => true,
+            HookOutcome::Reject(reason) => {
+                info!("Message hook {} held back a message in room {}: {}", hook.name(), message.room_id, reason);
+                false
+            }
+        })
+    }
+}
+
+/// Bot API tokens are this long after their prefix
+const API_TOKEN_SECRET_LEN: usize = 64;
+
+/// Keeps bot API tokens pasted into a room out of history
+struct RedactApiTokens;
+
+impl MessageHook for RedactApiTokens {
+    fn name(&self) -> &'static str {
+        "redact-api-tokens"
+    }
+
+    fn before_persist(&self, message: &mut DraftMessage) -> HookOutcome {
+        if message.content.contains(API_TOKEN_PREFIX) {
+            message.content = redact_api_tokens(&message.content);
+        }
+        HookOutcome::Continue
+    }
+}
+
+fn redact_api_tokens(content: &str) -> String {
+    let mut redacted = String::with_capacity(content.len());
+    let mut rest = content;
+    while let Some(start) = rest.find(API_TOKEN_PREFIX) {
+        let secret = &rest[start + API_TOKEN_PREFIX.len()..];
+        let len = secret
+            .find(|c: char| !c.is_ascii_hexdigit())
+            .unwrap_or(secret.len());
+        redacted.push_str(&rest[..start]);
+        if len == API_TOKEN_SECRET_LEN {
+            redacted.push_str("[redacted]");
+        } else {
+            redacted.push_str(&rest[start..start + API_TOKEN_PREFIX.len() + len]);
+        }
+        rest = &secret[len..];
+    }
+    redacted.push_str(rest);
+    redacted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    fn token(secret_len: usize) -> String {
+        format!("{API_TOKEN_PREFIX}{}", &"a1".repeat(secret_len)[..secret_len])
+    }
+
+    #[test]
+    fn full_length_tokens_are_redacted() {
+        let content = format!("my token is {} oops", token(API_TOKEN_SECRET_LEN));
+        assert_eq!(redact_api_tokens(&content), "my token is [redacted] oops");
+    }
+
+    #[test]
+    fn shorter_and_longer_hex_runs_are_kept() {
+        let shorter = format!("{} is too short", token(API_TOKEN_SECRET_LEN - 1));
+        assert_eq!(redact_api_tokens(&shorter), shorter);
+
+        let longer = format!("{} is too long", token(API_TOKEN_SECRET_LEN + 1));
+        assert_eq!(redact_api_tokens(&longer), longer);
+    }
+
+    #[test]
+    fn every_token_is_redacted() {
+        let content = format!(
+            "{}, {} and {}",
+            token(API_TOKEN_SECRET_LEN),
+            token(8),
+            token(API_TOKEN_SECRET_LEN)
+        );
+        assert_eq!(redact_api_tokens(&content), format!("[redacted], {} and [redacted]", token(8)));
+    }
+
+    #[test]
+    fn multi_byte_text_around_tokens_is_kept() {
+        let content = format!("clé→{}✓ 日本{API_TOKEN_PREFIX}", token(API_TOKEN_SECRET_LEN));
+        assert_eq!(redact_api_tokens(&content), format!("clé→[redacted]✓ 日本{API_TOKEN_PREFIX}"));
+    }
+
+    struct Reject;
+
+    impl MessageHook for Reject {
+        fn name(&self) -> &'static str {
+            "reject"
+        }
+
+        fn before_persist(&self, _message: &mut DraftMessage) -> HookOutcome {
+            HookOutcome::Reject("not here".to_string())
+        }
+    }
+
+    struct Count(Arc<AtomicUsize>);
+
+    impl MessageHook for Count {
+        fn name(&self) -> &'static str {
+            "count"
+        }
+
+        fn before_persist(&self, _message: &mut DraftMessage) -> HookOutcome {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            HookOutcome::Continue
+        }
+    }
+
+    #[test]
+    fn hooks_after_a_rejection_do_not_run() {
+        let before = Arc::new(AtomicUsize::new(0));
+        let after = Arc::new(AtomicUsize::new(0));
+        let mut registry = HookRegistry::new();
+        registry.register(Count(before.clone()));
+        registry.register(Reject);
+        registry.register(Count(after.clone()));
+
+        let mut message = DraftMessage {
+            room_id: "general".to_string(),
+            sender_id: "alice".to_string(),
+            content: "hi".to_string(),
+            display: DisplayOverride::default(),
+        };
+        assert_eq!(registry.before_persist(&mut message).as_deref(), Some("not here"));
+        assert_eq!(before.load(Ordering::SeqCst), 1);
+        assert_eq!(after.load(Ordering::SeqCst), 0);
+    }
+}
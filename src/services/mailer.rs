@@ -0,0 +1,133 @@
+use crate::config::settings::MailConfig;
+use chrono::Utc;
+use futures_util::future::BoxFuture;
+use lettre::message::{header::ContentType, Mailbox};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Tokio1Executor};
+use serde::Deserialize;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Arc;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MailTransport {
+    /// No mail is sent
+    Disabled,
+    Smtp,
+    /// Writes each email as an `.eml` file to `mail.spool_dir`
+    File,
+    /// Prints each email, for development
+    Stdout,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum MailError {
+    #[error("invalid address: {0}")]
+    Address(#[from] lettre::address::AddressError),
+    #[error("failed to build email: {0}")]
+    Build(#[from] lettre::error::Error),
+    #[error("SMTP error: {0}")]
+    Smtp(#[from] lettre::transport::smtp::Error),
+    #[error("failed to spool email: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// A plain-text email
+#[derive(Debug, Clone)]
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+pub trait Mailer: Send + Sync {
+    fn send<'a>(&'a self, email: &'a Email) -> BoxFuture<'a, Result<(), MailError>>;
+}
+
+/// The mailer selected by `mail.transport`, `None` when mail is disabled
+pub fn from_config(config: &MailConfig) -> Result<Option<Arc<dyn Mailer>>, MailError> {
+    let from: Mailbox = config.from.parse()?;
+    let mailer: Arc<dyn Mailer> = match config.transport {
+        MailTransport::Disabled => return Ok(None),
+        MailTransport::Smtp => Arc::new(SmtpMailer::new(config, from)?),
+        MailTransport::File => Arc::new(SpoolMailer {
+            from,
+            dir: Some(PathBuf::from(&config.spool_dir)),
+        }),
+        MailTransport::Stdout => Arc::new(SpoolMailer { from, dir: None }),
+    };
+    Ok(Some(mailer))
+}
+
+fn build_message(from: &Mailbox, email: &Email) -> Result<lettre::Message, MailError> {
+    let message = lettre::Message::builder()
+        .from(from.clone())
+        .to(email.to.parse()?)
+        .subject(&email.subject)
+        .header(ContentType::TEXT_PLAIN)
+        .body(email.body.clone())?;
+    Ok(message)
+}
+
+pub struct SmtpMailer {
+    from: Mailbox,
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+}
+
+impl SmtpMailer {
+    pub fn new(config: &MailConfig, from: Mailbox) -> Result<Self, MailError> {
+        let mut builder = if config.smtp_tls {
+            AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&config.smtp_host)?
+        } else {
+            AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&config.smtp_host)
+        };
+        builder = builder.port(config.smtp_port);
+        if let (Some(username), Some(password)) = (&config.smtp_username, &config.smtp_password) {
+            builder = builder.credentials(Credentials::new(username.clone(), password.clone()));
+        }
+
+        Ok(Self {
+            from,
+            transport: builder.build(),
+        })
+    }
+}
+
+impl Mailer for SmtpMailer {
+    fn send<'a>(&'a self, email: &'a Email) -> BoxFuture<'a, Result<(), MailError>> {
+        Box::pin(async move {
+            let message = build_message(&self.from, email)?;
+            self.transport.send(message).await?;
+            Ok(())
+        })
+    }
+}
+
+/// Keeps emails instead of sending them: as files in `dir`, or on stdout without one
+pub struct SpoolMailer {
+    from: Mailbox,
+    dir: Option<PathBuf>,
+}
+
+impl Mailer for SpoolMailer {
+    fn send<'a>(&'a self, email: &'a Email) -> BoxFuture<'a, Result<(), MailError>> {
+        Box::pin(async move {
+            let message = build_message(&self.from, email)?.formatted();
+            match &self.dir {
+                Some(dir) => {
+                    tokio::fs::create_dir_all(dir).await?;
+                    let name = format!("{}-{}.eml", Utc::now().format("%Y%m%dT%H%M%S"), Uuid::new_v4());
+                    tokio::fs::write(dir.join(name), message).await?;
+                }
+                None => {
+                    let mut stdout = std::io::stdout().lock();
+                    stdout.write_all(&message)?;
+                    stdout.write_all(b"\n")?;
+                }
+            }
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,285 @@
+use crate::database::connection::DbPool;
+use crate::models::mention::{MentionKind, MentionSpan, MessageMention};
+use crate::models::message::Message;
+use crate::models::notification::NotificationKind;
+use crate::models::room::Room;
+use crate::models::room_member::RoomMember;
+use crate::models::user::User;
+use crate::models::user_block::UserBlock;
+use crate::services::notifications;
+use crate::utils::types::ServerMessage;
+use crate::ws_router::ChatRouter;
+use crate::ws_server::{NotifyUser, Presence, RoomPresence};
+use std::collections::{HashMap, HashSet};
+use tracing::error;
+use uuid::Uuid;
+
+/// An `@name` found in message content, before it is resolved
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParsedMention {
+    pub kind: MentionKind,
+    /// Character offsets, `start` is the `@`
+    pub start: usize,
+    pub end: usize,
+    pub name: String,
+}
+
+/// Mention spans to store with a message and the users to notify
+#[derive(Debug, Default)]
+pub struct ResolvedMentions {
+    pub spans: Vec<MentionSpan>,
+    pub notify: Vec<(Uuid, MentionKind)>,
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '.' | '-')
+}
+
+/// Finds `@username`, `@here` and `@room` in message content. An `@` only starts a
+/// mention at the beginning of a word, so email addresses are skipped.
+pub fn parse(content: &str) -> Vec<ParsedMention> {
+    let chars: Vec<char> = content.chars().collect();
+    let mut mentions = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let at_word_start = i == 0 || !(chars[i - 1].is_alphanumeric() || chars[i - 1] == '_');
+        if chars[i] != '@' || !at_word_start {
+            i += 1;
+            continue;
+        }
+        let mut end = i + 1;
+        while end < chars.len() && is_name_char(chars[end]) {
+            end += 1;
+        }
+        // Trailing punctuation ends the sentence rather than the name
+        while end > i + 1 && matches!(chars[end - 1], '.' | '-') {
+            end -= 1;
+        }
+        if end > i + 1 {
+            let name: String = chars[i + 1..end].iter().collect();
+            let kind = match name.as_str() {
+                "here" => MentionKind::Here,
+                "room" => MentionKind::Room,
+                _ => MentionKind::User,
+            };
+            mentions.push(ParsedMention {
+                kind,
+                start: i,
+                end,
+                name,
+            });
+        }
+        i = end.max(i + 1);
+    }
+    mentions
+}
+
+/// Resolves mentions against users and the room's members. Users mentioned by name are
+/// notified if they can read the room; `@here` and `@room` skip members who muted it.
+pub async fn resolve(
+    pool: &DbPool,
+    router: &ChatRouter,
+    room_id: Uuid,
+    sender_id: Uuid,
+    content: &str,
+) -> Result<ResolvedMentions, sqlx::Error> {
+    let parsed = parse(content);
+    if parsed.is_empty() {
+        return Ok(ResolvedMentions::default());
+    }
+
+    let usernames: Vec<String> = parsed
+        .iter()
+        .filter(|mention| mention.kind == MentionKind::User)
+        .map(|mention| mention.name.clone())
+        .collect();
+    let users: HashMap<String, Uuid> = User::find_by_usernames(pool, &usernames)
+        .await?
+        .into_iter()
+        .map(|user| (user.username, user.id))
+        .collect();
+    let is_private = Room::find_by_id(pool, room_id)
+        .await?
+        .is_some_and(|room| room.is_private);
+
+    let crowd = if parsed.iter().any(|mention| mention.kind != MentionKind::User) {
+        crowd(pool, router, room_id).await?
+    } else {
+        Crowd::default()
+    };
+
+    let mut resolved = ResolvedMentions::default();
+    let mut notified = HashSet::from([sender_id]);
+    for mention in &parsed {
+        let user_id = match mention.kind {
+            MentionKind::User => match users.get(&mention.name) {
+                Some(user_id) => Some(*user_id),
+                None => continue,
+            },
+            MentionKind::Here | MentionKind::Room => None,
+        };
+        resolved.spans.push(MentionSpan {
+            kind: mention.kind,
+            start: mention.start,
+            end: mention.end,
+            user_id,
+        });
+
+        let recipients = match user_id {
+            Some(user_id) if !is_private || RoomMember::find(pool, room_id, user_id).await?.is_some() => {
+                vec![user_id]
+            }
+            Some(_) => Vec::new(),
+            None if mention.kind == MentionKind::Here => crowd.here.clone(),
+            None => crowd.room.clone(),
+        };
+        for user_id in recipients {
+            if notified.insert(user_id) {
+                resolved.notify.push((user_id, mention.kind));
+            }
+        }
+    }
+
+    // Users who blocked the sender keep the span but are not notified
+    if !resolved.notify.is_empty() {
+        let candidates: Vec<Uuid> = resolved.notify.iter().map(|(user_id, _)| *user_id).collect();
+        let blockers = UserBlock::find_blocker_ids(pool, sender_id, &candidates).await?;
+        resolved.notify.retain(|(user_id, _)| !blockers.contains(user_id));
+    }
+
+    Ok(resolved)
+}
+
+/// Who `@here` and `@room` reach
+#[derive(Default)]
+struct Crowd {
+    here: Vec<Uuid>,
+    room: Vec<Uuid>,
+}
+
+/// `@room` reaches members who did not mute the room and, since public rooms need no
+/// membership, whoever is in the room right now. `@here` reaches those of them who are
+/// connected. Only sessions on this node are known, so with clustering `@here` misses
+/// members connected elsewhere.
+async fn crowd(pool: &DbPool, router: &ChatRouter, room_id: Uuid) -> Result<Crowd, sqlx::Error> {
+    let members = RoomMember::find_by_room_id(pool, room_id).await?;
+    let muted: HashSet<Uuid> = members.iter().filter(|m| m.muted).map(|m| m.user_id).collect();
+    let unmuted: Vec<Uuid> = members.iter().filter(|m| !m.muted).map(|m| m.user_id).collect();
+
+    let room_id = room_id.to_string();
+    let presence = router
+        .room(&room_id)
+        .send(RoomPresence {
+            room_id: room_id.clone(),
+            user_ids: unmuted.clone(),
+        })
+        .await
+        .unwrap_or_else(|e| {
+            error!("Failed to look up who is in room {}: {}", room_id, e);
+            Presence::default()
+        });
+    let in_room: Vec<Uuid> = presence
+        .in_room
+        .into_iter()
+        .filter(|user_id| !muted.contains(user_id))
+        .collect();
+
+    Ok(Crowd {
+        here: presence.online.into_iter().chain(in_room.iter().copied()).collect(),
+        room: unmuted.into_iter().chain(in_room).collect(),
+    })
+}
+
+/// Records who a stored message mentions, sends each of them a `Mention` event and
+/// adds it to their notifications
+pub async fn notify(
+    pool: &DbPool,
+    router: &ChatRouter,
+    message: &Message,
+    mentioned: &[(Uuid, MentionKind)],
+) -> Result<(), sqlx::Error> {
+    if mentioned.is_empty() {
+        return Ok(());
+    }
+
+    MessageMention::create_many(pool, message.id, message.room_id, mentioned).await?;
+    for (user_id, kind) in mentioned {
+        let to = user_id.to_string();
+        router.user(&to).do_send(NotifyUser {
+            to: to.clone(),
+            message: ServerMessage::Mention {
+                room_id: message.room_id.to_string(),
+                message_id: message.id.to_string(),
+                from: message.sender_id.to_string(),
+                content: message.content.clone(),
+                kind: *kind,
+            },
+        });
+        notifications::notify(
+            pool,
+            router,
+            *user_id,
+            NotificationKind::Mention,
+            serde_json::json!({
+                "room_id": message.room_id,
+                "message_id": message.id,
+                "from": message.sender_id,
+                "content": message.content,
+                "mention": kind,
+            }),
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(content: &str) -> Vec<(MentionKind, String)> {
+        parse(content).into_iter().map(|m| (m.kind, m.name)).collect()
+    }
+
+    #[test]
+    fn finds_users_here_and_room() {
+        assert_eq!(
+            names("@alice, @here and @room: ping @bob_2"),
+            vec![
+                (MentionKind::User, "alice".to_string()),
+                (MentionKind::Here, "here".to_string()),
+                (MentionKind::Room, "room".to_string()),
+                (MentionKind::User, "bob_2".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn offsets_are_in_characters() {
+        let mentions = parse("héllo @zoë!");
+        assert_eq!(mentions.len(), 1);
+        assert_eq!((mentions[0].start, mentions[0].end), (6, 10));
+        assert_eq!(mentions[0].name, "zoë");
+    }
+
+    #[test]
+    fn skips_email_addresses_and_bare_at_signs() {
+        assert!(parse("mail bob@example.com or alice_@x").is_empty());
+        assert!(parse("meet @ noon, @@ @.").is_empty());
+    }
+
+    #[test]
+    fn trailing_punctuation_is_not_part_of_the_name() {
+        assert_eq!(names("thanks @alice."), vec![(MentionKind::User, "alice".to_string())]);
+        assert_eq!(names("@first.last- ok"), vec![(MentionKind::User, "first.last".to_string())]);
+    }
+
+    #[test]
+    fn here_and_room_only_match_whole_names() {
+        assert_eq!(
+            names("@heres @roomy"),
+            vec![(MentionKind::User, "heres".to_string()), (MentionKind::User, "roomy".to_string())]
+        );
+    }
+}
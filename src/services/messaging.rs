@@ -0,0 +1,207 @@
+use crate::database::connection::DbPool;
+use crate::models::contact::Contact;
+use crate::models::message::{CreateMessage, DisplayOverride, Message};
+use crate::models::notification::NotificationKind;
+use crate::models::user_block::UserBlock;
+use crate::services::hooks::{self, DraftMessage};
+use crate::services::content_filter::Verdict;
+use crate::services::reports::FlagReason;
+use crate::services::spam::SpamOutcome;
+use crate::services::{mentions, moderation, notifications, reports};
+use crate::ws_router::ChatRouter;
+use crate::ws_server::{BroadcastMessage, PrivateMessage};
+use std::fmt;
+use tracing::error;
+use uuid::Uuid;
+
+#[derive(Debug)]
+pub enum PostError {
+    /// A message hook, the content filter or a sanction refused the message, with the reason shown to the sender
+    Rejected(String),
+    Database(sqlx::Error),
+}
+
+impl fmt::Display for PostError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PostError::Rejected(reason) => write!(f, "message rejected: {}", reason),
+            PostError::Database(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for PostError {}
+
+impl From<sqlx::Error> for PostError {
+    fn from(e: sqlx::Error) -> Self {
+        PostError::Database(e)
+    }
+}
+
+/// Stores a room message and broadcasts it with its sequence number, whichever
+/// transport it came in through. Rooms that are not backed by the `rooms` table
+/// are broadcast without being stored.
+///
+/// Message hooks run before the message is stored, after it is stored and before
+/// it is broadcast. The content filter runs right after the first of them, and
+/// senders who are not members of a private room, or are muted, kicked or banned
+/// from the room, are rejected. Messages to rooms in the database then go through
+/// spam detection.
+pub async fn post_room_message(
+    pool: &DbPool,
+    router: &ChatRouter,
+    room_id: &str,
+    sender_id: &str,
+    content: String,
+    display: DisplayOverride,
+) -> Result<Option<Message>, PostError> {
+    let hooks = hooks::registry();
+    let mut draft = DraftMessage {
+        room_id: room_id.to_string(),
+        sender_id: sender_id.to_string(),
+        content,
+        display,
+    };
+    if let Some(reason) = hooks.before_persist(&mut draft) {
+        return Err(PostError::Rejected(reason));
+    }
+    let mut flagged_by: Vec<FlagReason> = match router.content_filter().check(Uuid::parse_str(room_id).ok(), draft.content) {
+        Verdict::Allow { content, flagged_by } => {
+            draft.content = content;
+            flagged_by.into_iter().map(FlagReason::ContentRule).collect()
+        }
+        Verdict::Block(reason) => return Err(PostError::Rejected(reason)),
+    };
+
+    let (Ok(room_uuid), Ok(sender_uuid)) = (Uuid::parse_str(room_id), Uuid::parse_str(sender_id)) else {
+        fan_out_unstored(router, draft);
+        return Ok(None);
+    };
+
+    if let Some(reason) = moderation::privacy_refusal(pool, sender_id, room_id).await? {
+        return Err(PostError::Rejected(reason));
+    }
+    if let Some(reason) = moderation::post_refusal(pool, room_uuid, sender_uuid).await? {
+        return Err(PostError::Rejected(reason));
+    }
+
+    let mentions = mentions::resolve(pool, router, room_uuid, sender_uuid, &draft.content).await?;
+    let spam = router
+        .spam()
+        .screen(pool, router, room_uuid, sender_uuid, &draft.content, mentions.spans.len())
+        .await?;
+    match spam {
+        SpamOutcome::Clean | SpamOutcome::Warned => {}
+        SpamOutcome::Rejected(reason) => return Err(PostError::Rejected(reason)),
+        SpamOutcome::Flagged(description) => flagged_by.push(FlagReason::Spam(description)),
+    }
+    let stored = Message::create(
+        pool,
+        CreateMessage {
+            room_id: room_uuid,
+            sender_id: sender_uuid,
+            recipient_id: None,
+            content: draft.content.clone(),
+            display: draft.display.clone(),
+            mentions: mentions.spans,
+        },
+    )
+    .await?;
+    let Some(message) = stored else {
+        fan_out_unstored(router, draft);
+        return Ok(None);
+    };
+    hooks.after_persist(&message);
+
+    fan_out(
+        router,
+        BroadcastMessage {
+            room_id: room_id.to_string(),
+            message: message.content.clone(),
+            sender_id: sender_id.to_string(),
+            seq: Some(message.seq),
+            message_id: Some(message.id),
+            display: message.display.clone(),
+            mentions: message.mentions.clone(),
+        },
+    );
+
+    // The message is already stored and sent, failing here would only make the sender retry it
+    if let Err(e) = mentions::notify(pool, router, &message, &mentions.notify).await {
+        error!("Failed to notify users mentioned in message {}: {}", message.id, e);
+    }
+    if !flagged_by.is_empty() {
+        if let Err(e) = reports::flag(pool, &message, &flagged_by).await {
+            error!("Failed to queue flagged message {} for review: {}", message.id, e);
+        }
+    }
+
+    Ok(Some(message))
+}
+
+/// Broadcasts a message of a room that is not in the database, without a sequence number
+fn fan_out_unstored(router: &ChatRouter, draft: DraftMessage) {
+    fan_out(
+        router,
+        BroadcastMessage {
+            room_id: draft.room_id,
+            message: draft.content,
+            sender_id: draft.sender_id,
+            seq: None,
+            message_id: None,
+            display: draft.display,
+            mentions: Vec::new(),
+        },
+    );
+}
+
+fn fan_out(router: &ChatRouter, mut broadcast: BroadcastMessage) {
+    if hooks::registry().before_fanout(&mut broadcast) {
+        router.room(&broadcast.room_id).do_send(broadcast);
+    }
+}
+
+/// Sends a direct message, notifying the recipient when it is the first from this sender.
+/// Messages to a user who blocked the sender are rejected, the global content rules apply.
+pub async fn send_private_message(
+    pool: &DbPool,
+    router: &ChatRouter,
+    from: &str,
+    to: &str,
+    content: String,
+) -> Result<(), PostError> {
+    let ids = (Uuid::parse_str(from), Uuid::parse_str(to));
+    if let (Ok(from_uuid), Ok(to_uuid)) = ids {
+        if UserBlock::exists(pool, to_uuid, from_uuid).await? {
+            return Err(PostError::Rejected("You cannot message this user".to_string()));
+        }
+    }
+
+    // Direct messages have no room to review them in, flag rules do not apply
+    let content = match router.content_filter().check(None, content) {
+        Verdict::Allow { content, .. } => content,
+        Verdict::Block(reason) => return Err(PostError::Rejected(reason)),
+    };
+
+    router.user(to).do_send(PrivateMessage {
+        to: to.to_string(),
+        from: from.to_string(),
+        content,
+    });
+
+    let (Ok(from_uuid), Ok(to_uuid)) = ids else {
+        return Ok(());
+    };
+    if from_uuid != to_uuid && Contact::add(pool, from_uuid, to_uuid).await? {
+        notifications::notify(
+            pool,
+            router,
+            to_uuid,
+            NotificationKind::DirectMessage,
+            serde_json::json!({ "from": from_uuid }),
+        )
+        .await?;
+    }
+
+    Ok(())
+}
@@ -1 +1,18 @@
 pub mod auth;
+pub mod blocks;
+pub mod cluster;
+pub mod commands;
+pub mod content_filter;
+pub mod email_notifications;
+pub mod email_templates;
+pub mod email_verification;
+pub mod hooks;
+pub mod mailer;
+pub mod mentions;
+pub mod messaging;
+pub mod moderation;
+pub mod notifications;
+pub mod outbound;
+pub mod reports;
+pub mod spam;
+pub mod webhooks;
@@ -0,0 +1,181 @@
+use crate::database::connection::DbPool;
+use crate::models::message::Message;
+use crate::models::notification::NotificationKind;
+use crate::models::room_member::RoomMember;
+use crate::models::room_sanction::{CreateRoomSanction, RoomSanction, SanctionKind};
+use crate::models::user::User;
+use crate::services::notifications;
+use crate::utils::types::ServerMessage;
+use crate::ws_router::ChatRouter;
+use crate::ws_server::{MessageDeleted, Moderate};
+use chrono::{DateTime, Utc};
+use tracing::error;
+use uuid::Uuid;
+
+/// Shown to non-members trying to join a private room
+pub const PRIVATE_ROOM: &str = "This room is private";
+
+/// Warns, mutes, kicks or bans a member and announces it in the room. Kicked and banned
+/// members lose their membership and their sessions leave the room; without a
+/// duration a kick only removes them, a mute or ban lasts until lifted.
+pub async fn sanction(
+    pool: &DbPool,
+    router: &ChatRouter,
+    sanction: CreateRoomSanction,
+    target: &User,
+) -> Result<RoomSanction, sqlx::Error> {
+    let kind = sanction.kind;
+    let sanction = RoomSanction::create(pool, sanction).await?;
+    if kind.removes() {
+        RoomMember::remove(pool, sanction.room_id, sanction.user_id).await?;
+    }
+
+    let mut content = match kind {
+        SanctionKind::Warn => format!("{} was warned", target.username),
+        SanctionKind::Mute => format!("{} was muted", target.username),
+        SanctionKind::Kick => format!("{} was kicked", target.username),
+        SanctionKind::Ban => format!("{} was banned", target.username),
+    };
+    if let Some(expires_at) = sanction.expires_at.filter(|expires_at| *expires_at > sanction.created_at) {
+        content.push_str(&format!(" until {}", format_time(expires_at)));
+    }
+    if let Some(reason) = &sanction.reason {
+        content.push_str(&format!(": {}", reason));
+    }
+    announce(router, &sanction, kind, false, content.clone());
+    notify_sanctioned(pool, router, &sanction, kind, false, content).await;
+    Ok(sanction)
+}
+
+/// Lifts a sanction in force and announces it, `None` if there was none
+pub async fn lift(
+    pool: &DbPool,
+    router: &ChatRouter,
+    room_id: Uuid,
+    sanction_id: Uuid,
+) -> Result<Option<RoomSanction>, sqlx::Error> {
+    let Some(sanction) = RoomSanction::lift(pool, room_id, sanction_id).await? else {
+        return Ok(None);
+    };
+    let Some(kind) = sanction.kind() else {
+        return Ok(Some(sanction));
+    };
+
+    let username = User::find_by_id(pool, sanction.user_id)
+        .await?
+        .map_or_else(|| sanction.user_id.to_string(), |user| user.username);
+    let content = match kind {
+        SanctionKind::Mute => format!("{} was unmuted", username),
+        SanctionKind::Warn | SanctionKind::Kick | SanctionKind::Ban => format!("{} may rejoin the room", username),
+    };
+    announce(router, &sanction, kind, true, content.clone());
+    notify_sanctioned(pool, router, &sanction, kind, true, content).await;
+    Ok(Some(sanction))
+}
+
+/// Deletes a room message and tells the room's sessions to drop it
+pub async fn delete_message(pool: &DbPool, router: &ChatRouter, room_id: Uuid, message_id: Uuid) -> Result<bool, sqlx::Error> {
+    let deleted = Message::delete(pool, message_id).await?;
+    if deleted {
+        let room_id = room_id.to_string();
+        router.room(&room_id).do_send(MessageDeleted { room_id, message_id });
+    }
+    Ok(deleted)
+}
+
+/// Why the user may not post in the room, if a sanction is in force
+pub async fn post_refusal(pool: &DbPool, room_id: Uuid, user_id: Uuid) -> Result<Option<String>, sqlx::Error> {
+    let sanctions = RoomSanction::find_active(pool, room_id, user_id).await?;
+    Ok(sanctions
+        .iter()
+        .find(|sanction| sanction.kind().is_some_and(|kind| kind != SanctionKind::Warn))
+        .map(refusal))
+}
+
+/// Why the user may not join the room, if a kick or ban is in force
+pub async fn join_refusal(pool: &DbPool, user_id: &str, room_id: &str) -> Result<Option<String>, sqlx::Error> {
+    let (Ok(user_id), Ok(room_id)) = (Uuid::parse_str(user_id), Uuid::parse_str(room_id)) else {
+        return Ok(None);
+    };
+    let sanctions = RoomSanction::find_active(pool, room_id, user_id).await?;
+    Ok(sanctions
+        .iter()
+        .find(|sanction| sanction.kind().is_some_and(|kind| kind.removes()))
+        .map(refusal))
+}
+
+/// Why a user may not join a room they are not a member of, `None` for public rooms
+pub async fn privacy_refusal(pool: &DbPool, user_id: &str, room_id: &str) -> Result<Option<String>, sqlx::Error> {
+    let (Ok(user_id), Ok(room_id)) = (Uuid::parse_str(user_id), Uuid::parse_str(room_id)) else {
+        return Ok(None);
+    };
+    if RoomMember::may_read(pool, room_id, user_id).await? {
+        return Ok(None);
+    }
+    Ok(Some(PRIVATE_ROOM.to_string()))
+}
+
+fn refusal(sanction: &RoomSanction) -> String {
+    let mut refusal = match sanction.kind() {
+        Some(SanctionKind::Mute) => "You are muted in this room".to_string(),
+        Some(SanctionKind::Kick) => "You were kicked from this room".to_string(),
+        _ => "You are banned from this room".to_string(),
+    };
+    if let Some(expires_at) = sanction.expires_at {
+        refusal.push_str(&format!(" until {}", format_time(expires_at)));
+    }
+    refusal
+}
+
+/// Adds a `role_change` notification for the sanctioned member. The sanction is already
+/// in force, so failures are only logged.
+async fn notify_sanctioned(
+    pool: &DbPool,
+    router: &ChatRouter,
+    sanction: &RoomSanction,
+    kind: SanctionKind,
+    lifted: bool,
+    content: String,
+) {
+    let notified = notifications::notify(
+        pool,
+        router,
+        sanction.user_id,
+        NotificationKind::RoleChange,
+        serde_json::json!({
+            "room_id": sanction.room_id,
+            "sanction_id": sanction.id,
+            "action": kind,
+            "lifted": lifted,
+            "expires_at": sanction.expires_at,
+            "content": content,
+        }),
+    )
+    .await;
+    if let Err(e) = notified {
+        error!("Failed to notify user {} about sanction {}: {}", sanction.user_id, sanction.id, e);
+    }
+}
+
+fn announce(router: &ChatRouter, sanction: &RoomSanction, kind: SanctionKind, lifted: bool, content: String) {
+    let room_id = sanction.room_id.to_string();
+    let user_id = sanction.user_id.to_string();
+    router.room(&room_id).do_send(Moderate {
+        room_id: room_id.clone(),
+        user_id: user_id.clone(),
+        announcement: ServerMessage::Moderation {
+            room_id,
+            user_id,
+            action: kind,
+            lifted,
+            reason: sanction.reason.clone(),
+            expires_at: sanction.expires_at,
+            content,
+        },
+        remove: !lifted && kind.removes(),
+    });
+}
+
+fn format_time(time: DateTime<Utc>) -> String {
+    time.format("%Y-%m-%d %H:%M UTC").to_string()
+}
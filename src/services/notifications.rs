@@ -0,0 +1,21 @@
+use crate::database::connection::DbPool;
+use crate::models::notification::{Notification, NotificationKind};
+use crate::ws_router::ChatRouter;
+use crate::ws_server::PushNotification;
+use uuid::Uuid;
+
+/// Stores a notification in the user's inbox and pushes it to their connected sessions
+pub async fn notify(
+    pool: &DbPool,
+    router: &ChatRouter,
+    user_id: Uuid,
+    kind: NotificationKind,
+    data: serde_json::Value,
+) -> Result<Notification, sqlx::Error> {
+    let notification = Notification::create(pool, user_id, kind, data).await?;
+    router.user(&user_id.to_string()).do_send(PushNotification {
+        notification: notification.clone(),
+    });
+
+    Ok(notification)
+}
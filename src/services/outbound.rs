@@ -0,0 +1,154 @@
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::Url;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+
+/// Whether an address is reachable on the public internet. Loopback, private,
+/// link-local, unspecified and similar local ranges are not.
+pub fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || a == 0
+                // 100.64.0.0/10, carrier-grade NAT
+                || (a == 100 && (b & 0xc0) == 64))
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public(IpAddr::V4(ip)),
+            None => {
+                !(ip.is_loopback() || ip.is_unspecified() || ip.is_unique_local() || ip.is_unicast_link_local())
+            }
+        },
+    }
+}
+
+/// Parses an http(s) URL the server may call, refusing hosts that are local addresses
+pub fn parse_url(url: &str) -> Result<Url, String> {
+    let url = Url::parse(url).map_err(|_| "must be an http(s) URL".to_string())?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err("must be an http(s) URL".to_string());
+    }
+    let Some(host) = url.host_str() else {
+        return Err("must have a host".to_string());
+    };
+    let local = match host.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>() {
+        Ok(ip) => !is_public(ip),
+        Err(_) => host.eq_ignore_ascii_case("localhost"),
+    };
+    if local {
+        return Err("must not point to a local or private address".to_string());
+    }
+    Ok(url)
+}
+
+/// `parse_url`, also resolving the host and refusing it if any address is not public
+pub async fn check_url(url: &str) -> Result<Url, String> {
+    let url = parse_url(url)?;
+    let (Some(host), Some(port)) = (url.host_str(), url.port_or_known_default()) else {
+        return Err("must have a host".to_string());
+    };
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, port))
+        .await
+        .map_err(|_| "host could not be resolved".to_string())?
+        .collect();
+    if addrs.is_empty() {
+        return Err("host could not be resolved".to_string());
+    }
+    if addrs.iter().any(|addr| !is_public(addr.ip())) {
+        return Err("must not point to a local or private address".to_string());
+    }
+    Ok(url)
+}
+
+/// A client builder for calling user-supplied URLs. It does not follow redirects and
+/// only connects to public addresses, whatever a host resolves to at request time.
+/// Hosts given as IP addresses skip resolution, check them with `parse_url`.
+pub fn client_builder() -> reqwest::ClientBuilder {
+    reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .dns_resolver(Arc::new(PublicResolver))
+}
+
+struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((name.as_str(), 0))
+                .await?
+                .filter(|addr| is_public(addr.ip()))
+                .collect();
+            if addrs.is_empty() {
+                return Err(format!("{} has no public address", name.as_str()).into());
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(ip: &str) -> IpAddr {
+        ip.parse().unwrap()
+    }
+
+    #[test]
+    fn local_addresses_are_not_public() {
+        for local in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "0.0.0.0",
+            "100.64.0.1",
+            "255.255.255.255",
+            "::1",
+            "::",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+            "::ffff:169.254.169.254",
+        ] {
+            assert!(!is_public(ip(local)), "{local} should not be public");
+        }
+    }
+
+    #[test]
+    fn internet_addresses_are_public() {
+        for public in ["8.8.8.8", "100.128.0.1", "2606:4700:4700::1111", "::ffff:1.1.1.1"] {
+            assert!(is_public(ip(public)), "{public} should be public");
+        }
+    }
+
+    #[test]
+    fn parse_url_accepts_public_http_urls() {
+        assert!(parse_url("https://example.com/hook").is_ok());
+        assert!(parse_url("http://8.8.8.8:8080/hook").is_ok());
+    }
+
+    #[test]
+    fn parse_url_refuses_other_schemes_and_local_hosts() {
+        for url in [
+            "ftp://example.com/",
+            "file:///etc/passwd",
+            "not a url",
+            "http://localhost/hook",
+            "http://LOCALHOST:8080/",
+            "http://127.0.0.1/",
+            "http://[::1]:8080/",
+            "http://169.254.169.254/latest/meta-data/",
+        ] {
+            assert!(parse_url(url).is_err(), "{url} should be refused");
+        }
+    }
+}
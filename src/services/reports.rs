@@ -0,0 +1,109 @@
+use crate::database::connection::DbPool;
+use crate::models::message::Message;
+use crate::models::message_report::{CreateMessageReport, MessageReport, ReportCategory};
+use crate::models::notification::NotificationKind;
+use crate::services::notifications;
+use crate::ws_router::ChatRouter;
+use tracing::error;
+
+/// Tells reporters their reports were resolved. The reports are already closed,
+/// so failures are only logged.
+pub async fn notify_reporters(pool: &DbPool, router: &ChatRouter, reports: &[MessageReport]) {
+    for report in reports {
+        let Some(reporter_id) = report.reporter_id else {
+            continue;
+        };
+        let notified = notifications::notify(
+            pool,
+            router,
+            reporter_id,
+            NotificationKind::ReportResolved,
+            serde_json::json!({
+                "report_id": report.id,
+                "room_id": report.room_id,
+                "message_id": report.message_id,
+                "resolution": report.resolution,
+            }),
+        )
+        .await;
+        if let Err(e) = notified {
+            error!("Failed to notify user {} about report {}: {}", reporter_id, report.id, e);
+        }
+    }
+}
+
+/// Why a message was queued for review without a reporter
+pub enum FlagReason {
+    /// Describes the content rule that matched
+    ContentRule(String),
+    /// Describes what spam detection caught
+    Spam(String),
+}
+
+/// Queues a message content rules or spam detection flagged for review, without a reporter
+pub async fn flag(pool: &DbPool, message: &Message, reasons: &[FlagReason]) -> Result<MessageReport, sqlx::Error> {
+    MessageReport::create(
+        pool,
+        CreateMessageReport {
+            message_id: message.id,
+            room_id: message.room_id,
+            sender_id: message.sender_id,
+            content: message.content.clone(),
+            reporter_id: None,
+            category: ReportCategory::Other,
+            details: Some(flag_details(reasons)),
+        },
+    )
+    .await
+}
+
+/// Names what flagged a message, e.g. `Flagged by content rules: word "x"; spam detection: too many mentions`
+fn flag_details(reasons: &[FlagReason]) -> String {
+    let rules: Vec<&str> = reasons
+        .iter()
+        .filter_map(|reason| match reason {
+            FlagReason::ContentRule(rule) => Some(rule.as_str()),
+            FlagReason::Spam(_) => None,
+        })
+        .collect();
+    let spam: Vec<&str> = reasons
+        .iter()
+        .filter_map(|reason| match reason {
+            FlagReason::Spam(signal) => Some(signal.as_str()),
+            FlagReason::ContentRule(_) => None,
+        })
+        .collect();
+    let mut sources = Vec::new();
+    if !rules.is_empty() {
+        sources.push(format!("content rules: {}", rules.join(", ")));
+    }
+    if !spam.is_empty() {
+        sources.push(format!("spam detection: {}", spam.join(", ")));
+    }
+    format!("Flagged by {}", sources.join("; "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flag_details_name_each_source() {
+        assert_eq!(
+            flag_details(&[FlagReason::ContentRule("word \"refund\"".to_string())]),
+            "Flagged by content rules: word \"refund\""
+        );
+        assert_eq!(
+            flag_details(&[FlagReason::Spam("too many mentions".to_string())]),
+            "Flagged by spam detection: too many mentions"
+        );
+        assert_eq!(
+            flag_details(&[
+                FlagReason::Spam("too many mentions".to_string()),
+                FlagReason::ContentRule("word \"a\"".to_string()),
+                FlagReason::ContentRule("link \"b.com\"".to_string()),
+            ]),
+            "Flagged by content rules: word \"a\", link \"b.com\"; spam detection: too many mentions"
+        );
+    }
+}
@@ -0,0 +1,298 @@
+use crate::config::settings::ModerationConfig;
+use crate::database::connection::DbPool;
+use crate::models::room::Room;
+use crate::models::room_sanction::{CreateRoomSanction, SanctionKind};
+use crate::models::user::User;
+use crate::services::{content_filter, moderation};
+use crate::ws_router::ChatRouter;
+use chrono::Utc;
+use serde::Deserialize;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, VecDeque};
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// Users with no recent messages are swept out after this many recorded messages
+const SWEEP_EVERY: usize = 1024;
+
+/// What happens to a message caught by spam detection
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SpamResponse {
+    /// The message goes out and the sender is warned in the room
+    Warn,
+    /// The message is dropped and the sender muted in the room for a while
+    Mute,
+    /// The message goes out and is queued for review
+    Flag,
+}
+
+/// Why a message was considered spam
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpamSignal {
+    RepeatedMessage,
+    MentionFlood,
+    LinkFlood,
+}
+
+impl SpamSignal {
+    pub fn describe(&self) -> &'static str {
+        match self {
+            SpamSignal::RepeatedMessage => "repeated identical messages",
+            SpamSignal::MentionFlood => "too many mentions",
+            SpamSignal::LinkFlood => "too many links from a new account",
+        }
+    }
+}
+
+/// What to do with a screened message
+pub enum SpamOutcome {
+    Clean,
+    /// The message goes out, the sender was warned in the room
+    Warned,
+    /// The message is dropped, the reason is shown to the sender
+    Rejected(String),
+    /// The message goes out and is queued for review, with what was caught
+    Flagged(String),
+}
+
+struct RecentPost {
+    at: Instant,
+    hash: u64,
+    mentions: usize,
+    links: usize,
+}
+
+/// Remembers each user's recent messages on this node to spot floods.
+///
+/// History lives in memory only, so on a cluster every node judges the
+/// messages it receives on its own.
+pub struct SpamDetector {
+    window: Duration,
+    max_repeats: usize,
+    max_mentions: usize,
+    max_new_account_links: usize,
+    new_account_age: chrono::Duration,
+    response: SpamResponse,
+    mute_for: chrono::Duration,
+    recent: Mutex<HashMap<Uuid, VecDeque<RecentPost>>>,
+    recorded: AtomicUsize,
+}
+
+impl SpamDetector {
+    pub fn new(config: &ModerationConfig) -> Self {
+        Self {
+            window: Duration::from_secs(config.spam_window_secs),
+            max_repeats: config.spam_max_repeats,
+            max_mentions: config.spam_max_mentions,
+            max_new_account_links: config.spam_max_new_account_links,
+            new_account_age: seconds(config.spam_new_account_secs),
+            response: config.spam_response,
+            mute_for: seconds(config.spam_mute_secs),
+            recent: Mutex::default(),
+            recorded: AtomicUsize::new(0),
+        }
+    }
+
+    /// Records a message and reports the first limit it breaks within the window.
+    /// The user's history is cleared when one does, so a single burst is caught once.
+    /// `links` only counts for new accounts and is 0 otherwise.
+    pub fn record(&self, user_id: Uuid, content: &str, mentions: usize, links: usize) -> Option<SpamSignal> {
+        let now = Instant::now();
+        let mut hasher = DefaultHasher::new();
+        content.trim().to_lowercase().hash(&mut hasher);
+        let post = RecentPost {
+            at: now,
+            hash: hasher.finish(),
+            mentions,
+            links,
+        };
+
+        let mut recent = self.recent.lock().unwrap();
+        if self.recorded.fetch_add(1, Ordering::Relaxed) % SWEEP_EVERY == SWEEP_EVERY - 1 {
+            recent.retain(|_, posts| posts.back().is_some_and(|last| now.duration_since(last.at) < self.window));
+        }
+
+        let posts = recent.entry(user_id).or_default();
+        while posts.front().is_some_and(|first| now.duration_since(first.at) >= self.window) {
+            posts.pop_front();
+        }
+        let repeats = posts.iter().filter(|earlier| earlier.hash == post.hash).count();
+        let mentions = posts.iter().map(|earlier| earlier.mentions).sum::<usize>() + post.mentions;
+        let links = posts.iter().map(|earlier| earlier.links).sum::<usize>() + post.links;
+        posts.push_back(post);
+
+        let signal = if repeats >= self.max_repeats {
+            Some(SpamSignal::RepeatedMessage)
+        } else if mentions > self.max_mentions {
+            Some(SpamSignal::MentionFlood)
+        } else if links > self.max_new_account_links {
+            Some(SpamSignal::LinkFlood)
+        } else {
+            None
+        };
+        if signal.is_some() {
+            recent.remove(&user_id);
+        }
+        signal
+    }
+
+    /// Screens a room message and applies the configured response. Warnings and
+    /// mutes are recorded as sanctions issued by no one. Bots and the room's owner
+    /// are let through.
+    pub async fn screen(
+        &self,
+        pool: &DbPool,
+        router: &ChatRouter,
+        room_id: Uuid,
+        sender_id: Uuid,
+        content: &str,
+        mentions: usize,
+    ) -> Result<SpamOutcome, sqlx::Error> {
+        let mut sender = None;
+        let mut links = content_filter::count_links(content);
+        if links > 0 {
+            let user = User::find_by_id(pool, sender_id).await?;
+            if user.as_ref().is_none_or(|user| Utc::now() - user.created_at >= self.new_account_age) {
+                links = 0;
+            }
+            sender = user;
+        }
+
+        let Some(signal) = self.record(sender_id, content, mentions, links) else {
+            return Ok(SpamOutcome::Clean);
+        };
+        let sender = match sender {
+            Some(sender) => sender,
+            None => match User::find_by_id(pool, sender_id).await? {
+                Some(sender) => sender,
+                None => return Ok(SpamOutcome::Clean),
+            },
+        };
+        if sender.is_bot {
+            return Ok(SpamOutcome::Clean);
+        }
+        // Owners cannot be sanctioned in their own room, by a moderator or automatically
+        let is_owner = Room::find_by_id(pool, room_id)
+            .await?
+            .is_some_and(|room| room.created_by == sender_id);
+        if is_owner {
+            return Ok(SpamOutcome::Clean);
+        }
+
+        let now = Utc::now();
+        let kind = match self.response {
+            SpamResponse::Flag => return Ok(SpamOutcome::Flagged(signal.describe().to_string())),
+            SpamResponse::Warn => SanctionKind::Warn,
+            SpamResponse::Mute => SanctionKind::Mute,
+        };
+        let expires_at = match kind {
+            SanctionKind::Mute => now.checked_add_signed(self.mute_for),
+            _ => Some(now),
+        };
+        moderation::sanction(
+            pool,
+            router,
+            CreateRoomSanction {
+                room_id,
+                user_id: sender_id,
+                kind,
+                reason: Some(format!("Automatic: {}", signal.describe())),
+                issued_by: None,
+                expires_at,
+            },
+            &sender,
+        )
+        .await?;
+        match kind {
+            SanctionKind::Mute => Ok(SpamOutcome::Rejected(format!("Your message was not sent: {}", signal.describe()))),
+            _ => Ok(SpamOutcome::Warned),
+        }
+    }
+}
+
+fn seconds(secs: u64) -> chrono::Duration {
+    i64::try_from(secs)
+        .ok()
+        .and_then(chrono::Duration::try_seconds)
+        .unwrap_or(chrono::Duration::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detector(window_secs: u64) -> SpamDetector {
+        SpamDetector::new(&ModerationConfig {
+            max_message_chars: 4000,
+            rules_refresh_secs: 30,
+            spam_window_secs: window_secs,
+            spam_max_repeats: 2,
+            spam_max_mentions: 5,
+            spam_max_new_account_links: 3,
+            spam_new_account_secs: 3600,
+            spam_response: SpamResponse::Warn,
+            spam_mute_secs: 600,
+        })
+    }
+
+    #[test]
+    fn repeats_beyond_the_limit_are_caught() {
+        let spam = detector(60);
+        let user = Uuid::new_v4();
+        assert_eq!(spam.record(user, "buy now", 0, 0), None);
+        assert_eq!(spam.record(user, "Buy Now ", 0, 0), None);
+        assert_eq!(spam.record(user, "buy now", 0, 0), Some(SpamSignal::RepeatedMessage));
+    }
+
+    #[test]
+    fn different_messages_are_not_repeats() {
+        let spam = detector(60);
+        let user = Uuid::new_v4();
+        for content in ["one", "two", "three", "four"] {
+            assert_eq!(spam.record(user, content, 0, 0), None);
+        }
+    }
+
+    #[test]
+    fn mentions_and_links_add_up_within_the_window() {
+        let spam = detector(60);
+        let user = Uuid::new_v4();
+        assert_eq!(spam.record(user, "a", 3, 0), None);
+        assert_eq!(spam.record(user, "b", 3, 0), Some(SpamSignal::MentionFlood));
+        assert_eq!(spam.record(user, "c", 0, 2), None);
+        assert_eq!(spam.record(user, "d", 0, 2), Some(SpamSignal::LinkFlood));
+    }
+
+    #[test]
+    fn history_is_cleared_once_caught() {
+        let spam = detector(60);
+        let user = Uuid::new_v4();
+        spam.record(user, "x", 0, 0);
+        spam.record(user, "x", 0, 0);
+        assert_eq!(spam.record(user, "x", 0, 0), Some(SpamSignal::RepeatedMessage));
+        assert_eq!(spam.record(user, "x", 0, 0), None);
+    }
+
+    #[test]
+    fn users_are_tracked_separately() {
+        let spam = detector(60);
+        let (alice, bob) = (Uuid::new_v4(), Uuid::new_v4());
+        spam.record(alice, "hi", 0, 0);
+        spam.record(alice, "hi", 0, 0);
+        assert_eq!(spam.record(bob, "hi", 0, 0), None);
+        assert_eq!(spam.record(alice, "hi", 0, 0), Some(SpamSignal::RepeatedMessage));
+    }
+
+    #[test]
+    fn messages_outside_the_window_are_forgotten() {
+        let spam = detector(0);
+        let user = Uuid::new_v4();
+        for _ in 0..5 {
+            assert_eq!(spam.record(user, "same", 1, 1), None);
+        }
+    }
+}
@@ -0,0 +1,274 @@
+use crate::config::settings::WebhookConfig;
+use crate::database::connection::DbPool;
+use crate::models::webhook::{Webhook, WebhookDelivery, WebhookEvent};
+use crate::services::outbound;
+use chrono::{Duration as ChronoDuration, Utc};
+use futures_util::future::join_all;
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, Notify};
+use tracing::{error, warn};
+use uuid::Uuid;
+
+pub const EVENT_HEADER: &str = "X-RustyChat-Event";
+pub const DELIVERY_HEADER: &str = "X-RustyChat-Delivery";
+/// `sha256=<hex HMAC-SHA256 of the body keyed with the webhook secret>`
+pub const SIGNATURE_HEADER: &str = "X-RustyChat-Signature";
+
+/// Deliveries claimed per round
+const BATCH_SIZE: i64 = 50;
+/// Backoff never grows beyond this
+const MAX_BACKOFF_SECS: u64 = 60 * 60;
+
+type HmacSha256 = Hmac<Sha256>;
+
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+#[derive(Serialize)]
+struct DeliveryBody<'a> {
+    id: Uuid,
+    event: &'a str,
+    created_at: chrono::DateTime<Utc>,
+    data: &'a serde_json::Value,
+}
+
+struct QueuedEvent {
+    room_id: Uuid,
+    event: WebhookEvent,
+    data: serde_json::Value,
+}
+
+/// Persists room events to the `webhook_deliveries` queue and delivers them in the background.
+///
+/// Deliveries are claimed with `SKIP LOCKED`, so every node can run a worker against the
+/// same database.
+#[derive(Clone)]
+pub struct WebhookQueue {
+    outgoing: mpsc::UnboundedSender<QueuedEvent>,
+}
+
+impl WebhookQueue {
+    /// Creates the queue and starts the tasks storing and delivering events
+    pub fn start(pool: DbPool, config: WebhookConfig) -> Self {
+        let (outgoing, mut rx) = mpsc::unbounded_channel::<QueuedEvent>();
+        let wake = Arc::new(Notify::new());
+
+        let enqueue_pool = pool.clone();
+        let enqueue_wake = wake.clone();
+        tokio::spawn(async move {
+            while let Some(queued) = rx.recv().await {
+                match WebhookDelivery::enqueue(&enqueue_pool, queued.room_id, queued.event, &queued.data).await {
+                    Ok(0) => {}
+                    Ok(_) => enqueue_wake.notify_one(),
+                    Err(e) => error!("Failed to queue {} webhook deliveries: {}", queued.event.as_str(), e),
+                }
+            }
+        });
+
+        let worker_wake = wake;
+        tokio::spawn(async move {
+            let client = match outbound::client_builder()
+                .timeout(Duration::from_secs(config.request_timeout_secs))
+                .build()
+            {
+                Ok(client) => client,
+                Err(e) => {
+                    error!("Failed to build webhook HTTP client: {}", e);
+                    return;
+                }
+            };
+
+            loop {
+                match deliver_due(&pool, &client, &config).await {
+                    // A full batch means more may be due already
+                    Ok(claimed) if claimed as i64 == BATCH_SIZE => continue,
+                    Ok(_) => {}
+                    Err(e) => error!("Failed to process webhook deliveries: {}", e),
+                }
+                let _ = tokio::time::timeout(
+                    Duration::from_secs(config.poll_interval_secs),
+                    worker_wake.notified(),
+                )
+                .await;
+            }
+        });
+
+        Self { outgoing }
+    }
+
+    /// Queues an event for the webhooks of a room; rooms that are not stored have none
+    pub fn enqueue<T: Serialize>(&self, room_id: &str, event: WebhookEvent, data: &T) {
+        let Ok(room_id) = Uuid::parse_str(room_id) else {
+            return;
+        };
+        let data = match serde_json::to_value(data) {
+            Ok(data) => data,
+            Err(e) => {
+                error!("Failed to serialize {} webhook payload: {}", event.as_str(), e);
+                return;
+            }
+        };
+        if self.outgoing.send(QueuedEvent { room_id, event, data }).is_err() {
+            error!("Webhook queue has stopped, event dropped");
+        }
+    }
+}
+
+async fn deliver_due(
+    pool: &DbPool,
+    client: &reqwest::Client,
+    config: &WebhookConfig,
+) -> Result<usize, sqlx::Error> {
+    // Leased for longer than a request can take
+    let lease_secs = config.request_timeout_secs as i64 * 2;
+    let deliveries = WebhookDelivery::claim_due(pool, BATCH_SIZE, lease_secs).await?;
+    if deliveries.is_empty() {
+        return Ok(0);
+    }
+
+    let webhook_ids: Vec<Uuid> = deliveries.iter().map(|d| d.webhook_id).collect();
+    let webhooks: HashMap<Uuid, Webhook> = Webhook::find_by_ids(pool, &webhook_ids)
+        .await?
+        .into_iter()
+        .map(|webhook| (webhook.id, webhook))
+        .collect();
+
+    let claimed = deliveries.len();
+    join_all(
+        deliveries
+            .iter()
+            .map(|delivery| deliver(pool, client, config, webhooks.get(&delivery.webhook_id), delivery)),
+    )
+    .await;
+
+    Ok(claimed)
+}
+
+async fn deliver(
+    pool: &DbPool,
+    client: &reqwest::Client,
+    config: &WebhookConfig,
+    webhook: Option<&Webhook>,
+    delivery: &WebhookDelivery,
+) {
+    let result = match webhook {
+        Some(webhook) if webhook.is_active => send(client, webhook, delivery).await,
+        _ => Err((None, "webhook disabled".to_string())),
+    };
+
+    let recorded = match result {
+        Ok(status_code) => WebhookDelivery::mark_delivered(pool, delivery.id, status_code).await,
+        Err((status_code, error)) => {
+            let retry_at = (webhook.is_some_and(|w| w.is_active) && delivery.attempts < config.max_attempts)
+                .then(|| Utc::now() + ChronoDuration::seconds(backoff_secs(config, delivery.attempts) as i64));
+            if retry_at.is_none() {
+                warn!(
+                    "Giving up on webhook delivery {} after {} attempts: {}",
+                    delivery.id, delivery.attempts, error
+                );
+            }
+            WebhookDelivery::mark_attempt_failed(pool, delivery.id, status_code, &error, retry_at).await
+        }
+    };
+
+    if let Err(e) = recorded {
+        error!("Failed to record webhook delivery {}: {}", delivery.id, e);
+    }
+}
+
+/// Posts the signed event, returning the status code or the reason it failed
+async fn send(
+    client: &reqwest::Client,
+    webhook: &Webhook,
+    delivery: &WebhookDelivery,
+) -> Result<i32, (Option<i32>, String)> {
+    let body = serde_json::to_vec(&DeliveryBody {
+        id: delivery.id,
+        event: &delivery.event,
+        created_at: delivery.created_at,
+        data: &delivery.payload,
+    })
+    .map_err(|e| (None, e.to_string()))?;
+    let url = outbound::parse_url(&webhook.url).map_err(|reason| (None, format!("url {}", reason)))?;
+
+    let response = client
+        .post(url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header(EVENT_HEADER, &delivery.event)
+        .header(DELIVERY_HEADER, delivery.id.to_string())
+        .header(SIGNATURE_HEADER, sign(&webhook.secret, &body))
+        .body(body)
+        .send()
+        .await
+        .map_err(|e| (None, e.to_string()))?;
+
+    let status = response.status();
+    if status.is_success() {
+        Ok(status.as_u16() as i32)
+    } else {
+        Err((Some(status.as_u16() as i32), format!("endpoint responded {}", status)))
+    }
+}
+
+/// Exponential backoff after the given number of attempts
+fn backoff_secs(config: &WebhookConfig, attempts: i32) -> u64 {
+    let exponent = attempts.saturating_sub(1).clamp(0, 20) as u32;
+    config
+        .backoff_base_secs
+        .saturating_mul(1 << exponent)
+        .min(MAX_BACKOFF_SECS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(backoff_base_secs: u64) -> WebhookConfig {
+        WebhookConfig { max_attempts: 5, backoff_base_secs, request_timeout_secs: 10, poll_interval_secs: 5 }
+    }
+
+    #[test]
+    fn sign_matches_hmac_sha256() {
+        // RFC 4231, test case 2
+        assert_eq!(
+            sign("Jefe", b"what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn sign_depends_on_secret_and_body() {
+        let signature = sign("secret", b"{}");
+        assert_ne!(signature, sign("other", b"{}"));
+        assert_ne!(signature, sign("secret", b"{ }"));
+    }
+
+    #[test]
+    fn backoff_doubles_per_attempt() {
+        let config = config(30);
+        assert_eq!(backoff_secs(&config, 1), 30);
+        assert_eq!(backoff_secs(&config, 2), 60);
+        assert_eq!(backoff_secs(&config, 4), 240);
+    }
+
+    #[test]
+    fn backoff_is_capped() {
+        assert_eq!(backoff_secs(&config(30), 10), MAX_BACKOFF_SECS);
+        assert_eq!(backoff_secs(&config(30), i32::MAX), MAX_BACKOFF_SECS);
+        assert_eq!(backoff_secs(&config(u64::MAX), 3), MAX_BACKOFF_SECS);
+    }
+
+    #[test]
+    fn backoff_before_first_attempt_is_the_base() {
+        assert_eq!(backoff_secs(&config(30), 0), 30);
+        assert_eq!(backoff_secs(&config(30), -1), 30);
+    }
+}
@@ -0,0 +1,358 @@
+use actix::prelude::*;
+use bytestring::ByteString;
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{Mutex as AsyncMutex, Notify};
+use uuid::Uuid;
+use crate::config::settings::WebSocketConfig;
+use crate::database::connection::DbPool;
+use crate::models::message::{Message, MAX_REPLAY};
+use crate::utils::types::ServerMessage;
+use crate::ws_outbox::{CloseSession, FlushOutbox, Outbox};
+use crate::ws_protocol::{Payload, WireCodec};
+use crate::ws_router::ChatRouter;
+use crate::services::blocks;
+use crate::ws_server::{Connect, Disconnect, JoinRoom, RemovedFromRoom, SessionHandle, SessionId};
+
+/// A `ChatServer` session for the HTTP fallback transports (Server-Sent Events
+/// and long-polling). The actor only registers with the chat shards and wakes
+/// whoever is waiting on the matching `StreamSubscription`.
+struct StreamSession {
+    id: SessionId,
+    user_id: String,
+    router: ChatRouter,
+    notify: Arc<Notify>,
+    closed: Arc<AtomicBool>,
+    /// Long-poll sessions expire when not polled for this long
+    idle_timeout: Option<Duration>,
+    last_seen: Instant,
+}
+
+impl Actor for StreamSession {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        if let Some(idle_timeout) = self.idle_timeout {
+            ctx.run_interval(idle_timeout / 2, move |act, ctx| {
+                if act.last_seen.elapsed() > idle_timeout {
+                    ctx.stop();
+                }
+            });
+        }
+    }
+
+    fn stopped(&mut self, _ctx: &mut Self::Context) {
+        self.closed.store(true, Ordering::Release);
+        self.notify.notify_one();
+        self.router.broadcast(Disconnect {
+            user_id: self.user_id.clone(),
+            session_id: self.id,
+        });
+    }
+}
+
+impl Handler<FlushOutbox> for StreamSession {
+    type Result = ();
+
+    fn handle(&mut self, _: FlushOutbox, _: &mut Self::Context) {
+        self.notify.notify_one();
+    }
+}
+
+impl Handler<CloseSession> for StreamSession {
+    type Result = ();
+
+    fn handle(&mut self, _: CloseSession, ctx: &mut Self::Context) {
+        ctx.stop();
+    }
+}
+
+impl Handler<RemovedFromRoom> for StreamSession {
+    type Result = ();
+
+    // The room's shard already stopped sending its events, a stream has no current room
+    fn handle(&mut self, _: RemovedFromRoom, _: &mut Self::Context) {}
+}
+
+struct Touch;
+impl actix::Message for Touch {
+    type Result = ();
+}
+
+impl Handler<Touch> for StreamSession {
+    type Result = ();
+
+    fn handle(&mut self, _: Touch, _: &mut Self::Context) {
+        self.last_seen = Instant::now();
+    }
+}
+
+/// Closes the session once the token it was opened with expires (unix seconds)
+struct ExpireAt(i64);
+impl actix::Message for ExpireAt {
+    type Result = ();
+}
+
+impl Handler<ExpireAt> for StreamSession {
+    type Result = ();
+
+    fn handle(&mut self, ExpireAt(expires_at): ExpireAt, ctx: &mut Self::Context) {
+        let remaining = (expires_at - chrono::Utc::now().timestamp()).max(0) as u64;
+        ctx.run_later(Duration::from_secs(remaining), |_, ctx| ctx.stop());
+    }
+}
+
+struct Close;
+impl actix::Message for Close {
+    type Result = ();
+}
+
+impl Handler<Close> for StreamSession {
+    type Result = ();
+
+    fn handle(&mut self, _: Close, ctx: &mut Self::Context) {
+        ctx.stop();
+    }
+}
+
+/// An event delivered over SSE or long-polling, `id` is the stored message id if any
+pub struct StreamEvent {
+    pub id: Option<Uuid>,
+    pub data: ByteString,
+}
+
+/// The receiving end of a `StreamSession`; dropping it ends the session
+pub struct StreamSubscription {
+    pub id: SessionId,
+    pub user_id: String,
+    addr: Addr<StreamSession>,
+    outbox: Arc<Outbox>,
+    notify: Arc<Notify>,
+    closed: Arc<AtomicBool>,
+    /// Replayed history not handed out yet
+    backlog: VecDeque<StreamEvent>,
+    /// Highest sequence number delivered by replay, per room
+    replayed_through: HashMap<String, i64>,
+}
+
+impl StreamSubscription {
+    /// Subscribes to `rooms` through the same delivery layer as WebSocket sessions and,
+    /// when resuming after a message id, replays what was stored since.
+    pub async fn open(
+        user_id: String,
+        rooms: Vec<String>,
+        resume_after: Option<Uuid>,
+        router: ChatRouter,
+        pool: &DbPool,
+        config: &WebSocketConfig,
+        idle_timeout: Option<Duration>,
+    ) -> Result<Self, sqlx::Error> {
+        let id = Uuid::new_v4();
+        let filter = blocks::session_filter(pool, &user_id).await?;
+        let outbox = Arc::new(Outbox::new(
+            config.outbound_capacity,
+            config.slow_consumer_policy,
+            WireCodec::Json,
+        ));
+        let notify = Arc::new(Notify::new());
+        let closed = Arc::new(AtomicBool::new(false));
+
+        let addr = StreamSession {
+            id,
+            user_id: user_id.clone(),
+            router: router.clone(),
+            notify: notify.clone(),
+            closed: closed.clone(),
+            idle_timeout,
+            last_seen: Instant::now(),
+        }
+        .start();
+
+        let session = SessionHandle {
+            id,
+            user_id: user_id.clone(),
+            outbox: outbox.clone(),
+            flush: addr.clone().recipient(),
+            close: addr.clone().recipient(),
+            removed: addr.clone().recipient(),
+            filter: Arc::new(filter),
+        };
+        router.broadcast(Connect {
+            user_id: user_id.clone(),
+            session: session.clone(),
+        });
+        // Wait for the joins so nothing stored after the replay query is missed
+        for room_id in &rooms {
+            let _ = router
+                .room(room_id)
+                .send(JoinRoom {
+                    user_id: user_id.clone(),
+                    room_id: room_id.clone(),
+                    session: session.clone(),
+                })
+                .await;
+        }
+
+        let mut subscription = Self {
+            id,
+            user_id,
+            addr,
+            outbox,
+            notify,
+            closed,
+            backlog: VecDeque::new(),
+            replayed_through: HashMap::new(),
+        };
+
+        if let Some(message_id) = resume_after {
+            subscription.replay(pool, &rooms, message_id).await?;
+        }
+
+        Ok(subscription)
+    }
+
+    async fn replay(&mut self, pool: &DbPool, rooms: &[String], message_id: Uuid) -> Result<(), sqlx::Error> {
+        let Some(anchor) = Message::find_by_id(pool, message_id).await? else {
+            return Ok(());
+        };
+        let room_ids: Vec<Uuid> = rooms.iter().filter_map(|r| Uuid::parse_str(r).ok()).collect();
+
+        let Ok(viewer) = Uuid::parse_str(&self.user_id) else {
+            return Ok(());
+        };
+
+        // One more than is replayed, to tell whether any were skipped
+        let mut messages = Message::find_in_rooms_after(pool, &room_ids, &anchor, viewer, MAX_REPLAY as i64 + 1).await?;
+        if messages.len() > MAX_REPLAY {
+            messages.remove(0);
+            for room_id in rooms {
+                let truncated = ServerMessage::ReplayTruncated {
+                    room_id: room_id.clone(),
+                    from_seq: messages
+                        .iter()
+                        .find(|message| message.room_id.to_string() == *room_id)
+                        .map(|message| message.seq),
+                };
+                if let Some(Payload::Text(data)) = WireCodec::Json.encode(&truncated) {
+                    self.backlog.push_back(StreamEvent { id: None, data });
+                }
+            }
+        }
+
+        for message in messages {
+            let room_id = message.room_id.to_string();
+            let replayed = self.replayed_through.entry(room_id.clone()).or_default();
+            *replayed = (*replayed).max(message.seq);
+
+            let text = ServerMessage::Text {
+                room_id,
+                content: message.content,
+                user_id: message.sender_id.to_string(),
+                seq: Some(message.seq),
+                message_id: Some(message.id.to_string()),
+                display: message.display,
+                mentions: message.mentions,
+            };
+            if let Some(Payload::Text(data)) = WireCodec::Json.encode(&text) {
+                self.backlog.push_back(StreamEvent {
+                    id: Some(message.id),
+                    data,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Waits up to `wait` for events. Returns an empty batch on timeout and `None`
+    /// once the session has been closed.
+    pub async fn next_events(&mut self, wait: Duration) -> Option<Vec<StreamEvent>> {
+        if !self.backlog.is_empty() {
+            return Some(self.backlog.drain(..).collect());
+        }
+
+        let events = self.drain_outbox();
+        if !events.is_empty() {
+            return Some(events);
+        }
+        if self.closed.load(Ordering::Acquire) {
+            return None;
+        }
+
+        let _ = tokio::time::timeout(wait, self.notify.notified()).await;
+        if self.closed.load(Ordering::Acquire) {
+            return None;
+        }
+        Some(self.drain_outbox())
+    }
+
+    fn drain_outbox(&self) -> Vec<StreamEvent> {
+        self.outbox
+            .drain()
+            .into_iter()
+            .filter(|frame| match (&frame.room_id, frame.seq) {
+                (Some(room_id), Some(seq)) => self
+                    .replayed_through
+                    .get(room_id)
+                    .is_none_or(|replayed| seq > *replayed),
+                _ => true,
+            })
+            .filter_map(|frame| match frame.payload {
+                Payload::Text(data) => Some(StreamEvent {
+                    id: frame.message_id,
+                    data,
+                }),
+                Payload::Binary(_) => None,
+            })
+            .collect()
+    }
+
+    /// Ends the session when the token it was opened with expires. Revoked tokens
+    /// close it through `RevokeSessions` like any other session.
+    pub fn expire_at(&self, expires_at: i64) {
+        self.addr.do_send(ExpireAt(expires_at));
+    }
+
+    /// Keeps a long-poll session from expiring
+    pub fn touch(&self) {
+        self.addr.do_send(Touch);
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Acquire)
+    }
+}
+
+impl Drop for StreamSubscription {
+    fn drop(&mut self) {
+        self.addr.do_send(Close);
+    }
+}
+
+/// Long-poll subscriptions kept between requests, keyed by session id
+#[derive(Default)]
+pub struct PollRegistry {
+    subscriptions: Mutex<HashMap<SessionId, Arc<AsyncMutex<StreamSubscription>>>>,
+}
+
+impl PollRegistry {
+    pub fn insert(&self, subscription: StreamSubscription) -> Arc<AsyncMutex<StreamSubscription>> {
+        let id = subscription.id;
+        let subscription = Arc::new(AsyncMutex::new(subscription));
+        let mut subscriptions = self.subscriptions.lock().unwrap();
+        // Drop sessions that expired since nobody polled them
+        subscriptions.retain(|_, sub| sub.try_lock().map_or(true, |sub| !sub.is_closed()));
+        subscriptions.insert(id, subscription.clone());
+        subscription
+    }
+
+    pub fn get(&self, id: SessionId) -> Option<Arc<AsyncMutex<StreamSubscription>>> {
+        self.subscriptions.lock().unwrap().get(&id).cloned()
+    }
+
+    pub fn remove(&self, id: SessionId) {
+        self.subscriptions.lock().unwrap().remove(&id);
+    }
+}
@@ -0,0 +1,46 @@
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+
+/// A random token with a recognizable prefix, e.g. `rcb_<64 hex chars>`
+pub fn generate_token(prefix: &str) -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    format!("{}{}", prefix, hex::encode(bytes))
+}
+
+/// Tokens are only stored as their SHA-256, looked up by hashing what the client sent
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens_carry_the_prefix_and_32_random_bytes() {
+        let token = generate_token("rcr_");
+        let random = token.strip_prefix("rcr_").unwrap();
+        assert_eq!(random.len(), 64);
+        assert!(random.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_ne!(token, generate_token("rcr_"));
+    }
+
+    #[test]
+    fn hash_token_is_hex_sha256() {
+        assert_eq!(
+            hash_token("abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn hashes_do_not_reveal_or_match_other_tokens() {
+        let token = generate_token("rcr_");
+        let hash = hash_token(&token);
+        assert_eq!(hash, hash_token(&token));
+        assert!(!hash.contains(token.strip_prefix("rcr_").unwrap()));
+        assert_ne!(hash, hash_token(&generate_token("rcr_")));
+        assert_ne!(hash, hash_token(&token.to_uppercase()));
+    }
+}
@@ -1,3 +1,8 @@
+use crate::models::mention::{MentionKind, MentionSpan};
+use crate::models::message::DisplayOverride;
+use crate::models::notification::Notification;
+use crate::models::room_sanction::SanctionKind;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -6,20 +11,86 @@ pub enum ClientMessage {
     Text { content: String },
     Typing { user_id: String },
     Read { message_id: String },
-    Join { room_id: String, user_id: String },
+    Join {
+        room_id: String,
+        user_id: String,
+        /// Highest sequence number the client has seen in this room; missed messages are replayed
+        #[serde(default)]
+        last_seq: Option<i64>,
+    },
     Leave { room_id: String, user_id: String },
     Private { to: String, content: String },
+    /// Presents a fresh token before the current one expires
+    Reauth { token: String },
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(tag = "type", content = "data")]
 pub enum ServerMessage {
-    Text { content: String, user_id: String },
+    Text {
+        room_id: String,
+        content: String,
+        user_id: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        seq: Option<i64>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        message_id: Option<String>,
+        #[serde(flatten)]
+        display: DisplayOverride,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        mentions: Vec<MentionSpan>,
+    },
     Typing { user_id: String },
     Read { message_id: String, user_id: String },
     Join { room_id: String, user_id: String },
     Leave { room_id: String, user_id: String },
     Private { from: String, content: String },
+    Error { message: String },
+    /// The session's token expires soon and must be replaced with `Reauth`
+    AuthExpiring { expires_at: i64 },
+    Reauthenticated { expires_at: i64 },
+    /// Reply to a slash command, seen only by the user who ran it
+    CommandResponse { command: String, content: String },
+    RoomUpdated {
+        room_id: String,
+        name: String,
+        topic: Option<String>,
+    },
+    Invite { room_id: String, from: String },
+    /// A member changed how they are shown, clients refresh cached names and avatars
+    UserUpdated {
+        room_id: String,
+        user_id: String,
+        full_name: String,
+        display_name: Option<String>,
+        avatar_url: Option<String>,
+        pronouns: Option<String>,
+    },
+    /// Sent to mentioned users whether or not they are in the room
+    Mention {
+        room_id: String,
+        message_id: String,
+        from: String,
+        content: String,
+        kind: MentionKind,
+    },
+    Notification { notification: Notification },
+    /// A moderator deleted a message, clients drop it
+    MessageDeleted { room_id: String, message_id: String },
+    /// A moderator sanctioned a member or lifted a sanction, `content` is the line to show.
+    /// Kicked and banned members receive it before they are removed from the room.
+    Moderation {
+        room_id: String,
+        user_id: String,
+        action: SanctionKind,
+        lifted: bool,
+        reason: Option<String>,
+        expires_at: Option<DateTime<Utc>>,
+        content: String,
+    },
+    /// More messages were missed than are replayed, the room's messages before `from_seq`
+    /// may have been skipped. `from_seq` is `None` when none of the room's were replayed.
+    ReplayTruncated { room_id: String, from_seq: Option<i64> },
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -27,3 +98,60 @@ pub struct Claims {
     pub sub: String,
     pub exp: usize,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn join_reads_last_seq_when_given() {
+        let join: ClientMessage = serde_json::from_value(json!({
+            "type": "Join",
+            "data": { "room_id": "general", "user_id": "alice", "last_seq": 41 }
+        }))
+        .unwrap();
+        assert!(matches!(join, ClientMessage::Join { last_seq: Some(41), .. }));
+    }
+
+    #[test]
+    fn join_without_last_seq_does_not_replay() {
+        let join: ClientMessage = serde_json::from_value(json!({
+            "type": "Join",
+            "data": { "room_id": "general", "user_id": "alice" }
+        }))
+        .unwrap();
+        assert!(matches!(join, ClientMessage::Join { last_seq: None, .. }));
+    }
+
+    fn text(seq: Option<i64>, message_id: Option<&str>) -> ServerMessage {
+        ServerMessage::Text {
+            room_id: "general".to_string(),
+            content: "hi".to_string(),
+            user_id: "alice".to_string(),
+            seq,
+            message_id: message_id.map(str::to_string),
+            display: DisplayOverride::default(),
+            mentions: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn stored_messages_carry_their_seq() {
+        assert_eq!(
+            serde_json::to_value(text(Some(42), Some("m1"))).unwrap(),
+            json!({
+                "type": "Text",
+                "data": { "room_id": "general", "content": "hi", "user_id": "alice", "seq": 42, "message_id": "m1" }
+            })
+        );
+    }
+
+    #[test]
+    fn unstored_messages_leave_seq_out() {
+        assert_eq!(
+            serde_json::to_value(text(None, None)).unwrap(),
+            json!({ "type": "Text", "data": { "room_id": "general", "content": "hi", "user_id": "alice" } })
+        );
+    }
+}
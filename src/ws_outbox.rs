@@ -0,0 +1,292 @@
+use crate::ws_protocol::{Payload, WireCodec};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use uuid::Uuid;
+
+/// What to do when a session's outbound buffer is full
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SlowConsumerPolicy {
+    /// Drop the oldest queued frame to make room
+    DropOldest,
+    /// Replace queued typing indicators instead of piling them up, then drop the oldest frame
+    CoalesceTyping,
+    /// Close the connection with a "too slow" close code
+    Disconnect,
+}
+
+/// Process-wide counters for frames that never reached slow clients
+pub struct OutboundMetrics {
+    dropped_frames: AtomicU64,
+    coalesced_frames: AtomicU64,
+    slow_disconnects: AtomicU64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OutboundMetricsSnapshot {
+    pub dropped_frames: u64,
+    pub coalesced_frames: u64,
+    pub slow_disconnects: u64,
+}
+
+impl OutboundMetrics {
+    const fn new() -> Self {
+        Self {
+            dropped_frames: AtomicU64::new(0),
+            coalesced_frames: AtomicU64::new(0),
+            slow_disconnects: AtomicU64::new(0),
+        }
+    }
+
+    pub fn snapshot(&self) -> OutboundMetricsSnapshot {
+        OutboundMetricsSnapshot {
+            dropped_frames: self.dropped_frames.load(Ordering::Relaxed),
+            coalesced_frames: self.coalesced_frames.load(Ordering::Relaxed),
+            slow_disconnects: self.slow_disconnects.load(Ordering::Relaxed),
+        }
+    }
+}
+
+pub static OUTBOUND_METRICS: OutboundMetrics = OutboundMetrics::new();
+
+/// A serialized server message waiting to be written to a client.
+///
+/// The payload is reference counted, so a room broadcast is encoded once and
+/// every session's frame points at the same buffer.
+#[derive(Debug)]
+pub struct OutboundFrame {
+    pub room_id: Option<String>,
+    pub seq: Option<i64>,
+    pub message_id: Option<Uuid>,
+    pub payload: Payload,
+    /// Set for typing indicators, which may be coalesced per room and user
+    pub typing_user: Option<String>,
+}
+
+impl OutboundFrame {
+    fn is_typing_of(&self, room_id: &Option<String>, user_id: &str) -> bool {
+        self.typing_user.as_deref() == Some(user_id) && &self.room_id == room_id
+    }
+}
+
+pub enum PushOutcome {
+    /// The frame was queued; `wake` is set when the session has to be told to flush
+    Queued { wake: bool },
+    /// The buffer was full under the disconnect policy and the session must be closed
+    Overflowed,
+    /// The session has already overflowed and is being closed
+    Closed,
+}
+
+#[derive(Default)]
+struct OutboxState {
+    frames: VecDeque<OutboundFrame>,
+    flush_scheduled: bool,
+    overflowed: bool,
+}
+
+/// Bounded buffer between `ChatServer` and a single `ChatSession`.
+///
+/// The session only drains it while its connection is being written to, so a stalled
+/// client fills the buffer and the configured policy decides what gets lost.
+pub struct Outbox {
+    capacity: usize,
+    policy: SlowConsumerPolicy,
+    codec: WireCodec,
+    state: Mutex<OutboxState>,
+}
+
+impl Outbox {
+    pub fn new(capacity: usize, policy: SlowConsumerPolicy, codec: WireCodec) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            policy,
+            codec,
+            state: Mutex::new(OutboxState::default()),
+        }
+    }
+
+    /// The codec frames for this session must be encoded with
+    pub fn codec(&self) -> WireCodec {
+        self.codec
+    }
+
+    pub fn push(&self, frame: OutboundFrame) -> PushOutcome {
+        let mut state = self.state.lock().unwrap();
+        if state.overflowed {
+            return PushOutcome::Closed;
+        }
+
+        if self.policy == SlowConsumerPolicy::CoalesceTyping {
+            if let Some(user_id) = &frame.typing_user {
+                if let Some(queued) = state
+                    .frames
+                    .iter_mut()
+                    .find(|queued| queued.is_typing_of(&frame.room_id, user_id))
+                {
+                    *queued = frame;
+                    OUTBOUND_METRICS.coalesced_frames.fetch_add(1, Ordering::Relaxed);
+                    return PushOutcome::Queued { wake: false };
+                }
+            }
+        }
+
+        if state.frames.len() >= self.capacity {
+            match self.policy {
+                SlowConsumerPolicy::Disconnect => {
+                    state.overflowed = true;
+                    OUTBOUND_METRICS
+                        .dropped_frames
+                        .fetch_add(state.frames.len() as u64 + 1, Ordering::Relaxed);
+                    state.frames.clear();
+                    OUTBOUND_METRICS.slow_disconnects.fetch_add(1, Ordering::Relaxed);
+                    return PushOutcome::Overflowed;
+                }
+                SlowConsumerPolicy::CoalesceTyping => {
+                    let victim = state
+                        .frames
+                        .iter()
+                        .position(|queued| queued.typing_user.is_some())
+                        .unwrap_or(0);
+                    state.frames.remove(victim);
+                }
+                SlowConsumerPolicy::DropOldest => {
+                    state.frames.pop_front();
+                }
+            }
+            OUTBOUND_METRICS.dropped_frames.fetch_add(1, Ordering::Relaxed);
+        }
+
+        state.frames.push_back(frame);
+        let wake = !state.flush_scheduled;
+        state.flush_scheduled = true;
+        PushOutcome::Queued { wake }
+    }
+
+    /// Takes everything queued so far; frames pushed afterwards schedule a new flush
+    pub fn drain(&self) -> Vec<OutboundFrame> {
+        let mut state = self.state.lock().unwrap();
+        state.flush_scheduled = false;
+        state.frames.drain(..).collect()
+    }
+}
+
+// Sent by ws_server.rs when frames were queued in an empty outbox
+pub struct FlushOutbox;
+impl actix::Message for FlushOutbox {
+    type Result = ();
+}
+
+/// Why `ChatServer` is closing a session
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CloseReason {
+    /// The outbox overflowed under the disconnect policy
+    TooSlow,
+    /// The user's tokens were revoked or the account is gone
+    AuthRevoked,
+}
+
+// Sent by ws_server.rs when a session has been dropped from routing and must close
+pub struct CloseSession {
+    pub reason: CloseReason,
+}
+impl actix::Message for CloseSession {
+    type Result = ();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(seq: i64) -> OutboundFrame {
+        OutboundFrame {
+            room_id: Some("room".to_string()),
+            seq: Some(seq),
+            message_id: None,
+            payload: Payload::Text(format!("message {seq}").into()),
+            typing_user: None,
+        }
+    }
+
+    fn typing(user_id: &str) -> OutboundFrame {
+        OutboundFrame {
+            room_id: Some("room".to_string()),
+            seq: None,
+            message_id: None,
+            payload: Payload::Text(format!("{user_id} is typing").into()),
+            typing_user: Some(user_id.to_string()),
+        }
+    }
+
+    /// The drained frames, messages by seq and typing indicators by user
+    fn drained(outbox: &Outbox) -> Vec<String> {
+        outbox
+            .drain()
+            .into_iter()
+            .map(|frame| match (frame.seq, frame.typing_user) {
+                (Some(seq), _) => seq.to_string(),
+                (None, Some(user_id)) => user_id,
+                (None, None) => String::new(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn drop_oldest_evicts_the_front_frame() {
+        let outbox = Outbox::new(2, SlowConsumerPolicy::DropOldest, WireCodec::Json);
+        outbox.push(typing("alice"));
+        outbox.push(message(1));
+        outbox.push(message(2));
+
+        assert_eq!(drained(&outbox), ["1", "2"]);
+    }
+
+    #[test]
+    fn coalesce_typing_evicts_the_oldest_typing_indicator() {
+        let outbox = Outbox::new(3, SlowConsumerPolicy::CoalesceTyping, WireCodec::Json);
+        outbox.push(message(1));
+        outbox.push(typing("alice"));
+        outbox.push(typing("bob"));
+        outbox.push(message(2));
+
+        assert_eq!(drained(&outbox), ["1", "bob", "2"]);
+    }
+
+    #[test]
+    fn coalesce_typing_evicts_the_oldest_frame_without_typing_indicators() {
+        let outbox = Outbox::new(2, SlowConsumerPolicy::CoalesceTyping, WireCodec::Json);
+        outbox.push(message(1));
+        outbox.push(message(2));
+        outbox.push(message(3));
+
+        assert_eq!(drained(&outbox), ["2", "3"]);
+    }
+
+    #[test]
+    fn coalesce_typing_replaces_the_queued_indicator_of_the_same_user() {
+        let outbox = Outbox::new(4, SlowConsumerPolicy::CoalesceTyping, WireCodec::Json);
+        outbox.push(typing("alice"));
+        outbox.push(message(1));
+        assert!(matches!(outbox.push(typing("alice")), PushOutcome::Queued { wake: false }));
+
+        assert_eq!(drained(&outbox), ["alice", "1"]);
+    }
+
+    #[test]
+    fn disconnect_overflows_and_counts_the_lost_frames() {
+        let before = OUTBOUND_METRICS.snapshot();
+        let outbox = Outbox::new(2, SlowConsumerPolicy::Disconnect, WireCodec::Json);
+        assert!(matches!(outbox.push(message(1)), PushOutcome::Queued { wake: true }));
+        assert!(matches!(outbox.push(message(2)), PushOutcome::Queued { wake: false }));
+        assert!(matches!(outbox.push(message(3)), PushOutcome::Overflowed));
+        assert!(matches!(outbox.push(message(4)), PushOutcome::Closed));
+        assert!(outbox.drain().is_empty());
+
+        // Other tests share the counters, so only a lower bound holds
+        let after = OUTBOUND_METRICS.snapshot();
+        assert!(after.slow_disconnects > before.slow_disconnects);
+        assert!(after.dropped_frames >= before.dropped_frames + 3);
+    }
+}
@@ -0,0 +1,246 @@
+use actix_web::{http::header, web::Bytes, HttpRequest};
+use bytestring::ByteString;
+use tracing::error;
+use crate::utils::types::{ClientMessage, ServerMessage};
+
+pub const JSON_PROTOCOL: &str = "rusty-chat.json.v1";
+pub const MSGPACK_PROTOCOL: &str = "rusty-chat.msgpack.v1";
+
+/// Prefix of a subprotocol carrying the bearer token, e.g. `rusty-chat.bearer.<jwt>`.
+/// It is never echoed back, so clients must offer a codec subprotocol alongside it.
+pub const BEARER_PROTOCOL_PREFIX: &str = "rusty-chat.bearer.";
+
+/// Subprotocols offered in the WebSocket handshake, in order of preference
+pub const SUPPORTED_PROTOCOLS: &[&str] = &[JSON_PROTOCOL, MSGPACK_PROTOCOL];
+
+/// How `ServerMessage`s are written to a client.
+///
+/// Incoming frames are decoded by frame type regardless: text frames as JSON,
+/// binary frames as MessagePack.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WireCodec {
+    /// JSON over text frames, used when no subprotocol was requested
+    #[default]
+    Json,
+    /// MessagePack (named fields) over binary frames
+    MessagePack,
+}
+
+/// An encoded message ready to be written as a WebSocket frame
+#[derive(Debug, Clone)]
+pub enum Payload {
+    Text(ByteString),
+    Binary(Bytes),
+}
+
+impl WireCodec {
+    /// Picks the codec for the first subprotocol the client offers that we support,
+    /// the same one the handshake response echoes back
+    pub fn negotiate(req: &HttpRequest) -> Self {
+        req.headers()
+            .get(header::SEC_WEBSOCKET_PROTOCOL)
+            .and_then(|h| h.to_str().ok())
+            .and_then(|offered| {
+                offered
+                    .split(',')
+                    .map(str::trim)
+                    .find(|p| SUPPORTED_PROTOCOLS.contains(p))
+            })
+            .map(|protocol| match protocol {
+                MSGPACK_PROTOCOL => WireCodec::MessagePack,
+                _ => WireCodec::Json,
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn encode(&self, message: &ServerMessage) -> Option<Payload> {
+        let encoded = match self {
+            WireCodec::Json => serde_json::to_string(message)
+                .map(|json| Payload::Text(ByteString::from(json)))
+                .map_err(|e| e.to_string()),
+            WireCodec::MessagePack => rmp_serde::to_vec_named(message)
+                .map(|bytes| Payload::Binary(Bytes::from(bytes)))
+                .map_err(|e| e.to_string()),
+        };
+
+        match encoded {
+            Ok(payload) => Some(payload),
+            Err(e) => {
+                error!("Failed to encode server message as {:?}: {}", self, e);
+                None
+            }
+        }
+    }
+}
+
+/// The bearer token offered through `Sec-WebSocket-Protocol`, if any
+pub fn bearer_token(req: &HttpRequest) -> Option<&str> {
+    req.headers()
+        .get(header::SEC_WEBSOCKET_PROTOCOL)
+        .and_then(|h| h.to_str().ok())?
+        .split(',')
+        .map(str::trim)
+        .find_map(|p| p.strip_prefix(BEARER_PROTOCOL_PREFIX))
+}
+
+pub fn decode_text(text: &str) -> Option<ClientMessage> {
+    serde_json::from_str(text).ok()
+}
+
+pub fn decode_binary(bytes: &[u8]) -> Option<ClientMessage> {
+    rmp_serde::from_slice(bytes).ok()
+}
+
+/// Encodes a broadcast lazily, at most once per codec, so every session using
+/// the same codec shares one buffer
+pub struct EncodedMessage<'a> {
+    message: &'a ServerMessage,
+    json: Option<Option<Payload>>,
+    msgpack: Option<Option<Payload>>,
+}
+
+impl<'a> EncodedMessage<'a> {
+    pub fn new(message: &'a ServerMessage) -> Self {
+        Self {
+            message,
+            json: None,
+            msgpack: None,
+        }
+    }
+
+    pub fn get(&mut self, codec: WireCodec) -> Option<Payload> {
+        let slot = match codec {
+            WireCodec::Json => &mut self.json,
+            WireCodec::MessagePack => &mut self.msgpack,
+        };
+        slot.get_or_insert_with(|| codec.encode(self.message)).clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::message::DisplayOverride;
+    use actix_web::test::TestRequest;
+
+    fn offering(protocols: &str) -> HttpRequest {
+        TestRequest::default()
+            .insert_header((header::SEC_WEBSOCKET_PROTOCOL, protocols))
+            .to_http_request()
+    }
+
+    #[test]
+    fn json_is_used_without_a_subprotocol() {
+        assert_eq!(WireCodec::negotiate(&TestRequest::default().to_http_request()), WireCodec::Json);
+    }
+
+    #[test]
+    fn the_first_supported_protocol_offered_wins() {
+        let msgpack_first = offering("rusty-chat.msgpack.v1, rusty-chat.json.v1");
+        assert_eq!(WireCodec::negotiate(&msgpack_first), WireCodec::MessagePack);
+
+        let json_first = offering("rusty-chat.json.v1, rusty-chat.msgpack.v1");
+        assert_eq!(WireCodec::negotiate(&json_first), WireCodec::Json);
+    }
+
+    #[test]
+    fn unknown_protocols_are_skipped() {
+        assert_eq!(WireCodec::negotiate(&offering("chat.v2, rusty-chat.msgpack.v1")), WireCodec::MessagePack);
+        assert_eq!(WireCodec::negotiate(&offering("chat.v2, rusty-chat.json.v2")), WireCodec::Json);
+    }
+
+    #[test]
+    fn the_bearer_entry_is_not_a_codec() {
+        let req = offering("rusty-chat.bearer.rusty-chat.msgpack.v1, rusty-chat.json.v1");
+        assert_eq!(WireCodec::negotiate(&req), WireCodec::Json);
+        assert_eq!(bearer_token(&req), Some("rusty-chat.msgpack.v1"));
+
+        let req = offering("rusty-chat.bearer.token, rusty-chat.msgpack.v1");
+        assert_eq!(WireCodec::negotiate(&req), WireCodec::MessagePack);
+        assert_eq!(bearer_token(&req), Some("token"));
+    }
+
+    /// Encodes as a session using MessagePack would and reads it back as a client would
+    fn msgpack_round_trip(message: &ServerMessage) -> ServerMessage {
+        let Some(Payload::Binary(bytes)) = WireCodec::MessagePack.encode(message) else {
+            panic!("MessagePack must be written as binary frames");
+        };
+        rmp_serde::from_slice(&bytes).unwrap()
+    }
+
+    fn text(display: DisplayOverride) -> ServerMessage {
+        ServerMessage::Text {
+            room_id: "general".to_string(),
+            content: "hi".to_string(),
+            user_id: "alice".to_string(),
+            seq: Some(7),
+            message_id: Some("42".to_string()),
+            display,
+            mentions: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn text_keeps_its_display_override_through_messagepack() {
+        let display = DisplayOverride {
+            display_name: Some("Deploy bot".to_string()),
+            avatar_url: Some("https://example.com/bot.png".to_string()),
+        };
+        match msgpack_round_trip(&text(display)) {
+            ServerMessage::Text { seq, message_id, display, .. } => {
+                assert_eq!(seq, Some(7));
+                assert_eq!(message_id.as_deref(), Some("42"));
+                assert_eq!(display.display_name.as_deref(), Some("Deploy bot"));
+                assert_eq!(display.avatar_url.as_deref(), Some("https://example.com/bot.png"));
+            }
+            other => panic!("expected a text message, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn text_without_a_display_override_survives_messagepack() {
+        match msgpack_round_trip(&text(DisplayOverride::default())) {
+            ServerMessage::Text { content, display, .. } => {
+                assert_eq!(content, "hi");
+                assert_eq!(display.display_name, None);
+                assert_eq!(display.avatar_url, None);
+            }
+            other => panic!("expected a text message, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn other_server_messages_survive_messagepack() {
+        let message = ServerMessage::ReplayTruncated {
+            room_id: "general".to_string(),
+            from_seq: None,
+        };
+        assert!(matches!(
+            msgpack_round_trip(&message),
+            ServerMessage::ReplayTruncated { room_id, from_seq: None } if room_id == "general"
+        ));
+    }
+
+    #[test]
+    fn client_messages_are_read_from_binary_frames() {
+        let join = ClientMessage::Join {
+            room_id: "general".to_string(),
+            user_id: "alice".to_string(),
+            last_seq: Some(41),
+        };
+        let bytes = rmp_serde::to_vec_named(&join).unwrap();
+        assert!(matches!(
+            decode_binary(&bytes),
+            Some(ClientMessage::Join { last_seq: Some(41), room_id, .. }) if room_id == "general"
+        ));
+
+        let text = rmp_serde::to_vec_named(&ClientMessage::Text { content: "hi".to_string() }).unwrap();
+        assert!(matches!(decode_binary(&text), Some(ClientMessage::Text { content }) if content == "hi"));
+    }
+
+    #[test]
+    fn garbage_frames_are_not_messages() {
+        assert!(decode_binary(&[0xc1]).is_none());
+        assert!(decode_text("{\"type\":\"Shout\"}").is_none());
+    }
+}
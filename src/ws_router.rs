@@ -0,0 +1,110 @@
+use actix::prelude::*;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+use crate::services::cluster::{ClusterBus, ClusterEvent};
+use crate::services::content_filter::ContentFilter;
+use crate::services::email_notifications::EmailNotifier;
+use crate::services::spam::SpamDetector;
+use crate::services::webhooks::WebhookQueue;
+use crate::ws_server::ChatServer;
+
+/// Partitions rooms across several `ChatServer` shards by room id hash.
+///
+/// Room traffic (join, leave, broadcast, typing) goes to the room's shard only.
+/// Connections are registered with every shard, since a session may join rooms
+/// living on any of them, and private messages are spread by recipient.
+#[derive(Clone)]
+pub struct ChatRouter {
+    shards: Arc<[Addr<ChatServer>]>,
+    spam: Arc<SpamDetector>,
+    content_filter: ContentFilter,
+}
+
+impl ChatRouter {
+    /// Starts `shard_count` chat server actors, each on its own arbiter thread
+    pub fn start(
+        shard_count: usize,
+        cluster: Option<ClusterBus>,
+        webhooks: Option<WebhookQueue>,
+        email: Option<EmailNotifier>,
+        spam: SpamDetector,
+        content_filter: ContentFilter,
+    ) -> Self {
+        let shards: Vec<Addr<ChatServer>> = (0..shard_count.max(1))
+            .map(|_| {
+                let cluster = cluster.clone();
+                let webhooks = webhooks.clone();
+                let email = email.clone();
+                ChatServer::start_in_arbiter(&Arbiter::new().handle(), move |_| {
+                    let mut server = ChatServer::new();
+                    if let Some(cluster) = cluster {
+                        server = server.with_cluster(cluster);
+                    }
+                    if let Some(webhooks) = webhooks {
+                        server = server.with_webhooks(webhooks);
+                    }
+                    if let Some(email) = email {
+                        server = server.with_email(email);
+                    }
+                    server
+                })
+            })
+            .collect();
+
+        Self {
+            shards: shards.into(),
+            spam: Arc::new(spam),
+            content_filter,
+        }
+    }
+
+    /// Per-user spam detection, shared by all shards
+    pub fn spam(&self) -> &SpamDetector {
+        &self.spam
+    }
+
+    /// The content rules every message is checked against
+    pub fn content_filter(&self) -> &ContentFilter {
+        &self.content_filter
+    }
+
+    /// The shard owning a room
+    pub fn room(&self, room_id: &str) -> &Addr<ChatServer> {
+        self.shard_for(room_id)
+    }
+
+    /// The shard handling private messages addressed to a user
+    pub fn user(&self, user_id: &str) -> &Addr<ChatServer> {
+        self.shard_for(user_id)
+    }
+
+    /// Sends a message to every shard
+    pub fn broadcast<M>(&self, msg: M)
+    where
+        M: Message + Send + Clone + 'static,
+        M::Result: Send,
+        ChatServer: Handler<M>,
+    {
+        for shard in self.shards.iter() {
+            shard.do_send(msg.clone());
+        }
+    }
+
+    /// Hands an event published by another node to the shard responsible for it
+    pub fn deliver(&self, event: ClusterEvent) {
+        let shard = match &event {
+            ClusterEvent::Room { room_id, .. } | ClusterEvent::Kick { room_id, .. } => self.room(room_id),
+            ClusterEvent::Private { to, .. } => self.user(to),
+            ClusterEvent::User { user_id, .. } | ClusterEvent::Revoke { user_id } => self.user(user_id),
+            ClusterEvent::Block { blocker_id, .. } => self.user(blocker_id),
+        };
+        shard.do_send(event);
+    }
+
+    fn shard_for(&self, key: &str) -> &Addr<ChatServer> {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        &self.shards[(hasher.finish() % self.shards.len() as u64) as usize]
+    }
+}
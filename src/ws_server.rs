@@ -1,32 +1,353 @@
 use actix::prelude::*;
 use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, RwLock};
+use uuid::Uuid;
+use crate::models::mention::MentionSpan;
+use crate::models::message::DisplayOverride;
+use crate::models::notification::{Notification, NotificationKind};
+use crate::models::room::Room;
+use crate::models::user::User;
+use crate::models::webhook::WebhookEvent;
+use crate::services::cluster::{ClusterBus, ClusterEvent};
+use crate::services::email_notifications::EmailNotifier;
+use crate::services::webhooks::WebhookQueue;
 use crate::utils::types::ServerMessage;
-use crate::ws_session::OutgoingRawMessage;
+use crate::ws_outbox::{CloseReason, CloseSession, FlushOutbox, OutboundFrame, Outbox, PushOutcome};
+use crate::ws_protocol::EncodedMessage;
 
-type SessionAddr = Addr<super::ws_session::ChatSession>;
+pub type SessionId = Uuid;
 
+/// How `ChatServer` reaches a connected session, whatever its transport
+/// (WebSocket, Server-Sent Events or long-polling)
+#[derive(Clone)]
+pub struct SessionHandle {
+    pub id: SessionId,
+    pub user_id: String,
+    pub outbox: Arc<Outbox>,
+    pub flush: Recipient<FlushOutbox>,
+    pub close: Recipient<CloseSession>,
+    pub removed: Recipient<RemovedFromRoom>,
+    /// Shared by every shard the session is registered with
+    pub filter: Arc<SessionFilter>,
+}
+
+/// Tells a session it was kicked or banned from a room and no longer receives its events
+pub struct RemovedFromRoom {
+    pub room_id: String,
+}
+impl Message for RemovedFromRoom {
+    type Result = ();
+}
+
+/// Events a session must not receive because of blocks, kept up to date as the
+/// session's user blocks and unblocks others or gets blocked
+#[derive(Debug, Default)]
+pub struct SessionFilter {
+    /// Users this session's user blocked, with whether their room messages are hidden too
+    blocked: RwLock<HashMap<String, bool>>,
+    /// Users who blocked this session's user, their presence is not shown
+    blocked_by: RwLock<HashSet<String>>,
+}
+
+impl SessionFilter {
+    pub fn new(blocked: HashMap<String, bool>, blocked_by: HashSet<String>) -> Self {
+        Self {
+            blocked: RwLock::new(blocked),
+            blocked_by: RwLock::new(blocked_by),
+        }
+    }
+
+    /// Records that this session's user blocked someone, `None` once unblocked
+    pub fn set_blocked(&self, user_id: &str, hide_messages: Option<bool>) {
+        let mut blocked = self.blocked.write().unwrap();
+        match hide_messages {
+            Some(hide_messages) => blocked.insert(user_id.to_string(), hide_messages),
+            None => blocked.remove(user_id),
+        };
+    }
+
+    /// Records that someone blocked or unblocked this session's user
+    pub fn set_blocked_by(&self, user_id: &str, blocked: bool) {
+        let mut blocked_by = self.blocked_by.write().unwrap();
+        if blocked {
+            blocked_by.insert(user_id.to_string());
+        } else {
+            blocked_by.remove(user_id);
+        }
+    }
+
+    pub fn hides(&self, message: &ServerMessage) -> bool {
+        let blocked = self.blocked.read().unwrap();
+        let hides_messages_of = |user_id: &String| blocked.get(user_id).copied().unwrap_or(false);
+        match message {
+            ServerMessage::Text { user_id, .. } => hides_messages_of(user_id),
+            ServerMessage::Typing { user_id } => {
+                hides_messages_of(user_id) || self.blocked_by.read().unwrap().contains(user_id)
+            }
+            ServerMessage::Join { user_id, .. } | ServerMessage::Leave { user_id, .. } => {
+                self.blocked_by.read().unwrap().contains(user_id)
+            }
+            ServerMessage::Private { from, .. }
+            | ServerMessage::Invite { from, .. }
+            | ServerMessage::Mention { from, .. } => blocked.contains_key(from),
+            _ => false,
+        }
+    }
+}
+
+#[derive(Default)]
 pub struct ChatServer {
-    rooms: HashMap<String, HashSet<SessionAddr>>,
-    user_sessions: HashMap<String, SessionAddr>,
+    rooms: HashMap<String, HashSet<SessionId>>,
+    /// Every live session of each connected user
+    user_sessions: HashMap<String, HashSet<SessionId>>,
+    /// Every connected session with its bounded outbound buffer
+    sessions: HashMap<SessionId, SessionHandle>,
+    /// Present when running as one of several nodes
+    cluster: Option<ClusterBus>,
+    /// Room events are queued for outgoing webhooks where they originate
+    webhooks: Option<WebhookQueue>,
+    /// Emails users about notifications they get while not connected
+    email: Option<EmailNotifier>,
 }
 
 impl ChatServer {
     pub fn new() -> Self {
-        Self {
-            rooms: HashMap::new(),
-            user_sessions: HashMap::new(),
+        Self::default()
+    }
+
+    pub fn with_cluster(mut self, cluster: ClusterBus) -> Self {
+        self.cluster = Some(cluster);
+        self
+    }
+
+    pub fn with_webhooks(mut self, webhooks: WebhookQueue) -> Self {
+        self.webhooks = Some(webhooks);
+        self
+    }
+
+    pub fn with_email(mut self, email: EmailNotifier) -> Self {
+        self.email = Some(email);
+        self
+    }
+
+    /// Queues an email when the user has no session left. Only this node's sessions are
+    /// known, which is why offline email is not enabled with clustering.
+    fn email_if_offline(&self, user_id: &str, category: &str, data: serde_json::Value) {
+        let Some(email) = &self.email else {
+            return;
+        };
+        if self.user_sessions.contains_key(user_id) {
+            return;
+        }
+        if let Ok(user_id) = Uuid::parse_str(user_id) {
+            email.enqueue(user_id, category, data);
+        }
+    }
+
+    /// Delivers an event to this node's sessions and, when clustered, publishes it to the others
+    fn dispatch(&mut self, event: ClusterEvent) {
+        self.deliver_local(&event);
+        if let Some(cluster) = &self.cluster {
+            cluster.publish(event);
+        }
+    }
+
+    fn deliver_local(&mut self, event: &ClusterEvent) {
+        let mut overflowed = Vec::new();
+        match event {
+            ClusterEvent::Room { room_id, seq, message_id, message } => {
+                let Some(session_ids) = self.rooms.get(room_id) else {
+                    return;
+                };
+                // Encoded once per codec, every session's frame shares the buffer
+                let mut encoded = EncodedMessage::new(message);
+                let typing_user = match message {
+                    ServerMessage::Typing { user_id } => Some(user_id.clone()),
+                    _ => None,
+                };
+                for session_id in session_ids {
+                    let Some(session) = self.sessions.get(session_id) else {
+                        continue;
+                    };
+                    if session.filter.hides(message) {
+                        continue;
+                    }
+                    let Some(payload) = encoded.get(session.outbox.codec()) else {
+                        continue;
+                    };
+                    let frame = OutboundFrame {
+                        room_id: Some(room_id.clone()),
+                        seq: *seq,
+                        message_id: *message_id,
+                        payload,
+                        typing_user: typing_user.clone(),
+                    };
+                    if Self::push_frame(session, frame) {
+                        overflowed.push(*session_id);
+                    }
+                }
+            }
+            ClusterEvent::Private { to, message } => {
+                let Some(session_ids) = self.user_sessions.get(to) else {
+                    return;
+                };
+                let mut encoded = EncodedMessage::new(message);
+                for session in session_ids.iter().filter_map(|id| self.sessions.get(id)) {
+                    if session.filter.hides(message) {
+                        continue;
+                    }
+                    let Some(payload) = encoded.get(session.outbox.codec()) else {
+                        continue;
+                    };
+                    let frame = OutboundFrame {
+                        room_id: None,
+                        seq: None,
+                        message_id: None,
+                        payload,
+                        typing_user: None,
+                    };
+                    if Self::push_frame(session, frame) {
+                        overflowed.push(session.id);
+                    }
+                }
+            }
+            ClusterEvent::User { user_id, message } => {
+                // Encoded once per codec, like room messages
+                let mut encoded = EncodedMessage::new(message);
+                for session in self.sessions.values().filter(|session| &session.user_id == user_id) {
+                    if session.filter.hides(message) {
+                        continue;
+                    }
+                    let Some(payload) = encoded.get(session.outbox.codec()) else {
+                        continue;
+                    };
+                    let frame = OutboundFrame {
+                        room_id: None,
+                        seq: None,
+                        message_id: None,
+                        payload,
+                        typing_user: None,
+                    };
+                    if Self::push_frame(session, frame) {
+                        overflowed.push(session.id);
+                    }
+                }
+            }
+            ClusterEvent::Kick { room_id, user_id } => {
+                let Some(session_ids) = self.rooms.get_mut(room_id) else {
+                    return;
+                };
+                let sessions = &self.sessions;
+                session_ids.retain(|id| match sessions.get(id) {
+                    Some(session) if &session.user_id == user_id => {
+                        session.removed.do_send(RemovedFromRoom { room_id: room_id.clone() });
+                        false
+                    }
+                    _ => true,
+                });
+                if session_ids.is_empty() {
+                    self.rooms.remove(room_id);
+                }
+            }
+            ClusterEvent::Block { blocker_id, blocked_id, hide_messages } => {
+                // Every shard shares the sessions' filters, updating them once is enough
+                for session in self.sessions.values() {
+                    if &session.user_id == blocker_id {
+                        session.filter.set_blocked(blocked_id, *hide_messages);
+                    } else if &session.user_id == blocked_id {
+                        session.filter.set_blocked_by(blocker_id, hide_messages.is_some());
+                    }
+                }
+            }
+            ClusterEvent::Revoke { user_id } => {
+                let revoked: Vec<SessionId> = self
+                    .sessions
+                    .values()
+                    .filter(|session| &session.user_id == user_id)
+                    .map(|session| session.id)
+                    .collect();
+                for session_id in revoked {
+                    self.close_session(session_id, CloseReason::AuthRevoked);
+                }
+            }
+        }
+
+        for session_id in overflowed {
+            self.close_session(session_id, CloseReason::TooSlow);
+        }
+    }
+
+    /// Queues a frame for a session, returning whether its outbox just overflowed
+    fn push_frame(session: &SessionHandle, frame: OutboundFrame) -> bool {
+        match session.outbox.push(frame) {
+            PushOutcome::Queued { wake: true } => session.flush.do_send(FlushOutbox),
+            PushOutcome::Queued { wake: false } | PushOutcome::Closed => {}
+            PushOutcome::Overflowed => return true,
+        }
+        false
+    }
+
+    /// Stops routing to a session and tells it to close, it does so once it is polled again
+    fn close_session(&mut self, session_id: SessionId, reason: CloseReason) {
+        let Some(session) = self.sessions.remove(&session_id) else {
+            return;
+        };
+        self.rooms.retain(|_, sessions| {
+            sessions.remove(&session_id);
+            !sessions.is_empty()
+        });
+        self.forget_user_session(&session.user_id, session_id);
+        session.close.do_send(CloseSession { reason });
+    }
+
+    fn forget_user_session(&mut self, user_id: &str, session_id: SessionId) {
+        if let Some(session_ids) = self.user_sessions.get_mut(user_id) {
+            session_ids.remove(&session_id);
+            if session_ids.is_empty() {
+                self.user_sessions.remove(user_id);
+            }
+        }
+    }
+
+    /// Delivers a message to a user's sessions on whichever node holds them
+    fn send_to_user(&mut self, to: String, message: ServerMessage) {
+        // Only go through the cluster when the recipient is not connected here
+        let is_local = self.user_sessions.contains_key(&to);
+        let event = ClusterEvent::Private { to, message };
+        match &self.cluster {
+            Some(cluster) if !is_local => cluster.publish(event),
+            _ => self.deliver_local(&event),
         }
     }
+
+    fn announce_leave(&mut self, room_id: String, user_id: String) {
+        self.dispatch(ClusterEvent::Room {
+            room_id: room_id.clone(),
+            seq: None,
+            message_id: None,
+            message: ServerMessage::Leave { room_id, user_id },
+        });
+    }
 }
 
 impl Actor for ChatServer {
     type Context = Context<Self>;
 }
 
+#[derive(Clone)]
+pub struct Connect {
+    pub user_id: String,
+    pub session: SessionHandle,
+}
+impl Message for Connect {
+    type Result = ();
+}
+
 pub struct JoinRoom {
     pub user_id: String,
     pub room_id: String,
-    pub addr: SessionAddr,
+    /// Carried along since the room's shard may see the join before the session's `Connect`
+    pub session: SessionHandle,
 }
 impl Message for JoinRoom {
     type Result = ();
@@ -34,17 +355,77 @@ impl Message for JoinRoom {
 
 pub struct LeaveRoom {
     pub user_id: String,
+    pub room_id: String,
+    pub session_id: SessionId,
 }
 impl Message for LeaveRoom {
     type Result = ();
 }
 
+#[derive(Clone)]
+pub struct Disconnect {
+    pub user_id: String,
+    pub session_id: SessionId,
+}
+impl Message for Disconnect {
+    type Result = ();
+}
+
+impl Handler<Connect> for ChatServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: Connect, _: &mut Context<Self>) {
+        self.user_sessions.entry(msg.user_id).or_default().insert(msg.session.id);
+        self.sessions.insert(msg.session.id, msg.session);
+    }
+}
+
 impl Handler<JoinRoom> for ChatServer {
     type Result = ();
 
     fn handle(&mut self, msg: JoinRoom, _: &mut Context<Self>) {
-        self.rooms.entry(msg.room_id).or_default().insert(msg.addr.clone());
-        self.user_sessions.insert(msg.user_id, msg.addr);
+        if !msg.session.flush.connected() {
+            return;
+        }
+        self.rooms.entry(msg.room_id.clone()).or_default().insert(msg.session.id);
+        self.sessions.entry(msg.session.id).or_insert(msg.session);
+        self.dispatch(ClusterEvent::Room {
+            room_id: msg.room_id.clone(),
+            seq: None,
+            message_id: None,
+            message: ServerMessage::Join {
+                room_id: msg.room_id,
+                user_id: msg.user_id,
+            },
+        });
+    }
+}
+
+/// Someone became a member of a room, sent to the room's shard
+pub struct MemberAdded {
+    pub room_id: String,
+    pub user_id: String,
+    pub invited_by: String,
+}
+impl Message for MemberAdded {
+    type Result = ();
+}
+
+impl Handler<MemberAdded> for ChatServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: MemberAdded, _: &mut Context<Self>) {
+        if let Some(webhooks) = &self.webhooks {
+            webhooks.enqueue(
+                &msg.room_id,
+                WebhookEvent::MemberJoined,
+                &serde_json::json!({
+                    "room_id": msg.room_id,
+                    "user_id": msg.user_id,
+                    "invited_by": msg.invited_by,
+                }),
+            );
+        }
     }
 }
 
@@ -52,7 +433,35 @@ impl Handler<LeaveRoom> for ChatServer {
     type Result = ();
 
     fn handle(&mut self, msg: LeaveRoom, _: &mut Context<Self>) {
-        self.user_sessions.remove(&msg.user_id);
+        if let Some(sessions) = self.rooms.get_mut(&msg.room_id) {
+            if !sessions.remove(&msg.session_id) {
+                return;
+            }
+            if sessions.is_empty() {
+                self.rooms.remove(&msg.room_id);
+            }
+        }
+        self.announce_leave(msg.room_id, msg.user_id);
+    }
+}
+
+impl Handler<Disconnect> for ChatServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: Disconnect, _: &mut Context<Self>) {
+        self.sessions.remove(&msg.session_id);
+        let mut left = Vec::new();
+        self.rooms.retain(|room_id, sessions| {
+            if sessions.remove(&msg.session_id) {
+                left.push(room_id.clone());
+            }
+            !sessions.is_empty()
+        });
+        // The user's other sessions keep them online
+        self.forget_user_session(&msg.user_id, msg.session_id);
+        for room_id in left {
+            self.announce_leave(room_id, msg.user_id.clone());
+        }
     }
 }
 
@@ -60,6 +469,11 @@ pub struct BroadcastMessage {
     pub room_id: String,
     pub message: String,
     pub sender_id: String,
+    /// Sequence number assigned when the message was stored, `None` for ephemeral rooms
+    pub seq: Option<i64>,
+    pub message_id: Option<Uuid>,
+    pub display: DisplayOverride,
+    pub mentions: Vec<MentionSpan>,
 }
 impl Message for BroadcastMessage {
     type Result = ();
@@ -69,16 +483,54 @@ impl Handler<BroadcastMessage> for ChatServer {
     type Result = ();
 
     fn handle(&mut self, msg: BroadcastMessage, _: &mut Context<Self>) {
-        if let Some(sessions) = self.rooms.get(&msg.room_id) {
-            for session in sessions {
-                session.do_send(OutgoingRawMessage(
-                    serde_json::to_string(&ServerMessage::Text {
-                        content: msg.message.clone(),
-                        user_id: msg.sender_id.clone(),
-                    }).unwrap()
-                ));
-            }
+        if let (Some(webhooks), Some(message_id)) = (&self.webhooks, msg.message_id) {
+            webhooks.enqueue(
+                &msg.room_id,
+                WebhookEvent::MessageCreated,
+                &serde_json::json!({
+                    "message_id": message_id,
+                    "room_id": msg.room_id,
+                    "sender_id": msg.sender_id,
+                    "content": msg.message,
+                    "seq": msg.seq,
+                }),
+            );
         }
+        self.dispatch(ClusterEvent::Room {
+            room_id: msg.room_id.clone(),
+            seq: msg.seq,
+            message_id: msg.message_id,
+            message: ServerMessage::Text {
+                room_id: msg.room_id,
+                content: msg.message,
+                user_id: msg.sender_id,
+                seq: msg.seq,
+                message_id: msg.message_id.map(|id| id.to_string()),
+                display: msg.display,
+                mentions: msg.mentions,
+            },
+        });
+    }
+}
+
+pub struct TypingIndicator {
+    pub room_id: String,
+    pub user_id: String,
+}
+impl Message for TypingIndicator {
+    type Result = ();
+}
+
+impl Handler<TypingIndicator> for ChatServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: TypingIndicator, _: &mut Context<Self>) {
+        self.dispatch(ClusterEvent::Room {
+            room_id: msg.room_id,
+            seq: None,
+            message_id: None,
+            message: ServerMessage::Typing { user_id: msg.user_id },
+        });
     }
 }
 
@@ -95,13 +547,278 @@ impl Handler<PrivateMessage> for ChatServer {
     type Result = ();
 
     fn handle(&mut self, msg: PrivateMessage, _: &mut Context<Self>) {
-        if let Some(session) = self.user_sessions.get(&msg.to) {
-            session.do_send(OutgoingRawMessage(
-                serde_json::to_string(&ServerMessage::Private {
-                    from: msg.from,
-                    content: msg.content,
-                }).unwrap()
-            ));
+        self.email_if_offline(
+            &msg.to,
+            NotificationKind::DirectMessage.as_str(),
+            serde_json::json!({ "from": msg.from, "content": msg.content }),
+        );
+        self.send_to_user(
+            msg.to,
+            ServerMessage::Private {
+                from: msg.from,
+                content: msg.content,
+            },
+        );
+    }
+}
+
+/// Sends a server event to a single user, such as an invite
+pub struct NotifyUser {
+    pub to: String,
+    pub message: ServerMessage,
+}
+impl Message for NotifyUser {
+    type Result = ();
+}
+
+impl Handler<NotifyUser> for ChatServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyUser, _: &mut Context<Self>) {
+        self.send_to_user(msg.to, msg.message);
+    }
+}
+
+/// Pushes a stored notification to every session of its user, on every node
+pub struct PushNotification {
+    pub notification: Notification,
+}
+impl Message for PushNotification {
+    type Result = ();
+}
+
+impl Handler<PushNotification> for ChatServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: PushNotification, _: &mut Context<Self>) {
+        let user_id = msg.notification.user_id.to_string();
+        // Direct messages are emailed as they are sent, with their content
+        if msg.notification.kind != NotificationKind::DirectMessage.as_str() {
+            self.email_if_offline(&user_id, &msg.notification.kind, msg.notification.data.clone());
+        }
+        self.dispatch(ClusterEvent::User {
+            user_id,
+            message: ServerMessage::Notification {
+                notification: msg.notification,
+            },
+        });
+    }
+}
+
+/// Tells a room's members about its new name or topic
+pub struct RoomUpdated {
+    pub room: Room,
+}
+impl Message for RoomUpdated {
+    type Result = ();
+}
+
+impl Handler<RoomUpdated> for ChatServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: RoomUpdated, _: &mut Context<Self>) {
+        let room_id = msg.room.id.to_string();
+        if let Some(webhooks) = &self.webhooks {
+            webhooks.enqueue(&room_id, WebhookEvent::RoomUpdated, &msg.room);
         }
+        self.dispatch(ClusterEvent::Room {
+            room_id: room_id.clone(),
+            seq: None,
+            message_id: None,
+            message: ServerMessage::RoomUpdated {
+                room_id,
+                name: msg.room.name,
+                topic: msg.room.topic,
+            },
+        });
+    }
+}
+
+/// Tells a room one of its members changed their profile
+pub struct ProfileUpdated {
+    pub room_id: String,
+    pub user: User,
+}
+impl Message for ProfileUpdated {
+    type Result = ();
+}
+
+impl Handler<ProfileUpdated> for ChatServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: ProfileUpdated, _: &mut Context<Self>) {
+        self.dispatch(ClusterEvent::Room {
+            room_id: msg.room_id.clone(),
+            seq: None,
+            message_id: None,
+            message: ServerMessage::UserUpdated {
+                room_id: msg.room_id,
+                user_id: msg.user.id.to_string(),
+                full_name: msg.user.full_name,
+                display_name: msg.user.display_name,
+                avatar_url: msg.user.avatar_url,
+                pronouns: msg.user.pronouns,
+            },
+        });
+    }
+}
+
+/// Asks a room's shard which of `user_ids` are connected to this node, and who is in the room
+pub struct RoomPresence {
+    pub room_id: String,
+    pub user_ids: Vec<Uuid>,
+}
+impl Message for RoomPresence {
+    type Result = Presence;
+}
+
+#[derive(Debug, Default)]
+pub struct Presence {
+    /// Those of the given users with a session on this node
+    pub online: Vec<Uuid>,
+    /// Users with a session in the room
+    pub in_room: Vec<Uuid>,
+}
+
+impl Handler<RoomPresence> for ChatServer {
+    type Result = MessageResult<RoomPresence>;
+
+    fn handle(&mut self, msg: RoomPresence, _: &mut Context<Self>) -> Self::Result {
+        let online = msg
+            .user_ids
+            .into_iter()
+            .filter(|user_id| self.user_sessions.contains_key(&user_id.to_string()))
+            .collect();
+        let in_room = self
+            .rooms
+            .get(&msg.room_id)
+            .into_iter()
+            .flatten()
+            .filter_map(|session_id| self.sessions.get(session_id))
+            .filter_map(|session| Uuid::parse_str(&session.user_id).ok())
+            .collect();
+        MessageResult(Presence { online, in_room })
+    }
+}
+
+/// Tells a room one of its messages was deleted
+pub struct MessageDeleted {
+    pub room_id: String,
+    pub message_id: Uuid,
+}
+impl Message for MessageDeleted {
+    type Result = ();
+}
+
+impl Handler<MessageDeleted> for ChatServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: MessageDeleted, _: &mut Context<Self>) {
+        self.dispatch(ClusterEvent::Room {
+            room_id: msg.room_id.clone(),
+            seq: None,
+            message_id: None,
+            message: ServerMessage::MessageDeleted {
+                room_id: msg.room_id,
+                message_id: msg.message_id.to_string(),
+            },
+        });
+    }
+}
+
+/// Announces a moderator action in its room, then removes a kicked or banned
+/// member's sessions from it on every node
+pub struct Moderate {
+    pub room_id: String,
+    pub user_id: String,
+    pub announcement: ServerMessage,
+    pub remove: bool,
+}
+impl Message for Moderate {
+    type Result = ();
+}
+
+impl Handler<Moderate> for ChatServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: Moderate, _: &mut Context<Self>) {
+        self.dispatch(ClusterEvent::Room {
+            room_id: msg.room_id.clone(),
+            seq: None,
+            message_id: None,
+            message: msg.announcement,
+        });
+        if msg.remove {
+            self.dispatch(ClusterEvent::Kick {
+                room_id: msg.room_id,
+                user_id: msg.user_id,
+            });
+        }
+    }
+}
+
+/// Updates the filters of both users' sessions after a block changed, on every node
+pub struct BlockChanged {
+    pub blocker_id: String,
+    pub blocked_id: String,
+    /// `None` when the block was lifted
+    pub hide_messages: Option<bool>,
+}
+impl Message for BlockChanged {
+    type Result = ();
+}
+
+impl Handler<BlockChanged> for ChatServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: BlockChanged, _: &mut Context<Self>) {
+        self.dispatch(ClusterEvent::Block {
+            blocker_id: msg.blocker_id,
+            blocked_id: msg.blocked_id,
+            hide_messages: msg.hide_messages,
+        });
+    }
+}
+
+/// Closes every session of a user whose tokens were revoked, on every node
+pub struct RevokeSessions {
+    pub user_id: String,
+}
+impl Message for RevokeSessions {
+    type Result = ();
+}
+
+impl Handler<RevokeSessions> for ChatServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: RevokeSessions, _: &mut Context<Self>) {
+        self.dispatch(ClusterEvent::Revoke { user_id: msg.user_id });
+    }
+}
+
+/// Events published by other nodes
+impl Handler<ClusterEvent> for ChatServer {
+    type Result = ();
+
+    fn handle(&mut self, event: ClusterEvent, _: &mut Context<Self>) {
+        self.deliver_local(&event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn users_stay_online_until_their_last_session_closes() {
+        let mut server = ChatServer::new();
+        let (older, newer) = (Uuid::new_v4(), Uuid::new_v4());
+        server.user_sessions.entry("alice".to_string()).or_default().extend([older, newer]);
+
+        server.forget_user_session("alice", newer);
+        assert!(server.user_sessions.contains_key("alice"));
+
+        server.forget_user_session("alice", older);
+        assert!(!server.user_sessions.contains_key("alice"));
     }
 }
@@ -1,92 +1,533 @@
-use actix::{Actor, Addr, AsyncContext, Context, Handler, StreamHandler};
+use actix::{Actor, ActorContext, ActorFutureExt, AsyncContext, ContextFutureSpawner, Handler, SpawnHandle, StreamHandler, WrapFuture};
 use actix_web_actors::ws;
-use serde::Deserialize;
+use chrono::Utc;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::error;
+use uuid::Uuid;
+use crate::database::connection::DbPool;
+use crate::middleware::auth::API_TOKEN_SESSION_SECS;
+use crate::models::api_token::{ApiToken, API_TOKEN_PREFIX};
+use crate::models::message::{DisplayOverride, Message, MAX_REPLAY};
+use crate::services::auth::AuthService;
+use crate::services::commands::{self, CommandContext, CommandOutcome, Invocation, NO_ROOM};
+use crate::services::email_verification::{self, VERIFY_FIRST};
+use crate::services::messaging::{self, PostError};
+use crate::services::moderation;
 use crate::utils::types::{ClientMessage, ServerMessage};
+use crate::ws_protocol::{self, Payload};
+use crate::ws_outbox::{CloseReason, CloseSession, FlushOutbox, OutboundFrame, Outbox};
+use crate::ws_router::ChatRouter;
+use crate::ws_server::{RemovedFromRoom, SessionFilter, SessionHandle, SessionId};
+
+/// Close code sent to clients that could not keep up with their outbound buffer
+pub const CLOSE_TOO_SLOW: u16 = 4001;
+/// Close code sent when the session's token expired or was revoked
+pub const CLOSE_AUTH_EXPIRED: u16 = 4002;
+
+/// Clients are warned this long before their token expires
+const AUTH_EXPIRY_WARNING_SECS: i64 = 60;
+
+/// Credentials a session was opened with
+pub struct SessionAuth {
+    pub service: Arc<AuthService>,
+    /// Unix seconds after which the session is closed unless it reauthenticates
+    pub expires_at: i64,
+    /// Whether users with an unverified email are kept from posting and joining public rooms
+    pub require_verified_email: bool,
+}
 
 pub struct ChatSession {
+    pub id: SessionId,
     pub user_id: String,
-    pub room_id: String,
-    pub server_addr: Addr<super::ws_server::ChatServer>,
+    /// Where text, typing and commands go, set once a join succeeds
+    pub room_id: Option<String>,
+    pub router: ChatRouter,
+    pool: DbPool,
+    outbox: Arc<Outbox>,
+    /// Room and sequence number requested on the handshake, joined when the session starts
+    initial_join: Option<(String, Option<i64>)>,
+    /// Highest sequence number delivered by replay, per room; live copies at or below it are dropped
+    replayed_through: HashMap<String, i64>,
+    /// Live messages held back while a room's history is being replayed
+    pending: HashMap<String, Vec<OutboundFrame>>,
+    auth: SessionAuth,
+    /// Expiry warning and close timers, replaced on every reauth
+    expiry_timers: Vec<SpawnHandle>,
+    filter: Arc<SessionFilter>,
 }
 
 impl ChatSession {
-    pub fn new(user_id: String, room_id: String, server_addr: Addr<super::ws_server::ChatServer>) -> Self {
+    pub fn new(
+        user_id: String,
+        room_id: String,
+        last_seq: Option<i64>,
+        router: ChatRouter,
+        pool: DbPool,
+        outbox: Arc<Outbox>,
+        auth: SessionAuth,
+    ) -> Self {
         Self {
+            id: Uuid::new_v4(),
             user_id,
-            room_id,
-            server_addr,
+            room_id: None,
+            router,
+            pool,
+            outbox,
+            initial_join: Some((room_id, last_seq)),
+            replayed_through: HashMap::new(),
+            pending: HashMap::new(),
+            auth,
+            expiry_timers: Vec::new(),
+            filter: Arc::default(),
+        }
+    }
+
+    /// Hides what the user's blocks say they must not receive
+    pub fn with_filter(mut self, filter: SessionFilter) -> Self {
+        self.filter = Arc::new(filter);
+        self
+    }
+
+    fn session_handle(&self, ctx: &mut ws::WebsocketContext<Self>) -> SessionHandle {
+        let addr = ctx.address();
+        SessionHandle {
+            id: self.id,
+            user_id: self.user_id.clone(),
+            outbox: self.outbox.clone(),
+            flush: addr.clone().recipient(),
+            close: addr.clone().recipient(),
+            removed: addr.recipient(),
+            filter: self.filter.clone(),
         }
     }
 
     fn send_server_message(&self, msg: ServerMessage, ctx: &mut ws::WebsocketContext<Self>) {
-        if let Ok(json) = serde_json::to_string(&msg) {
-            ctx.text(json);
+        if let Some(payload) = self.outbox.codec().encode(&msg) {
+            write_payload(payload, ctx);
         }
     }
+
+    /// Subscribes to a room and, when `last_seq` is given, replays everything stored after it
+    /// before switching to live delivery.
+    fn join_room(&mut self, room_id: String, last_seq: Option<i64>, ctx: &mut ws::WebsocketContext<Self>) {
+        let join = self.router.room(&room_id).send(super::ws_server::JoinRoom {
+            user_id: self.user_id.clone(),
+            room_id: room_id.clone(),
+            session: self.session_handle(ctx),
+        });
+
+        let Some(last_seq) = last_seq else {
+            join.into_actor(self).map(|_, _, _| ()).spawn(ctx);
+            return;
+        };
+
+        // Hold back live messages until the replay query has run, so nothing
+        // broadcast after the join is delivered ahead of older history.
+        self.pending.insert(room_id.clone(), Vec::new());
+        join.into_actor(self)
+            .then(move |_, act, _| {
+                let pool = act.pool.clone();
+                let room_uuid = Uuid::parse_str(&room_id).ok();
+                let viewer = Uuid::parse_str(&act.user_id).ok();
+                async move {
+                    match (room_uuid, viewer) {
+                        (Some(room_uuid), Some(viewer)) => {
+                            // One more than is replayed, to tell whether any were skipped
+                            Message::find_by_room_id_after_seq(&pool, room_uuid, last_seq, viewer, MAX_REPLAY as i64 + 1)
+                                .await
+                        }
+                        _ => Ok(Vec::new()),
+                    }
+                }
+                .into_actor(act)
+                .map(move |result, act, ctx| act.finish_replay(room_id, result, ctx))
+            })
+            .spawn(ctx);
+    }
+
+    /// Joins a room unless it is private and this user not a member, or `auth.require_verified_email`
+    /// or a kick or ban keeps them out of it
+    fn request_join(&mut self, room_id: String, last_seq: Option<i64>, ctx: &mut ws::WebsocketContext<Self>) {
+        let pool = self.pool.clone();
+        let user_id = self.user_id.clone();
+        let room = room_id.clone();
+        let require_verified = self.auth.require_verified_email;
+        async move {
+            if let Some(refusal) = moderation::privacy_refusal(&pool, &user_id, &room).await? {
+                return Ok(Some(refusal));
+            }
+            if !email_verification::may_join(&pool, require_verified, &user_id, &room).await? {
+                return Ok(Some(VERIFY_FIRST.to_string()));
+            }
+            moderation::join_refusal(&pool, &user_id, &room).await
+        }
+            .into_actor(self)
+            .map(move |result, act, ctx| match result {
+                Ok(None) => {
+                    act.room_id = Some(room_id.clone());
+                    act.join_room(room_id, last_seq, ctx);
+                }
+                Ok(Some(refusal)) => act.send_server_message(ServerMessage::Error { message: refusal }, ctx),
+                Err(e) => {
+                    error!("Failed to check whether user {} may join room {}: {}", act.user_id, room_id, e);
+                    act.send_server_message(
+                        ServerMessage::Error {
+                            message: "Failed to join room".to_string(),
+                        },
+                        ctx,
+                    );
+                }
+            })
+            .spawn(ctx);
+    }
+
+    fn finish_replay(
+        &mut self,
+        room_id: String,
+        result: Result<Vec<Message>, sqlx::Error>,
+        ctx: &mut ws::WebsocketContext<Self>,
+    ) {
+        match result {
+            Ok(mut messages) => {
+                if messages.len() > MAX_REPLAY {
+                    messages.remove(0);
+                    self.send_server_message(
+                        ServerMessage::ReplayTruncated {
+                            room_id: room_id.clone(),
+                            from_seq: messages.first().map(|message| message.seq),
+                        },
+                        ctx,
+                    );
+                }
+                for message in messages {
+                    self.replayed_through.insert(room_id.clone(), message.seq);
+                    self.send_server_message(
+                        ServerMessage::Text {
+                            room_id: room_id.clone(),
+                            content: message.content,
+                            user_id: message.sender_id.to_string(),
+                            seq: Some(message.seq),
+                            message_id: Some(message.id.to_string()),
+                            display: message.display,
+                            mentions: message.mentions,
+                        },
+                        ctx,
+                    );
+                }
+            }
+            Err(e) => {
+                error!("Failed to replay messages for room {}: {}", room_id, e);
+                self.send_server_message(
+                    ServerMessage::Error {
+                        message: "Failed to replay missed messages".to_string(),
+                    },
+                    ctx,
+                );
+            }
+        }
+
+        for frame in self.pending.remove(&room_id).unwrap_or_default() {
+            self.deliver_frame(frame, ctx);
+        }
+    }
+
+    fn deliver_frame(&mut self, frame: OutboundFrame, ctx: &mut ws::WebsocketContext<Self>) {
+        if let Some(room_id) = &frame.room_id {
+            if let Some(pending) = self.pending.get_mut(room_id) {
+                pending.push(frame);
+                return;
+            }
+            if let (Some(seq), Some(replayed)) = (frame.seq, self.replayed_through.get(room_id)) {
+                if seq <= *replayed {
+                    return;
+                }
+            }
+        }
+        write_payload(frame.payload, ctx);
+    }
+
+    /// Stores a message in the current room and broadcasts it with its sequence number.
+    /// The session waits for it, so a sender's messages are stored in the order they arrive.
+    fn post_to_room(&mut self, content: String, ctx: &mut ws::WebsocketContext<Self>) {
+        let Some(room_id) = self.room_id.clone() else {
+            self.send_server_message(
+                ServerMessage::Error {
+                    message: NO_ROOM.to_string(),
+                },
+                ctx,
+            );
+            return;
+        };
+        let pool = self.pool.clone();
+        let router = self.router.clone();
+        let room = room_id.clone();
+        let sender_id = self.user_id.clone();
+        let require_verified = self.auth.require_verified_email;
+        async move {
+            if email_verification::is_restricted(&pool, require_verified, &sender_id).await? {
+                return Err(PostError::Rejected(VERIFY_FIRST.to_string()));
+            }
+            messaging::post_room_message(&pool, &router, &room_id, &sender_id, content, DisplayOverride::default())
+                .await
+        }
+            .into_actor(self)
+            .map(move |result, act, ctx| match result {
+                Ok(_) => {}
+                Err(PostError::Rejected(reason)) => {
+                    act.send_server_message(ServerMessage::Error { message: reason }, ctx);
+                }
+                Err(e) => {
+                    error!("Failed to store message in room {}: {}", room, e);
+                    act.send_server_message(
+                        ServerMessage::Error {
+                            message: "Failed to send message".to_string(),
+                        },
+                        ctx,
+                    );
+                }
+            })
+            .wait(ctx);
+    }
+
+    fn send_private(&mut self, to: String, content: String, ctx: &mut ws::WebsocketContext<Self>) {
+        let pool = self.pool.clone();
+        let router = self.router.clone();
+        let from = self.user_id.clone();
+        async move { messaging::send_private_message(&pool, &router, &from, &to, content).await }
+            .into_actor(self)
+            .map(|result, act, ctx| match result {
+                Ok(()) => {}
+                Err(PostError::Rejected(reason)) => {
+                    act.send_server_message(ServerMessage::Error { message: reason }, ctx);
+                }
+                Err(e) => {
+                    error!("Failed to record direct message of user {}: {}", act.user_id, e);
+                }
+            })
+            .spawn(ctx);
+    }
+
+    /// Runs a slash command in the current room instead of posting it
+    fn run_command(&mut self, invocation: Invocation, ctx: &mut ws::WebsocketContext<Self>) {
+        let context = CommandContext {
+            pool: self.pool.clone(),
+            router: self.router.clone(),
+            user_id: self.user_id.clone(),
+            room_id: self.room_id.clone().unwrap_or_default(),
+        };
+        let require_verified = self.auth.require_verified_email;
+        async move {
+            // Commands post on the user's behalf, so they are held to the same rule
+            let outcome = match email_verification::is_restricted(&context.pool, require_verified, &context.user_id).await {
+                Ok(true) => Ok(CommandOutcome::Reply(VERIFY_FIRST.to_string())),
+                Ok(false) => commands::registry().dispatch(&context, &invocation).await,
+                Err(e) => Err(e),
+            };
+            (invocation, context.room_id, outcome)
+        }
+            .into_actor(self)
+            .map(|(invocation, room_id, outcome), act, ctx| match outcome {
+                Ok(CommandOutcome::Reply(content)) => act.send_server_message(
+                    ServerMessage::CommandResponse {
+                        command: invocation.name,
+                        content,
+                    },
+                    ctx,
+                ),
+                Ok(CommandOutcome::Leave) => act.leave_room(room_id),
+                Ok(CommandOutcome::Done) => {}
+                Err(e) => {
+                    error!("Failed to run /{} in room {}: {}", invocation.name, room_id, e);
+                    act.send_server_message(
+                        ServerMessage::Error {
+                            message: format!("Failed to run /{}", invocation.name),
+                        },
+                        ctx,
+                    );
+                }
+            })
+            .spawn(ctx);
+    }
+
+    fn leave_room(&mut self, room_id: String) {
+        if self.room_id.as_ref() == Some(&room_id) {
+            self.room_id = None;
+        }
+        self.pending.remove(&room_id);
+        self.replayed_through.remove(&room_id);
+        self.router.room(&room_id).do_send(super::ws_server::LeaveRoom {
+            user_id: self.user_id.clone(),
+            room_id,
+            session_id: self.id,
+        });
+    }
+
+    /// (Re)arms the expiry warning and the close at `auth.expires_at`
+    fn schedule_auth_expiry(&mut self, ctx: &mut ws::WebsocketContext<Self>) {
+        for timer in self.expiry_timers.drain(..) {
+            ctx.cancel_future(timer);
+        }
+
+        let expires_at = self.auth.expires_at;
+        let remaining = (expires_at - Utc::now().timestamp()).max(0);
+        if remaining > AUTH_EXPIRY_WARNING_SECS {
+            let warn_in = Duration::from_secs((remaining - AUTH_EXPIRY_WARNING_SECS) as u64);
+            self.expiry_timers.push(ctx.run_later(warn_in, move |act, ctx| {
+                act.send_server_message(ServerMessage::AuthExpiring { expires_at }, ctx);
+            }));
+        }
+        self.expiry_timers.push(ctx.run_later(Duration::from_secs(remaining as u64), |_, ctx| {
+            close_with(CLOSE_AUTH_EXPIRED, "auth expired", ctx);
+        }));
+    }
+
+    /// Extends the session with a fresh token for the same user. API tokens do not expire,
+    /// sending the same one again extends the session as long as it was not deleted.
+    fn reauthenticate(&mut self, token: String, ctx: &mut ws::WebsocketContext<Self>) {
+        let service = self.auth.service.clone();
+        let pool = self.pool.clone();
+        async move {
+            if token.starts_with(API_TOKEN_PREFIX) {
+                let user = ApiToken::find_user(&pool, &token).await.map_err(|e| e.to_string())?;
+                return Ok(user.map(|user| (user.id, Utc::now().timestamp() + API_TOKEN_SESSION_SECS)));
+            }
+            let claims = service.validate_active_token(&pool, &token).await.map_err(|e| e.to_string())?;
+            Ok(claims.map(|claims| (claims.sub, claims.exp)))
+        }
+            .into_actor(self)
+            .map(|result: Result<Option<(Uuid, i64)>, String>, act, ctx| match result {
+                Ok(Some((user_id, expires_at))) if user_id.to_string() == act.user_id => {
+                    act.auth.expires_at = expires_at;
+                    act.schedule_auth_expiry(ctx);
+                    act.send_server_message(ServerMessage::Reauthenticated { expires_at }, ctx);
+                }
+                Ok(_) => act.send_server_message(
+                    ServerMessage::Error {
+                        message: "Invalid token".to_string(),
+                    },
+                    ctx,
+                ),
+                Err(e) => {
+                    error!("Failed to reauthenticate session of user {}: {}", act.user_id, e);
+                    act.send_server_message(
+                        ServerMessage::Error {
+                            message: "Failed to reauthenticate".to_string(),
+                        },
+                        ctx,
+                    );
+                }
+            })
+            .spawn(ctx);
+    }
+}
+
+fn close_with(code: u16, description: &str, ctx: &mut ws::WebsocketContext<ChatSession>) {
+    ctx.close(Some(ws::CloseReason {
+        code: ws::CloseCode::Other(code),
+        description: Some(description.to_string()),
+    }));
+    ctx.stop();
+}
+
+fn write_payload(payload: Payload, ctx: &mut ws::WebsocketContext<ChatSession>) {
+    match payload {
+        Payload::Text(text) => ctx.text(text),
+        Payload::Binary(bytes) => ctx.binary(bytes),
+    }
 }
 
 impl Actor for ChatSession {
     type Context = ws::WebsocketContext<Self>;
 
     fn started(&mut self, ctx: &mut Self::Context) {
-        self.server_addr.do_send(super::ws_server::JoinRoom {
+        self.router.broadcast(super::ws_server::Connect {
             user_id: self.user_id.clone(),
-            room_id: self.room_id.clone(),
-            addr: ctx.address(),
+            session: self.session_handle(ctx),
         });
+        if let Some((room_id, last_seq)) = self.initial_join.take() {
+            self.request_join(room_id, last_seq, ctx);
+        }
+        self.schedule_auth_expiry(ctx);
     }
 
     fn stopped(&mut self, _ctx: &mut Self::Context) {
-        self.server_addr.do_send(super::ws_server::LeaveRoom {
+        self.router.broadcast(super::ws_server::Disconnect {
             user_id: self.user_id.clone(),
+            session_id: self.id,
         });
     }
 }
 
-// Accepts a raw JSON string (from ws_server.rs) and sends it to the client
-pub struct OutgoingRawMessage(pub String);
-impl actix::Message for OutgoingRawMessage {
+impl Handler<FlushOutbox> for ChatSession {
+    type Result = ();
+
+    fn handle(&mut self, _: FlushOutbox, ctx: &mut Self::Context) {
+        for frame in self.outbox.drain() {
+            self.deliver_frame(frame, ctx);
+        }
+    }
+}
+
+impl Handler<CloseSession> for ChatSession {
     type Result = ();
+
+    fn handle(&mut self, msg: CloseSession, ctx: &mut Self::Context) {
+        match msg.reason {
+            CloseReason::TooSlow => close_with(CLOSE_TOO_SLOW, "too slow", ctx),
+            CloseReason::AuthRevoked => close_with(CLOSE_AUTH_EXPIRED, "auth revoked", ctx),
+        }
+    }
 }
 
-impl Handler<OutgoingRawMessage> for ChatSession {
+impl Handler<RemovedFromRoom> for ChatSession {
     type Result = ();
 
-    fn handle(&mut self, msg: OutgoingRawMessage, ctx: &mut Self::Context) {
-        ctx.text(msg.0);
+    fn handle(&mut self, msg: RemovedFromRoom, _: &mut Self::Context) {
+        self.pending.remove(&msg.room_id);
+        self.replayed_through.remove(&msg.room_id);
+        if self.room_id.as_ref() == Some(&msg.room_id) {
+            self.room_id = None;
+        }
     }
 }
 
 impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for ChatSession {
     fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
-        if let Ok(ws::Message::Text(text)) = msg {
-            if let Ok(client_msg) = serde_json::from_str::<ClientMessage>(&text) {
-                match client_msg {
-                    ClientMessage::Text { content } => {
-                        self.server_addr.do_send(super::ws_server::BroadcastMessage {
-                            room_id: self.room_id.clone(),
-                            message: content,
-                            sender_id: self.user_id.clone(),
+        let client_msg = match msg {
+            Ok(ws::Message::Text(text)) => ws_protocol::decode_text(&text),
+            Ok(ws::Message::Binary(bytes)) => ws_protocol::decode_binary(&bytes),
+            _ => None,
+        };
+        if let Some(client_msg) = client_msg {
+            match client_msg {
+                ClientMessage::Text { content } => match commands::parse(&content) {
+                    Some(invocation) => self.run_command(invocation, ctx),
+                    None => self.post_to_room(commands::unescape(content), ctx),
+                },
+                ClientMessage::Typing { .. } => {
+                    if let Some(room_id) = &self.room_id {
+                        self.router.room(room_id).do_send(super::ws_server::TypingIndicator {
+                            room_id: room_id.clone(),
+                            user_id: self.user_id.clone(),
                         });
-                    },
-                    ClientMessage::Private { to, content } => {
-                        self.server_addr.do_send(super::ws_server::PrivateMessage {
-                            to,
-                            from: self.user_id.clone(),
-                            content,
-                        });
-                    },
-                    _ => {}
-                }
+                    }
+                },
+                ClientMessage::Private { to, content } => {
+                    self.send_private(to, content, ctx);
+                },
+                ClientMessage::Join { room_id, last_seq, .. } => {
+                    self.request_join(room_id, last_seq, ctx);
+                },
+                ClientMessage::Reauth { token } => {
+                    self.reauthenticate(token, ctx);
+                },
+                ClientMessage::Leave { room_id, .. } => {
+                    self.leave_room(room_id);
+                },
+                _ => {}
             }
         }
     }
 }
-
-#[derive(Deserialize)]
-struct IncomingMessage {
-    #[serde(rename = "type")]
-    msg_type: String,
-    content: String,
-    room_id: Option<String>,
-}
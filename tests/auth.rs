@@ -0,0 +1,50 @@
+mod common;
+
+use rusty_chat::models::user::User;
+use rusty_chat::services::auth::AuthService;
+
+#[actix_web::test]
+async fn tokens_issued_right_after_a_revocation_are_valid() {
+    let Some((pool, _)) = common::setup().await else {
+        return;
+    };
+    let auth = AuthService::new().unwrap();
+    let user = common::create_user(&pool, "alice").await;
+    let before = auth.generate_token(&user).unwrap();
+
+    User::revoke_tokens(&pool, user.id).await.unwrap();
+    let after = auth.generate_token(&user).unwrap();
+
+    assert!(auth.validate_active_token(&pool, &before).await.unwrap().is_none());
+    assert!(auth.validate_active_token(&pool, &after).await.unwrap().is_some());
+}
+
+#[actix_web::test]
+async fn every_earlier_token_is_revoked() {
+    let Some((pool, _)) = common::setup().await else {
+        return;
+    };
+    let auth = AuthService::new().unwrap();
+    let user = common::create_user(&pool, "alice").await;
+    let tokens: Vec<String> = (0..3).map(|_| auth.generate_token(&user).unwrap()).collect();
+
+    User::revoke_tokens(&pool, user.id).await.unwrap();
+
+    for token in &tokens {
+        assert!(auth.validate_active_token(&pool, token).await.unwrap().is_none());
+    }
+}
+
+#[actix_web::test]
+async fn tokens_of_deleted_users_are_revoked() {
+    let Some((pool, _)) = common::setup().await else {
+        return;
+    };
+    let auth = AuthService::new().unwrap();
+    let user = common::create_user(&pool, "alice").await;
+    let token = auth.generate_token(&user).unwrap();
+
+    sqlx::query("DELETE FROM users WHERE id = $1").bind(user.id).execute(&pool).await.unwrap();
+
+    assert!(auth.validate_active_token(&pool, &token).await.unwrap().is_none());
+}
@@ -0,0 +1,67 @@
+//! Shared setup for tests against the database in `TEST_DATABASE_URL`, see the README.
+//! Tests return early when it is not set.
+#![allow(dead_code)]
+
+use rusty_chat::config::settings::AppConfig;
+use rusty_chat::database::connection::{run_migrations, DbPool};
+use rusty_chat::models::room::{CreateRoom, Room};
+use rusty_chat::models::user::User;
+use rusty_chat::services::auth::AuthService;
+use sqlx::postgres::PgPoolOptions;
+use std::env;
+use uuid::Uuid;
+
+/// A migrated pool on the test database and a config with the defaults, `None` when
+/// `TEST_DATABASE_URL` is not set
+pub async fn setup() -> Option<(DbPool, AppConfig)> {
+    let Ok(url) = env::var("TEST_DATABASE_URL") else {
+        eprintln!("TEST_DATABASE_URL is not set, skipping");
+        return None;
+    };
+    env::set_var("APP_ENV", "development");
+    env::set_var("LOCAL_DATABASE_URL", &url);
+    let config = AppConfig::from_env().expect("test configuration");
+    let pool = PgPoolOptions::new()
+        .max_connections(5)
+        .connect(&url)
+        .await
+        .expect("test database");
+    run_migrations(&pool).await.expect("migrations");
+    Some((pool, config))
+}
+
+/// A user with a unique name. The password is not a bcrypt hash, hashing one is too
+/// slow for tests that do not log in.
+pub async fn create_user(pool: &DbPool, name: &str) -> User {
+    let id = Uuid::new_v4();
+    let username = format!("{}_{}", name, &id.simple().to_string()[..12]);
+    sqlx::query_as::<_, User>(
+        "INSERT INTO users (id, full_name, username, email, password, status, created_at, updated_at) 
+         VALUES ($1, $2, $3, $4, '!', 'offline', NOW(), NOW()) 
+         RETURNING *",
+    )
+    .bind(id)
+    .bind(name)
+    .bind(&username)
+    .bind(format!("{}@example.com", username))
+    .fetch_one(pool)
+    .await
+    .expect("test user")
+}
+
+pub async fn create_room(pool: &DbPool, owner: &User, is_private: bool) -> Room {
+    Room::create(
+        pool,
+        CreateRoom {
+            name: format!("room {}", Uuid::new_v4()),
+            created_by: owner.id,
+            is_private,
+        },
+    )
+    .await
+    .expect("test room")
+}
+
+pub fn token(user: &User) -> String {
+    AuthService::new().unwrap().generate_token(user).unwrap()
+}
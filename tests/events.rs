@@ -0,0 +1,83 @@
+mod common;
+
+use actix_web::{test, web, App};
+use rusty_chat::models::message::DisplayOverride;
+use rusty_chat::routes;
+use rusty_chat::services::messaging::{self, PostError};
+use rusty_chat::services::moderation::PRIVATE_ROOM;
+use rusty_chat::services::content_filter::ContentFilter;
+use rusty_chat::services::spam::SpamDetector;
+use rusty_chat::stream_session::PollRegistry;
+use rusty_chat::ws_router::ChatRouter;
+use serde_json::json;
+
+#[actix_web::test]
+async fn send_refuses_private_rooms_of_non_members() {
+    let Some((pool, config)) = common::setup().await else {
+        return;
+    };
+    let owner = common::create_user(&pool, "owner").await;
+    let outsider = common::create_user(&pool, "outsider").await;
+    let room = common::create_room(&pool, &owner, true).await;
+    let router = ChatRouter::start(1, None, None, None, SpamDetector::new(&config.moderation), ContentFilter::default());
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(pool.clone()))
+            .app_data(web::Data::new(router))
+            .app_data(web::Data::new(PollRegistry::default()))
+            .app_data(web::Data::new(config))
+            .service(web::scope("/api/v1").configure(routes::api::scoped_config)),
+    )
+    .await;
+    let send = |user, message| {
+        test::TestRequest::post()
+            .uri(&format!("/api/v1/events/send?room_id={}", room.id))
+            .insert_header(("Authorization", format!("Bearer {}", common::token(user))))
+            .set_json(message)
+            .to_request()
+    };
+
+    let text = json!({ "type": "Text", "data": { "content": "hello" } });
+    let typing = json!({ "type": "Typing", "data": { "user_id": outsider.id.to_string() } });
+    for message in [&text, &typing] {
+        let response = test::call_service(&app, send(&outsider, message)).await;
+        assert_eq!(response.status(), 403, "{message}");
+    }
+
+    let response = test::call_service(&app, send(&owner, &text)).await;
+    assert_eq!(response.status(), 200);
+}
+
+#[actix_web::test]
+async fn room_messages_from_non_members_of_private_rooms_are_rejected() {
+    let Some((pool, config)) = common::setup().await else {
+        return;
+    };
+    let owner = common::create_user(&pool, "owner").await;
+    let outsider = common::create_user(&pool, "outsider").await;
+    let room = common::create_room(&pool, &owner, true).await;
+    let router = ChatRouter::start(1, None, None, None, SpamDetector::new(&config.moderation), ContentFilter::default());
+
+    let posted = messaging::post_room_message(
+        &pool,
+        &router,
+        &room.id.to_string(),
+        &outsider.id.to_string(),
+        "hello".to_string(),
+        DisplayOverride::default(),
+    )
+    .await;
+    assert!(matches!(posted, Err(PostError::Rejected(reason)) if reason == PRIVATE_ROOM));
+
+    let public = common::create_room(&pool, &owner, false).await;
+    let posted = messaging::post_room_message(
+        &pool,
+        &router,
+        &public.id.to_string(),
+        &outsider.id.to_string(),
+        "hello".to_string(),
+        DisplayOverride::default(),
+    )
+    .await;
+    assert!(matches!(posted, Ok(Some(_))));
+}
@@ -0,0 +1,81 @@
+mod common;
+
+use rusty_chat::database::connection::DbPool;
+use rusty_chat::models::message::{CreateMessage, DisplayOverride, Message, MAX_REPLAY};
+use rusty_chat::services::content_filter::ContentFilter;
+use rusty_chat::services::spam::SpamDetector;
+use rusty_chat::stream_session::StreamSubscription;
+use rusty_chat::ws_router::ChatRouter;
+use std::time::Duration;
+use uuid::Uuid;
+
+async fn post(pool: &DbPool, room_id: Uuid, sender_id: Uuid, count: usize) -> Vec<Message> {
+    let mut messages = Vec::new();
+    for i in 0..count {
+        let message = Message::create(
+            pool,
+            CreateMessage {
+                room_id,
+                sender_id,
+                recipient_id: None,
+                content: format!("message {}", i),
+                display: DisplayOverride::default(),
+                mentions: Vec::new(),
+            },
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        messages.push(message);
+    }
+    messages
+}
+
+#[actix_web::test]
+async fn replay_is_limited_to_the_latest_messages() {
+    let Some((pool, _)) = common::setup().await else {
+        return;
+    };
+    let owner = common::create_user(&pool, "owner").await;
+    let room = common::create_room(&pool, &owner, false).await;
+    post(&pool, room.id, owner.id, 10).await;
+
+    let replay = Message::find_by_room_id_after_seq(&pool, room.id, 2, owner.id, 5).await.unwrap();
+    let seqs: Vec<i64> = replay.iter().map(|message| message.seq).collect();
+    assert_eq!(seqs, vec![6, 7, 8, 9, 10]);
+
+    let replay = Message::find_by_room_id_after_seq(&pool, room.id, 8, owner.id, 5).await.unwrap();
+    let seqs: Vec<i64> = replay.iter().map(|message| message.seq).collect();
+    assert_eq!(seqs, vec![9, 10]);
+}
+
+#[actix_web::test]
+async fn streams_resuming_far_back_are_told_the_replay_was_cut_short() {
+    let Some((pool, config)) = common::setup().await else {
+        return;
+    };
+    let owner = common::create_user(&pool, "owner").await;
+    let room = common::create_room(&pool, &owner, false).await;
+    let messages = post(&pool, room.id, owner.id, MAX_REPLAY + 5).await;
+    let router = ChatRouter::start(1, None, None, None, SpamDetector::new(&config.moderation), ContentFilter::default());
+
+    let mut subscription = StreamSubscription::open(
+        owner.id.to_string(),
+        vec![room.id.to_string()],
+        Some(messages[0].id),
+        router,
+        &pool,
+        &config.websocket,
+        None,
+    )
+    .await
+    .unwrap();
+    let events = subscription.next_events(Duration::from_millis(10)).await.unwrap();
+
+    assert_eq!(events.len(), MAX_REPLAY + 1);
+    let truncated: serde_json::Value = serde_json::from_str(&events[0].data).unwrap();
+    assert_eq!(truncated["type"], "ReplayTruncated");
+    assert_eq!(truncated["data"]["from_seq"], messages[5].seq);
+    assert_eq!(events[1].id, Some(messages[5].id));
+    assert_eq!(events[MAX_REPLAY].id, Some(messages[MAX_REPLAY + 4].id));
+}
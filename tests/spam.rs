@@ -0,0 +1,84 @@
+mod common;
+
+use rusty_chat::config::settings::AppConfig;
+use rusty_chat::database::connection::DbPool;
+use rusty_chat::models::message::DisplayOverride;
+use rusty_chat::models::room_sanction::{RoomSanction, SanctionKind};
+use rusty_chat::models::user::User;
+use rusty_chat::services::content_filter::ContentFilter;
+use rusty_chat::services::messaging::{self, PostError};
+use rusty_chat::services::spam::{SpamDetector, SpamResponse};
+use rusty_chat::ws_router::ChatRouter;
+use uuid::Uuid;
+
+/// Posts the same message twice with one repeat allowed, so the second is caught.
+/// Returns what became of it and the sender's sanctions in the room afterwards.
+async fn post_repeat(
+    pool: &DbPool,
+    config: &AppConfig,
+    response: SpamResponse,
+) -> (Result<bool, PostError>, Vec<RoomSanction>, User, Uuid) {
+    let owner = common::create_user(pool, "owner").await;
+    let sender = common::create_user(pool, "sender").await;
+    let room = common::create_room(pool, &owner, false).await;
+    let mut moderation = config.moderation.clone();
+    moderation.spam_max_repeats = 1;
+    moderation.spam_response = response;
+    let router = ChatRouter::start(1, None, None, None, SpamDetector::new(&moderation), ContentFilter::default());
+
+    let (room_id, sender_id) = (room.id.to_string(), sender.id.to_string());
+    let post = || {
+        messaging::post_room_message(
+            pool,
+            &router,
+            &room_id,
+            &sender_id,
+            "buy now".to_string(),
+            DisplayOverride::default(),
+        )
+    };
+    post().await.expect("first message");
+    let caught = post().await.map(|message| message.is_some());
+    let sanctions = RoomSanction::find_active(pool, room.id, sender.id).await.unwrap();
+    (caught, sanctions, sender, room.id)
+}
+
+#[actix_web::test]
+async fn warn_lets_the_message_through_and_warns_the_sender() {
+    let Some((pool, config)) = common::setup().await else {
+        return;
+    };
+    let (caught, _, sender, room_id) = post_repeat(&pool, &config, SpamResponse::Warn).await;
+    assert!(caught.expect("warned message is sent"));
+
+    let warnings: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM room_sanctions WHERE room_id = $1 AND user_id = $2 AND kind = $3 AND issued_by IS NULL",
+    )
+    .bind(room_id)
+    .bind(sender.id)
+    .bind(SanctionKind::Warn.as_str())
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    assert_eq!(warnings, 1);
+}
+
+#[actix_web::test]
+async fn mute_drops_the_message_and_mutes_the_sender() {
+    let Some((pool, config)) = common::setup().await else {
+        return;
+    };
+    let (caught, sanctions, _, _) = post_repeat(&pool, &config, SpamResponse::Mute).await;
+    assert!(matches!(caught, Err(PostError::Rejected(_))));
+    assert!(sanctions.iter().any(|sanction| sanction.kind() == Some(SanctionKind::Mute)));
+}
+
+#[actix_web::test]
+async fn flag_lets_the_message_through_without_a_sanction() {
+    let Some((pool, config)) = common::setup().await else {
+        return;
+    };
+    let (caught, sanctions, _, _) = post_repeat(&pool, &config, SpamResponse::Flag).await;
+    assert!(caught.expect("flagged message is sent"));
+    assert!(sanctions.is_empty());
+}